@@ -112,6 +112,8 @@ pub mod {ext}_{stem}
         ty: \"{shader_type}\",
         bytes: \"{out_folder}/{file_name}\",
     }}
+
+    pub const SOURCE_PATH: &str = \"shaders/src/{file_name}\";
 }}
 "
                 )
@@ -12,6 +12,7 @@ layout( set = 0, binding = 0 ) uniform CartesianToNormalizedUbo {
 
 layout( set = 2, binding = 0) uniform CameraUbo {
     mat4 camera;
+    vec2 translation;
 };
 
 layout( push_constant ) uniform ObjectData {
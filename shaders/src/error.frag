@@ -0,0 +1,9 @@
+#version 450
+
+layout(location = 0) out vec4 out_color;
+
+// Used when a shader failed to load, magenta so it stands out.
+void main()
+{
+    out_color = vec4(1.0, 0.0, 1.0, 1.0);
+}
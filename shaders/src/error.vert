@@ -0,0 +1,10 @@
+#version 450
+
+layout(location = 0) in vec2 pos;
+
+// Used when a vertex shader failed to load. There is no camera here so the
+// vertices are used as they are, the geometry will usually be out of place.
+void main()
+{
+    gl_Position = vec4(pos, 0.0f, 1.0f);
+}
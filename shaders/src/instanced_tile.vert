@@ -0,0 +1,28 @@
+#version 450
+
+layout(location = 0) in vec2 pos;
+layout(location = 1) in vec2 uv;
+
+// per instance
+layout(location = 2) in vec2 instance_offset;
+layout(location = 3) in vec2 uv_offset;
+layout(location = 4) in vec4 tint;
+
+layout(location = 0) out vec2 out_uv;
+layout(location = 1) out vec4 out_tint;
+
+layout( set = 0, binding = 0 ) uniform CartesianToNormalizedUbo {
+    mat4 cartesian_to_normalized;
+};
+
+layout( set = 2, binding = 0) uniform CameraUbo {
+    mat4 camera;
+    vec2 translation;
+};
+
+void main()
+{
+    gl_Position =  cartesian_to_normalized * camera * vec4(pos + instance_offset, 0.0f, 1.0f);
+    out_uv = uv + uv_offset;
+    out_tint = tint;
+}
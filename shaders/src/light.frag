@@ -0,0 +1,31 @@
+#version 450
+
+#define MAX_LIGHTS 32
+
+layout(location = 0) in vec2 world_pos;
+layout(location = 0) out vec4 out_color;
+
+struct Light {
+    // xy is the world position, z the radius
+    vec4 position_radius;
+    // rgb is the color, a the intensity
+    vec4 color_intensity;
+};
+
+layout( set = 1, binding = 0 ) uniform LightsUbo {
+    vec4 ambient;
+    // unused lights have an intensity of 0
+    Light lights[MAX_LIGHTS];
+};
+
+void main()
+{
+    vec3 light = ambient.rgb;
+    for (int i = 0; i < MAX_LIGHTS; i++) {
+        float distance_to_light = distance(world_pos, lights[i].position_radius.xy);
+        float falloff = clamp(1.0f - distance_to_light / lights[i].position_radius.z, 0.0f, 1.0f);
+        light += lights[i].color_intensity.rgb * lights[i].color_intensity.a * falloff * falloff;
+    }
+    // multiplied with the scene, so anything above 1.0 is the same as 1.0
+    out_color = vec4(light, 1.0f);
+}
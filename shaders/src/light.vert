@@ -0,0 +1,23 @@
+#version 450
+
+// the corners of the screen in normalized coordinates
+layout(location = 0) in vec2 pos;
+
+layout(location = 0) out vec2 world_pos;
+
+layout( set = 0, binding = 0 ) uniform CartesianToNormalizedUbo {
+    mat4 cartesian_to_normalized;
+};
+
+layout( set = 2, binding = 0) uniform CameraUbo {
+    mat4 camera;
+    vec2 translation;
+};
+
+void main()
+{
+    gl_Position = vec4(pos, 0.0f, 1.0f);
+    // both matrices are affine, so the world position can be interpolated across the screen
+    vec2 cartesian_pos = (inverse(cartesian_to_normalized) * vec4(pos, 0.0f, 1.0f)).xy;
+    world_pos = (inverse(camera) * vec4(cartesian_pos, 0.0f, 1.0f)).xy;
+}
@@ -11,10 +11,20 @@ layout( set = 0, binding = 0 ) uniform CartesianToNormalizedUbo {
 
 layout( set = 2, binding = 0) uniform CameraUbo {
     mat4 camera;
+    vec2 translation;
+};
+
+layout( push_constant ) uniform GroupData {
+    // places the mesh in the world, so moving it doesn't need new vertices
+    mat4 model;
+    vec2 parallax_factor;
 };
 
 void main()
 {
-    gl_Position =  cartesian_to_normalized * camera * vec4(pos, 0.0f, 1.0f);
+    vec2 world_pos = (model * vec4(pos, 0.0f, 1.0f)).xy;
+    // a parallax factor of 1.0 moves with the camera, lower values lag behind it.
+    vec2 parallax_pos = world_pos + (parallax_factor - vec2(1.0)) * translation;
+    gl_Position =  cartesian_to_normalized * camera * vec4(parallax_pos, 0.0f, 1.0f);
     out_uv = uv;
-}
\ No newline at end of file
+}
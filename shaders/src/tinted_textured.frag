@@ -0,0 +1,16 @@
+#version 450
+
+layout(location = 0) in vec2 uv;
+layout(location = 0) out vec4 out_color;
+
+layout(set = 1, binding = 0) uniform sampler2D tex;
+
+// multiplied into the sampled color, white leaves the texture unchanged
+layout(set = 3, binding = 0) uniform TintUbo {
+    vec4 tint;
+};
+
+void main()
+{
+    out_color = texture(tex, uv) * tint;
+}
@@ -9,12 +9,8 @@ layout( set = 0, binding = 0 ) uniform CartesianToNormalizedUbo {
     mat4 cartesian_to_normalized;
 };
 
-layout( set = 3, binding = 0 ) uniform ObjectData {
-    uint thing;
-};
-
 void main()
 {
     gl_Position = cartesian_to_normalized * vec4(pos, 0.0f, 1.0f);
     out_uv = uv;
-}
\ No newline at end of file
+}
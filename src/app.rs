@@ -1,21 +1,38 @@
 use crate::drawables::tiles::AnimatedTile;
 use crate::drawables::tiles::AnimatedTileDesc;
+use crate::drawables::tiles::AnimationMode;
+use crate::drawables::tiles::InstancedTiles;
+use crate::drawables::tiles::SpriteAnimator;
 use crate::drawables::tiles::StaticTileGroup;
+use crate::drawables::tiles::TileInstanceDesc;
 use crate::drawables::tiles::TileSet;
 use crate::graphics::camera::Camera;
 use crate::graphics::Graphics;
+use crate::input::Action;
 use crate::input::Input;
+use crate::timing::{FixedTimestep, Interpolated};
 use std::sync::Arc;
+use winit::window::WindowId;
 
 mod ui;
 
+const DEFAULT_TICK_RATE: u32 = 60;
+
+// in pixels per tick at zoom 1.0
+const EDITOR_KEY_PAN_SPEED: f32 = 8.0;
+// fraction of the zoom per tick
+const EDITOR_KEY_ZOOM_SPEED: f32 = 0.02;
+
 pub struct App {
     input: Arc<Input>,
     tile_set: Arc<TileSet>,
     tile_map: StaticTileGroup,
     animated_tiles: Vec<AnimatedTile>,
-    last_frame_change: std::time::Instant,
+    instanced_tiles: InstancedTiles,
+    water_animation: SpriteAnimator,
     camera: Camera,
+    camera_position: Interpolated<[f32; 2]>,
+    timestep: FixedTimestep,
 }
 
 impl App {
@@ -142,42 +159,110 @@ impl App {
             ),
         ];
 
+        // 1000 tiles below the map drawn from a single instance buffer
+        let instanced_tiles = InstancedTiles::new(
+            gfx,
+            tile_set.clone(),
+            (0..1000)
+                .map(|i| TileInstanceDesc {
+                    tile_position: [(i % 40) as f32, (6 + i / 40) as f32],
+                    sprite_idx: [2, 3, 4, 7, 8, 9][i % 6],
+                    tint: [
+                        1.0,
+                        1.0 - (i % 40) as f32 / 80.0,
+                        1.0 - (i / 40) as f32 / 50.0,
+                        1.0,
+                    ],
+                })
+                .collect(),
+            64.0,
+            &camera,
+        );
+
+        let timestep = FixedTimestep::new(DEFAULT_TICK_RATE);
+
         Self {
             input: input,
             tile_set: tile_set,
             tile_map: tile_map,
             animated_tiles: animated_tiles,
-            last_frame_change: std::time::Instant::now(),
+            instanced_tiles: instanced_tiles,
+            water_animation: SpriteAnimator::new(
+                (0..7).collect(),
+                std::time::Duration::from_millis(150),
+                AnimationMode::Loop,
+            ),
+            camera_position: Interpolated::new(camera.position),
             camera: camera,
+            timestep: timestep,
         }
     }
 
-    pub fn resize_callback(&self, gfx: &mut Graphics) {
-        gfx.recreate_swapchain();
+    pub fn resize_callback(&self, gfx: &mut Graphics, window_id: WindowId) {
+        gfx.recreate_swapchain(window_id);
+    }
+
+    /// Sets how many times per second the game logic is updated, independent of the frame rate.
+    pub fn set_tick_rate(&mut self, ticks_per_second: u32) {
+        self.timestep.set_tick_rate(ticks_per_second);
     }
 
-    pub fn run(&mut self, _gfx: &Graphics) {
+    /// Runs the game logic for every tick that has passed since the last call and
+    /// interpolates what is drawn between them.
+    pub fn run(&mut self, gfx: &Graphics) {
+        let ticks = self.timestep.advance();
+        for _ in 0..ticks {
+            self.tick();
+            // every press should only be seen by one tick
+            self.input.clear_presses();
+        }
+
+        let alpha = self.timestep.alpha();
+        gfx.set_interpolation_alpha(alpha);
+
+        self.camera.position = self.camera_position.get(alpha);
+        self.camera.update_buffer(gfx);
+
+        self.tile_map.cull(gfx, &self.camera);
+    }
+
+    fn tick(&mut self) {
         self.editor_camera_movement();
 
-        if self.last_frame_change.elapsed().as_millis() > 150 {
-            self.last_frame_change = std::time::Instant::now();
+        let tick_duration = self.timestep.get_tick_duration();
+        if let Some(frame) = self.water_animation.update(tick_duration) {
             for tile in &self.animated_tiles {
-                tile.data.access_data(|data| {
-                    data.frame_offset = (data.frame_offset + 1) % 7; 
-                });
+                tile.set_frame(frame);
             }
         }
     }
 
     fn editor_camera_movement(&mut self) {
-        self.camera.zoom *= 1.0 + self.input.mouse.scroll_wheel_movement.get() / 10.0;
-        if self.input.keyboard.is_key_held(56).is_some() {
-            if self.input.mouse.is_button_held(1).is_some() {
+        let mut target_zoom = self.camera.get_target_zoom();
+        target_zoom *= 1.0 + self.input.mouse.scroll_wheel_movement.get() / 10.0;
+        if self.input.action_held(Action::ZoomIn).is_some() {
+            target_zoom *= 1.0 + EDITOR_KEY_ZOOM_SPEED;
+        }
+        if self.input.action_held(Action::ZoomOut).is_some() {
+            target_zoom /= 1.0 + EDITOR_KEY_ZOOM_SPEED;
+        }
+        self.camera.set_target_zoom(target_zoom);
+        self.camera.update_zoom(self.timestep.get_tick_duration());
+
+        let mut position = self.camera_position.current;
+        if self.input.action_held(Action::PanCamera).is_some() {
+            if self.input.action_held(Action::Drag).is_some() {
                 let mouse_movement = self.input.mouse.mouse_movement.get();
-                self.camera.position[0] -= mouse_movement.x as f32 / self.camera.zoom;
-                self.camera.position[1] -= mouse_movement.y as f32 / self.camera.zoom;
+                position[0] -= mouse_movement.x as f32 / self.camera.zoom;
+                position[1] -= mouse_movement.y as f32 / self.camera.zoom;
             }
         }
-        self.camera.update_buffer();
+
+        // the keys and a controller's stick move the camera the same way
+        let key_speed = EDITOR_KEY_PAN_SPEED / self.camera.zoom;
+        let direction = self.input.movement_direction();
+        position[0] += direction.x * key_speed;
+        position[1] += direction.y * key_speed;
+        self.camera_position.set(position);
     }
 }
@@ -1 +1,3 @@
+pub mod anchor;
 pub mod button;
+pub mod text_field;
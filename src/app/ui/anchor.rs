@@ -0,0 +1,82 @@
+/// The point of the window an element is positioned relative to.
+/// The same point of the element is placed on it, so a `TopRight` element stays inside the top right corner.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    #[default]
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    /// -1, 0 or 1 on each axis, with y pointing up.
+    fn direction(&self) -> [f32; 2] {
+        match self {
+            Anchor::TopLeft => [-1.0, 1.0],
+            Anchor::Top => [0.0, 1.0],
+            Anchor::TopRight => [1.0, 1.0],
+            Anchor::Left => [-1.0, 0.0],
+            Anchor::Center => [0.0, 0.0],
+            Anchor::Right => [1.0, 0.0],
+            Anchor::BottomLeft => [-1.0, -1.0],
+            Anchor::Bottom => [0.0, -1.0],
+            Anchor::BottomRight => [1.0, -1.0],
+        }
+    }
+
+    /// Returns the center of an element in UI pixels, see `UiRenderer`.
+    /// `offset` moves the element away from the anchor, with y pointing up.
+    pub fn resolve(&self, offset: [f32; 2], size: [f32; 2], window_size: [f32; 2]) -> [f32; 2] {
+        let direction = self.direction();
+        [
+            direction[0] * (window_size[0] - size[0]) / 2.0 + offset[0],
+            direction[1] * (window_size[1] - size[1]) / 2.0 + offset[1],
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW_SIZE: [f32; 2] = [800.0, 600.0];
+    const SIZE: [f32; 2] = [100.0, 50.0];
+
+    #[test]
+    fn every_anchor_touches_its_window_edge() {
+        let expected = [
+            (Anchor::TopLeft, [-350.0, 275.0]),
+            (Anchor::Top, [0.0, 275.0]),
+            (Anchor::TopRight, [350.0, 275.0]),
+            (Anchor::Left, [-350.0, 0.0]),
+            (Anchor::Center, [0.0, 0.0]),
+            (Anchor::Right, [350.0, 0.0]),
+            (Anchor::BottomLeft, [-350.0, -275.0]),
+            (Anchor::Bottom, [0.0, -275.0]),
+            (Anchor::BottomRight, [350.0, -275.0]),
+        ];
+
+        for (anchor, center) in expected {
+            assert_eq!(
+                anchor.resolve([0.0, 0.0], SIZE, WINDOW_SIZE),
+                center,
+                "{anchor:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn offset_moves_away_from_the_anchor() {
+        // a hotbar sitting 10 pixels above the bottom edge
+        assert_eq!(
+            Anchor::Bottom.resolve([0.0, 10.0], SIZE, WINDOW_SIZE),
+            [0.0, -265.0]
+        );
+    }
+}
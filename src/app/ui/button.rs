@@ -1,5 +1,173 @@
+use crate::input::{ButtonState, Input};
+
+use super::anchor::Anchor;
+
+// the same button as Action::Drag in the default bindings
+const DEFAULT_MOUSE_BUTTON: u32 = 1;
+
+/// How the button should be drawn this tick.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ButtonAppearance {
+    Normal,
+    Hovered,
+    Pressed,
+}
+
+/// Calls `on_click` when the mouse button is released over the button it was pressed on.
+/// Pressing outside the button and releasing inside it does nothing.
 pub struct Button {
     position: [f32; 2],
+    size: [f32; 2],
+    anchor: Anchor,
+    pub mouse_button: u32,
+    // true from a press inside the button until the release
+    captured: bool,
+    appearance: ButtonAppearance,
+    on_click: Box<dyn FnMut()>,
+}
+
+impl Button {
+    /// `position` is the offset in pixels from the anchor.
+    pub fn new(
+        position: [f32; 2],
+        size: [f32; 2],
+        anchor: Anchor,
+        on_click: Box<dyn FnMut()>,
+    ) -> Self {
+        Self {
+            position: position,
+            size: size,
+            anchor: anchor,
+            mouse_button: DEFAULT_MOUSE_BUTTON,
+            captured: false,
+            appearance: ButtonAppearance::Normal,
+            on_click: on_click,
+        }
+    }
+
+    /// Call once per tick, before `Input::clear_presses`.
+    pub fn update(&mut self, input: &Input, window_size: [f32; 2]) -> ButtonAppearance {
+        let cursor = input.mouse.cursor_position.get();
+        self.update_with(
+            [cursor.x as f32, cursor.y as f32],
+            input.mouse.get_button_state(self.mouse_button),
+            window_size,
+        )
+    }
+
+    fn update_with(
+        &mut self,
+        cursor: [f32; 2],
+        button_state: Option<ButtonState>,
+        window_size: [f32; 2],
+    ) -> ButtonAppearance {
+        let inside = self.contains(cursor, window_size);
+
+        match button_state {
+            Some(ButtonState::Pressed(_)) => {
+                if inside {
+                    self.captured = true;
+                }
+            }
+            Some(ButtonState::Held(_)) => {}
+            Some(ButtonState::Released) | None => {
+                if self.captured {
+                    self.captured = false;
+                    if inside {
+                        (self.on_click)();
+                    }
+                }
+            }
+        }
+
+        self.appearance = match (inside, self.captured) {
+            (true, true) => ButtonAppearance::Pressed,
+            (true, false) => ButtonAppearance::Hovered,
+            (false, _) => ButtonAppearance::Normal,
+        };
+        self.appearance
+    }
+
+    pub fn get_appearance(&self) -> ButtonAppearance {
+        self.appearance
+    }
+
+    /// The center of the button in UI pixels.
+    pub fn get_center(&self, window_size: [f32; 2]) -> [f32; 2] {
+        self.anchor.resolve(self.position, self.size, window_size)
+    }
+
+    /// `point` is in UI pixels, like `Mouse::cursor_position`.
+    pub fn contains(&self, point: [f32; 2], window_size: [f32; 2]) -> bool {
+        let center = self.get_center(window_size);
+        (point[0] - center[0]).abs() <= self.size[0] / 2.0
+            && (point[1] - center[1]).abs() <= self.size[1] / 2.0
+    }
 }
 
-impl Button {}
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc, time::Instant};
+
+    use super::*;
+
+    const WINDOW_SIZE: [f32; 2] = [800.0, 600.0];
+    const INSIDE: [f32; 2] = [0.0, 0.0];
+    const OUTSIDE: [f32; 2] = [300.0, 0.0];
+
+    fn counting_button() -> (Button, Rc<Cell<u32>>) {
+        let clicks = Rc::new(Cell::new(0));
+        let counter = clicks.clone();
+        let button = Button::new(
+            [0.0, 0.0],
+            [100.0, 40.0],
+            Anchor::Center,
+            Box::new(move || counter.set(counter.get() + 1)),
+        );
+        (button, clicks)
+    }
+
+    #[test]
+    fn release_inside_clicks_once() {
+        let (mut button, clicks) = counting_button();
+        let pressed = Instant::now();
+
+        let appearance = button.update_with(INSIDE, None, WINDOW_SIZE);
+        assert_eq!(appearance, ButtonAppearance::Hovered);
+
+        let pressed_state = Some(ButtonState::Pressed(pressed));
+        let appearance = button.update_with(INSIDE, pressed_state, WINDOW_SIZE);
+        assert_eq!(appearance, ButtonAppearance::Pressed);
+        button.update_with(INSIDE, Some(ButtonState::Held(pressed)), WINDOW_SIZE);
+        assert_eq!(clicks.get(), 0);
+
+        button.update_with(INSIDE, Some(ButtonState::Released), WINDOW_SIZE);
+        button.update_with(INSIDE, Some(ButtonState::Released), WINDOW_SIZE);
+        assert_eq!(clicks.get(), 1);
+    }
+
+    #[test]
+    fn release_outside_does_not_click() {
+        let (mut button, clicks) = counting_button();
+        let pressed = Instant::now();
+
+        button.update_with(INSIDE, Some(ButtonState::Pressed(pressed)), WINDOW_SIZE);
+        let appearance = button.update_with(OUTSIDE, Some(ButtonState::Held(pressed)), WINDOW_SIZE);
+        assert_eq!(appearance, ButtonAppearance::Normal);
+        button.update_with(OUTSIDE, Some(ButtonState::Released), WINDOW_SIZE);
+
+        // the press was released, so coming back doesn't click either
+        button.update_with(INSIDE, Some(ButtonState::Released), WINDOW_SIZE);
+        assert_eq!(clicks.get(), 0);
+    }
+
+    #[test]
+    fn press_outside_then_release_inside_does_not_click() {
+        let (mut button, clicks) = counting_button();
+
+        let pressed_state = Some(ButtonState::Pressed(Instant::now()));
+        button.update_with(OUTSIDE, pressed_state, WINDOW_SIZE);
+        button.update_with(INSIDE, Some(ButtonState::Released), WINDOW_SIZE);
+        assert_eq!(clicks.get(), 0);
+    }
+}
@@ -0,0 +1,41 @@
+use crate::input::{Input, TextInput};
+
+/// A single line of editable text. Only takes keyboard input while focused.
+pub struct TextField {
+    pub text: String,
+    pub max_length: usize,
+    pub focused: bool,
+}
+
+impl TextField {
+    pub fn new(max_length: usize) -> Self {
+        Self {
+            text: String::new(),
+            max_length: max_length,
+            focused: false,
+        }
+    }
+
+    /// Call once per tick. Returns true when enter was pressed.
+    pub fn update(&mut self, input: &Input) -> bool {
+        if !self.focused {
+            return false;
+        }
+
+        let mut submitted = false;
+        for text_input in input.keyboard.take_text_input() {
+            match text_input {
+                TextInput::Char(chr) => {
+                    if self.text.chars().count() < self.max_length {
+                        self.text.push(chr);
+                    }
+                }
+                TextInput::Backspace => {
+                    self.text.pop();
+                }
+                TextInput::Enter => submitted = true,
+            }
+        }
+        submitted
+    }
+}
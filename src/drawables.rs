@@ -1,9 +1,19 @@
 #![allow(unused_imports)]
 
+mod cube;
 mod grid;
+mod light_overlay;
+mod nine_slice;
 mod square;
+mod text;
 pub mod tiles;
 mod triangle;
+mod ubotest;
 
+pub use cube::Cube;
 pub use grid::Grid;
+pub use light_overlay::LightOverlay;
+pub use nine_slice::{NineSlice, NineSliceInsets};
 pub use square::Square;
+pub use text::{BitmapFont, Glyph, Text, TextAlignment};
+pub use ubotest::UboTestDrawable;
@@ -29,9 +29,9 @@ impl Cube
             proj: cgmath::perspective(Deg(90.0), aspect, 0.2, 10.0).into()
         }, ShaderStages::VERTEX);
 
-        let mut entry = GenericDrawable::new(gfx, 1, || {
+        let mut entry = GenericDrawable::new(gfx, || {
             vec![
-                uniform.clone()
+                bindable::UniformBufferBinding::new(uniform.clone(), 0)
             ]
         }, || {
             #[derive(BufferContents, Vertex)]
@@ -75,8 +75,8 @@ impl Cube
             ];
             
             vec![
-                bindable::VertexShader::from_module(vert_3dColored::load(gfx.get_device()).unwrap()),
-                bindable::FragmentShader::from_module(frag_3dColored::load(gfx.get_device()).unwrap()),
+                bindable::VertexShader::from_source(vert_3dColored::load(gfx.get_device()), vert_3dColored::SOURCE_PATH),
+                bindable::FragmentShader::from_source(frag_3dColored::load(gfx.get_device()), frag_3dColored::SOURCE_PATH),
                 bindable::IndexBuffer::new(&gfx, indices),
                 bindable::VertexBuffer::new(&gfx, vertices),
             ]
@@ -3,10 +3,7 @@ use std::sync::Arc;
 use cgmath::Vector2;
 use vulkano::{
     buffer::BufferContents,
-    pipeline::graphics::{
-        input_assembly::{InputAssemblyState, PrimitiveTopology},
-        vertex_input::Vertex,
-    },
+    pipeline::graphics::{input_assembly::PrimitiveTopology, vertex_input::Vertex},
     shader::ShaderStages,
 };
 
@@ -26,7 +23,6 @@ pub struct Grid {
 impl Grid {
     pub fn new(gfx: &mut Graphics, dimensions: Vector2<u32>, cell_width: f32) -> Self {
         let pc = bindable::PushConstant::new(
-            gfx,
             0,
             vert_cartesian_2d::Data {
                 transform: (cgmath::Matrix4::from_scale(cell_width)).into(),
@@ -92,11 +88,13 @@ impl Grid {
                 indices.extend((4..vertices.len() as u32).into_iter());
 
                 vec![
-                    bindable::VertexShader::from_module(
-                        vert_cartesian_2d::load(gfx.get_device()).unwrap(),
+                    bindable::VertexShader::from_source(
+                        vert_cartesian_2d::load(gfx.get_device()),
+                        vert_cartesian_2d::SOURCE_PATH,
                     ),
-                    bindable::FragmentShader::from_module(
-                        frag_solid_white::load(gfx.get_device()).unwrap(),
+                    bindable::FragmentShader::from_source(
+                        frag_solid_white::load(gfx.get_device()),
+                        frag_solid_white::SOURCE_PATH,
                     ),
                     bindable::IndexBuffer::new(&gfx, indices),
                     bindable::VertexBuffer::new(&gfx, vertices),
@@ -104,13 +102,7 @@ impl Grid {
                         gfx.get_utils().cartesian_to_normalized.clone(),
                         0,
                     ),
-                    bindable::GodBindable::new(
-                        |_, _| {},
-                        |pipeline_builder, _| {
-                            pipeline_builder.input_assembly_state =
-                                InputAssemblyState::new().topology(PrimitiveTopology::LineList);
-                        },
-                    ),
+                    bindable::Topology::new(PrimitiveTopology::LineList),
                 ]
             },
         );
@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use vulkano::{
+    buffer::BufferContents, pipeline::graphics::vertex_input::Vertex, shader::ShaderStages,
+};
+
+use crate::graphics::{
+    bindable::{self, UniformBuffer},
+    camera::Camera,
+    drawable::{DrawableEntry, GenericDrawable},
+    light::MAX_LIGHTS,
+    shaders::{frag_light, vert_light},
+    Graphics, CAMERA_SET,
+};
+
+/// Darkens the screen to the ambient color and brightens it around the lights queued with
+/// `Graphics::add_light`. It multiplies what was drawn before it, so create it after the world
+/// drawables it should light. UI is drawn after it and isn't affected. Lights don't cast shadows.
+pub struct LightOverlay {
+    entry: DrawableEntry,
+    lights: Arc<UniformBuffer<frag_light::LightsUbo>>,
+}
+
+impl LightOverlay {
+    /// `ambient` is the color of places no light reaches, white turns the overlay off.
+    pub fn new(gfx: &mut Graphics, camera: &Camera, ambient: [f32; 3]) -> Self {
+        let lights = UniformBuffer::new(
+            gfx,
+            0,
+            frag_light::LightsUbo {
+                ambient: [ambient[0], ambient[1], ambient[2], 1.0],
+                lights: [Self::unused_light(); MAX_LIGHTS],
+            },
+            ShaderStages::FRAGMENT,
+        );
+
+        let mut entry = GenericDrawable::new(
+            gfx,
+            || {
+                vec![
+                    bindable::UniformBufferBinding::new(lights.clone(), 1),
+                    bindable::UniformBufferBinding::new(camera.uniform_buffer(), CAMERA_SET),
+                ]
+            },
+            || {
+                #[derive(BufferContents, Vertex)]
+                #[repr(C)]
+                struct VertexT {
+                    #[format(R32G32_SFLOAT)]
+                    pos: [f32; 2],
+                }
+
+                // covers the whole screen in normalized coordinates
+                let vertices = vec![
+                    VertexT { pos: [-1.0, -1.0] },
+                    VertexT { pos: [1.0, -1.0] },
+                    VertexT { pos: [-1.0, 1.0] },
+                    VertexT { pos: [1.0, 1.0] },
+                ];
+                let indices = vec![0, 1, 2, 2, 1, 3];
+
+                vec![
+                    bindable::VertexBuffer::new(gfx, vertices),
+                    bindable::IndexBuffer::new(gfx, indices),
+                    bindable::VertexShader::from_source(
+                        vert_light::load(gfx.get_device()),
+                        vert_light::SOURCE_PATH,
+                    ),
+                    bindable::FragmentShader::from_source(
+                        frag_light::load(gfx.get_device()),
+                        frag_light::SOURCE_PATH,
+                    ),
+                    bindable::UniformBufferBinding::new(
+                        gfx.get_utils().cartesian_to_normalized.clone(),
+                        0,
+                    ),
+                    // normalized coordinates are y down, which flips the winding
+                    bindable::Rasterization::no_culling(),
+                    bindable::Blend::multiply(),
+                ]
+            },
+        );
+
+        gfx.register_drawable(&mut entry);
+
+        Self {
+            entry: entry,
+            lights: lights,
+        }
+    }
+
+    pub fn set_ambient(&self, ambient: [f32; 3]) {
+        self.lights.access_data(|data| {
+            data.ambient = [ambient[0], ambient[1], ambient[2], 1.0];
+        });
+    }
+
+    /// Uploads the lights queued on `gfx`. Call it every frame after queueing the lights
+    /// and before `Graphics::draw_frame`. Only the first `MAX_LIGHTS` lights are drawn.
+    pub fn update(&self, gfx: &Graphics) {
+        let queued = gfx.get_lights();
+        if queued.len() > MAX_LIGHTS {
+            println!(
+                "{} lights were queued but only {MAX_LIGHTS} are drawn",
+                queued.len()
+            );
+        }
+
+        self.lights.access_data(|data| {
+            for (i, light) in data.lights.iter_mut().enumerate() {
+                *light = match queued.get(i) {
+                    Some(queued) => frag_light::Light {
+                        position_radius: [
+                            queued.position[0],
+                            queued.position[1],
+                            queued.radius,
+                            0.0,
+                        ],
+                        color_intensity: [
+                            queued.color[0],
+                            queued.color[1],
+                            queued.color[2],
+                            queued.intensity,
+                        ],
+                    },
+                    None => Self::unused_light(),
+                };
+            }
+        });
+    }
+
+    // the radius is kept above 0 so the shader never divides by it
+    fn unused_light() -> frag_light::Light {
+        frag_light::Light {
+            position_radius: [0.0, 0.0, 1.0, 0.0],
+            color_intensity: [0.0; 4],
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use std::sync::Arc;
+
+use vulkano::{
+    buffer::BufferContents, image::ImageViewAbstract, pipeline::graphics::vertex_input::Vertex,
+};
+
+use crate::graphics::{
+    bindable::{self, Texture},
+    drawable::{DrawableEntry, GenericDrawable},
+    shaders::{frag_ui, vert_ui},
+    Graphics,
+};
+
+/// Border widths in texture pixels. They are drawn at the same size on screen.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NineSliceInsets {
+    pub left: f32,
+    pub right: f32,
+    pub top: f32,
+    pub bottom: f32,
+}
+
+#[derive(BufferContents, Vertex)]
+#[repr(C)]
+struct NineSliceVertex {
+    #[format(R32G32_SFLOAT)]
+    pos: [f32; 2],
+    #[format(R32G32_SFLOAT)]
+    uv: [f32; 2],
+}
+
+/// A textured rectangle that keeps its corners at a fixed size, only stretching the edges along one axis
+/// and the center along both. Used for bordered panels that need to be drawn at any size.
+/// The texture has to be loaded with binding 0.
+pub struct NineSlice {
+    entry: DrawableEntry,
+    texture: Arc<Texture>,
+}
+
+impl NineSlice {
+    /// `center` and `size` are in UI pixels, see `UiRenderer`.
+    pub fn new(
+        gfx: &mut Graphics,
+        texture: Arc<Texture>,
+        insets: NineSliceInsets,
+        center: [f32; 2],
+        size: [f32; 2],
+    ) -> Self {
+        let texture_size = texture
+            .get_image()
+            .dimensions()
+            .width_height()
+            .map(|x| x as f32);
+        let vertices = create_vertices(insets, texture_size, center, size);
+
+        let mut entry = GenericDrawable::new(
+            gfx,
+            || {
+                vec![
+                    bindable::VertexBuffer::new(gfx, vertices),
+                    bindable::TextureBinding::new(texture.clone(), 1),
+                ]
+            },
+            || {
+                vec![
+                    bindable::IndexBuffer::new(gfx, create_indices()),
+                    bindable::VertexShader::from_source(
+                        vert_ui::load(gfx.get_device()),
+                        vert_ui::SOURCE_PATH,
+                    ),
+                    bindable::FragmentShader::from_source(
+                        frag_ui::load(gfx.get_device()),
+                        frag_ui::SOURCE_PATH,
+                    ),
+                    bindable::UniformBufferBinding::new(
+                        gfx.get_utils().cartesian_to_normalized.clone(),
+                        0,
+                    ),
+                ]
+            },
+        );
+
+        gfx.register_drawable(&mut entry);
+
+        Self {
+            entry: entry,
+            texture: texture,
+        }
+    }
+
+    pub fn get_texture(&self) -> Arc<Texture> {
+        self.texture.clone()
+    }
+}
+
+/// A 4x4 grid of vertices, row by row from the top left.
+fn create_vertices(
+    insets: NineSliceInsets,
+    texture_size: [f32; 2],
+    center: [f32; 2],
+    size: [f32; 2],
+) -> Vec<NineSliceVertex> {
+    // borders that don't fit are shrunk, keeping the ratio between opposite borders
+    let fit = |start: f32, end: f32, length: f32| match start + end > length {
+        true => (start * length / (start + end), end * length / (start + end)),
+        false => (start, end),
+    };
+    let (left, right) = fit(insets.left, insets.right, size[0]);
+    let (top, bottom) = fit(insets.top, insets.bottom, size[1]);
+
+    let half_size = [size[0] / 2.0, size[1] / 2.0];
+    let xs = [
+        center[0] - half_size[0],
+        center[0] - half_size[0] + left,
+        center[0] + half_size[0] - right,
+        center[0] + half_size[0],
+    ];
+    // y points up, so the top row has the largest y
+    let ys = [
+        center[1] + half_size[1],
+        center[1] + half_size[1] - top,
+        center[1] - half_size[1] + bottom,
+        center[1] - half_size[1],
+    ];
+
+    // the uvs use the insets as they are, so the borders are squashed instead of cut off
+    let us = [
+        0.0,
+        insets.left / texture_size[0],
+        1.0 - insets.right / texture_size[0],
+        1.0,
+    ];
+    let vs = [
+        0.0,
+        insets.top / texture_size[1],
+        1.0 - insets.bottom / texture_size[1],
+        1.0,
+    ];
+
+    let mut vertices = Vec::with_capacity(16);
+    for row in 0..4 {
+        for column in 0..4 {
+            vertices.push(NineSliceVertex {
+                pos: [xs[column], ys[row]],
+                uv: [us[column], vs[row]],
+            });
+        }
+    }
+    vertices
+}
+
+fn create_indices() -> Vec<u32> {
+    let mut indices = Vec::with_capacity(9 * 6);
+    for row in 0..3 {
+        for column in 0..3 {
+            let top_left = row * 4 + column;
+            let top_right = top_left + 1;
+            let bottom_left = top_left + 4;
+            let bottom_right = top_left + 5;
+            indices.extend([
+                top_left,
+                top_right,
+                bottom_left,
+                bottom_left,
+                top_right,
+                bottom_right,
+            ]);
+        }
+    }
+    indices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INSETS: NineSliceInsets = NineSliceInsets {
+        left: 8.0,
+        right: 8.0,
+        top: 4.0,
+        bottom: 12.0,
+    };
+
+    #[test]
+    fn nine_quads_with_fixed_size_corners() {
+        let vertices = create_vertices(INSETS, [32.0, 32.0], [0.0, 0.0], [200.0, 100.0]);
+        assert_eq!(vertices.len(), 16);
+        assert_eq!(create_indices().len(), 9 * 6);
+
+        // outer corners of the panel use the corners of the texture
+        assert_eq!(vertices[0].pos, [-100.0, 50.0]);
+        assert_eq!(vertices[0].uv, [0.0, 0.0]);
+        assert_eq!(vertices[3].uv, [1.0, 0.0]);
+        assert_eq!(vertices[12].uv, [0.0, 1.0]);
+        assert_eq!(vertices[15].pos, [100.0, -50.0]);
+        assert_eq!(vertices[15].uv, [1.0, 1.0]);
+
+        // the inner corners are as far in as the insets, no matter the size of the panel
+        assert_eq!(vertices[5].pos, [-92.0, 46.0]);
+        assert_eq!(vertices[5].uv, [0.25, 0.125]);
+        assert_eq!(vertices[10].pos, [92.0, -38.0]);
+        assert_eq!(vertices[10].uv, [0.75, 0.625]);
+    }
+
+    #[test]
+    fn borders_that_do_not_fit_are_shrunk() {
+        let vertices = create_vertices(INSETS, [32.0, 32.0], [0.0, 0.0], [8.0, 100.0]);
+        assert_eq!(vertices[1].pos[0], 0.0);
+        assert_eq!(vertices[2].pos[0], 0.0);
+        // the uvs still cover the whole border
+        assert_eq!(vertices[1].uv[0], 0.25);
+    }
+}
@@ -7,7 +7,7 @@ use vulkano::{
 
 use crate::graphics::{
     bindable::{self, PushConstant, UniformBufferBinding},
-    drawable::{DrawableEntry, GenericDrawable},
+    drawable::{DrawableEntry, GenericDrawable, Poolable},
     shaders::{frag_solid_white, vert_cartesian_2d},
     Graphics,
 };
@@ -20,7 +20,6 @@ pub struct Square {
 impl Square {
     pub fn new(gfx: &mut Graphics, pos: Vector2<f32>, radius: f32) -> Self {
         let data = PushConstant::new(
-            gfx,
             0,
             vert_cartesian_2d::Data {
                 transform: (cgmath::Matrix4::from_translation(cgmath::Vector3 {
@@ -56,13 +55,15 @@ impl Square {
                 vec![
                     bindable::VertexBuffer::new(gfx, vertices),
                     bindable::IndexBuffer::new(gfx, indices),
-                    bindable::VertexShader::from_module(
-                        vert_cartesian_2d::load(gfx.get_device()).unwrap(),
+                    bindable::VertexShader::from_source(
+                        vert_cartesian_2d::load(gfx.get_device()),
+                        vert_cartesian_2d::SOURCE_PATH,
                     ),
-                    bindable::FragmentShader::from_module(
-                        frag_solid_white::load(gfx.get_device()).unwrap(),
+                    bindable::FragmentShader::from_source(
+                        frag_solid_white::load(gfx.get_device()),
+                        frag_solid_white::SOURCE_PATH,
                     ),
-                    UniformBufferBinding::new(gfx.get_utils().cartesian_to_normalized.clone(), 1),
+                    UniformBufferBinding::new(gfx.get_utils().cartesian_to_normalized.clone(), 0),
                 ]
             },
         );
@@ -75,3 +76,9 @@ impl Square {
         }
     }
 }
+
+impl Poolable for Square {
+    fn get_entry(&self) -> &DrawableEntry {
+        &self.entry
+    }
+}
@@ -0,0 +1,301 @@
+use std::{collections::HashMap, sync::Arc};
+
+use serde::Deserialize;
+use vulkano::{
+    buffer::BufferContents, image::ImageViewAbstract, pipeline::graphics::vertex_input::Vertex,
+};
+
+use crate::graphics::{
+    bindable::{self, LoadError, Texture},
+    drawable::{DrawableEntry, GenericDrawable},
+    shaders::{frag_ui, vert_ui},
+    Graphics,
+};
+
+/// Where a glyph is in the font atlas and how far it moves the next glyph, in atlas pixels.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct Glyph {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub advance: u32,
+}
+
+/// The ron descriptor that is loaded next to the atlas, e.g.
+/// `(line_height: 16, glyphs: { 'A': (x: 0, y: 0, width: 8, height: 16, advance: 9) })`
+#[derive(Deserialize)]
+struct FontDescriptor {
+    line_height: u32,
+    glyphs: HashMap<char, Glyph>,
+}
+
+pub struct BitmapFont {
+    atlas: Arc<Texture>,
+    atlas_size: [f32; 2],
+    pub line_height: u32,
+    glyphs: HashMap<char, Glyph>,
+}
+
+impl BitmapFont {
+    pub fn load(
+        gfx: &Graphics,
+        atlas_path: &str,
+        descriptor_path: &str,
+    ) -> Result<Arc<Self>, LoadError> {
+        let text = std::fs::read_to_string(descriptor_path)?;
+        let descriptor: FontDescriptor = ron::from_str(&text)
+            .map_err(|e| LoadError::Malformed(format!("{descriptor_path}: {e}")))?;
+
+        let atlas = Texture::load(gfx, atlas_path, 0, true)?;
+        let atlas_size = atlas.get_image().dimensions().width_height();
+
+        if let Some((chr, _)) = descriptor.glyphs.iter().find(|(_, glyph)| {
+            glyph.x + glyph.width > atlas_size[0] || glyph.y + glyph.height > atlas_size[1]
+        }) {
+            return Err(LoadError::Malformed(format!(
+                "glyph {chr:?} is outside of the font atlas"
+            )));
+        }
+
+        Ok(Arc::new(Self {
+            atlas: atlas,
+            atlas_size: atlas_size.map(|x| x as f32),
+            line_height: descriptor.line_height,
+            glyphs: descriptor.glyphs,
+        }))
+    }
+
+    pub fn get_glyph(&self, chr: char) -> Option<&Glyph> {
+        self.glyphs.get(&chr)
+    }
+
+    pub fn get_texture(&self) -> Arc<Texture> {
+        self.atlas.clone()
+    }
+
+    /// Width of a line of text in atlas pixels.
+    pub fn measure(&self, text: &str) -> u32 {
+        measure(&self.glyphs, text)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextAlignment {
+    #[default]
+    Left,
+    Center,
+}
+
+#[derive(BufferContents, Vertex)]
+#[repr(C)]
+struct GlyphVertex {
+    #[format(R32G32_SFLOAT)]
+    pos: [f32; 2],
+    #[format(R32G32_SFLOAT)]
+    uv: [f32; 2],
+}
+
+/// A single line of text drawn with a `BitmapFont`.
+/// Characters that aren't in the font are skipped. Create a new `Text` to change what it says.
+pub struct Text {
+    entry: DrawableEntry,
+    font: Arc<BitmapFont>,
+    text: String,
+}
+
+impl Text {
+    /// `position` is the top of the line in UI pixels, on the left edge or the center
+    /// depending on `alignment`. `scale` is how many screen pixels one atlas pixel covers.
+    pub fn new(
+        gfx: &mut Graphics,
+        font: Arc<BitmapFont>,
+        text: &str,
+        position: [f32; 2],
+        scale: f32,
+        alignment: TextAlignment,
+    ) -> Self {
+        let (mut vertices, mut indices) = create_mesh(
+            &font.glyphs,
+            font.atlas_size,
+            text,
+            position,
+            scale,
+            alignment,
+        );
+
+        // empty buffers can't be created, so an empty text keeps one quad and draws no indices
+        let glyph_index_count = indices.len() as u32;
+        if vertices.is_empty() {
+            vertices.resize_with(4, || GlyphVertex {
+                pos: position,
+                uv: [0.0, 0.0],
+            });
+            indices.extend([0, 1, 2, 2, 1, 3]);
+        }
+
+        let mut entry = GenericDrawable::new(
+            gfx,
+            || {
+                vec![
+                    bindable::VertexBuffer::new(gfx, vertices),
+                    bindable::IndexBuffer::new(gfx, indices),
+                    bindable::TextureBinding::new(font.get_texture(), 1),
+                ]
+            },
+            || {
+                vec![
+                    bindable::VertexShader::from_source(
+                        vert_ui::load(gfx.get_device()),
+                        vert_ui::SOURCE_PATH,
+                    ),
+                    bindable::FragmentShader::from_source(
+                        frag_ui::load(gfx.get_device()),
+                        frag_ui::SOURCE_PATH,
+                    ),
+                    bindable::UniformBufferBinding::new(
+                        gfx.get_utils().cartesian_to_normalized.clone(),
+                        0,
+                    ),
+                ]
+            },
+        );
+        entry.set_index_count(glyph_index_count);
+
+        gfx.register_drawable(&mut entry);
+
+        Self {
+            entry: entry,
+            font: font,
+            text: text.to_string(),
+        }
+    }
+
+    pub fn get_text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn get_font(&self) -> Arc<BitmapFont> {
+        self.font.clone()
+    }
+}
+
+fn measure(glyphs: &HashMap<char, Glyph>, text: &str) -> u32 {
+    text.chars()
+        .filter_map(|chr| glyphs.get(&chr))
+        .map(|glyph| glyph.advance)
+        .sum()
+}
+
+/// Four vertices per glyph, in the order top left, top right, bottom left, bottom right.
+fn create_mesh(
+    glyphs: &HashMap<char, Glyph>,
+    atlas_size: [f32; 2],
+    text: &str,
+    position: [f32; 2],
+    scale: f32,
+    alignment: TextAlignment,
+) -> (Vec<GlyphVertex>, Vec<u32>) {
+    let mut pen_x = match alignment {
+        TextAlignment::Left => position[0],
+        TextAlignment::Center => position[0] - measure(glyphs, text) as f32 * scale / 2.0,
+    };
+    let top = position[1];
+
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    for glyph in text.chars().filter_map(|chr| glyphs.get(&chr)) {
+        let left = pen_x;
+        let right = pen_x + glyph.width as f32 * scale;
+        let bottom = top - glyph.height as f32 * scale;
+
+        let u0 = glyph.x as f32 / atlas_size[0];
+        let u1 = (glyph.x + glyph.width) as f32 / atlas_size[0];
+        let v0 = glyph.y as f32 / atlas_size[1];
+        let v1 = (glyph.y + glyph.height) as f32 / atlas_size[1];
+
+        let first = vertices.len() as u32;
+        vertices.extend([
+            GlyphVertex {
+                pos: [left, top],
+                uv: [u0, v0],
+            },
+            GlyphVertex {
+                pos: [right, top],
+                uv: [u1, v0],
+            },
+            GlyphVertex {
+                pos: [left, bottom],
+                uv: [u0, v1],
+            },
+            GlyphVertex {
+                pos: [right, bottom],
+                uv: [u1, v1],
+            },
+        ]);
+        indices.extend([first, first + 1, first + 2, first + 2, first + 1, first + 3]);
+
+        pen_x += glyph.advance as f32 * scale;
+    }
+
+    (vertices, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // three 8 pixel wide glyphs next to each other in a 32x16 atlas
+    fn abc_glyphs() -> HashMap<char, Glyph> {
+        HashMap::from_iter(['A', 'B', 'C'].into_iter().enumerate().map(|(i, chr)| {
+            let glyph = Glyph {
+                x: i as u32 * 8,
+                y: 0,
+                width: 8,
+                height: 16,
+                advance: 9,
+            };
+            (chr, glyph)
+        }))
+    }
+
+    #[test]
+    fn glyphs_are_laid_out_by_their_advance() {
+        let glyphs = abc_glyphs();
+        let (vertices, indices) = create_mesh(
+            &glyphs,
+            [32.0, 16.0],
+            "ABC",
+            [10.0, 0.0],
+            2.0,
+            TextAlignment::Left,
+        );
+        assert_eq!(vertices.len(), 3 * 4);
+        assert_eq!(indices.len(), 3 * 6);
+
+        let left_edges: Vec<f32> = vertices.chunks(4).map(|quad| quad[0].pos[0]).collect();
+        assert_eq!(left_edges, vec![10.0, 28.0, 46.0]);
+        assert_eq!(vertices[1].pos, [26.0, 0.0]);
+        assert_eq!(vertices[3].pos[1], -32.0);
+        assert_eq!(vertices[4].uv, [0.25, 0.0]);
+    }
+
+    #[test]
+    fn centered_text_and_missing_glyphs() {
+        let glyphs = abc_glyphs();
+        assert_eq!(measure(&glyphs, "ABC"), 27);
+
+        let (vertices, _) = create_mesh(
+            &glyphs,
+            [32.0, 16.0],
+            "A?C",
+            [0.0, 0.0],
+            1.0,
+            TextAlignment::Center,
+        );
+        // the unknown character is skipped without leaving a gap
+        assert_eq!(vertices.len(), 2 * 4);
+        assert_eq!(vertices[0].pos[0], -9.0);
+        assert_eq!(vertices[4].pos[0], 0.0);
+    }
+}
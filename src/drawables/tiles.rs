@@ -1,7 +1,11 @@
+mod instanced_tiles;
+mod sprite_animator;
 mod tile;
 mod tile_group;
 mod tileset;
 
+pub use instanced_tiles::*;
+pub use sprite_animator::*;
 pub use tile::*;
 pub use tile_group::*;
 pub use tileset::*;
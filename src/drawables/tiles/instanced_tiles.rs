@@ -0,0 +1,139 @@
+use std::sync::Arc;
+
+use vulkano::{buffer::BufferContents, pipeline::graphics::vertex_input::Vertex};
+
+use crate::graphics::{
+    bindable::{self, InstanceBuffer},
+    camera::Camera,
+    drawable::{DrawableEntry, GenericDrawable},
+    shaders::{frag_tinted, vert_instanced_tile},
+    Graphics, CAMERA_SET,
+};
+
+use super::TileSet;
+
+#[derive(BufferContents, Vertex)]
+#[repr(C)]
+struct VertexT {
+    #[format(R32G32_SFLOAT)]
+    pos: [f32; 2],
+    #[format(R32G32_SFLOAT)]
+    uv: [f32; 2],
+}
+
+#[derive(BufferContents, Vertex)]
+#[repr(C)]
+struct InstanceT {
+    #[format(R32G32_SFLOAT)]
+    instance_offset: [f32; 2],
+    #[format(R32G32_SFLOAT)]
+    uv_offset: [f32; 2],
+    #[format(R32G32B32A32_SFLOAT)]
+    tint: [f32; 4],
+}
+
+pub struct TileInstanceDesc {
+    pub tile_position: [f32; 2],
+    pub sprite_idx: u32,
+    pub tint: [f32; 4],
+}
+
+/// Many copies of tiles from the same tile set, drawn with a single draw call.
+pub struct InstancedTiles {
+    instance_buffer: Arc<InstanceBuffer<InstanceT>>,
+    tile_set: Arc<TileSet>,
+    entry: DrawableEntry,
+}
+
+impl InstancedTiles {
+    pub fn new(
+        gfx: &mut Graphics,
+        tile_set: Arc<TileSet>,
+        instances: Vec<TileInstanceDesc>,
+        scale: f32,
+        camera: &Camera,
+    ) -> Self {
+        // the quad uses the uvs of the first sprite, every instance offsets them to its own sprite
+        let instance_data = instances
+            .into_iter()
+            .map(|instance| InstanceT {
+                instance_offset: [
+                    instance.tile_position[0] * scale,
+                    instance.tile_position[1] * -scale,
+                ],
+                uv_offset: tile_set.get_uv_offset_of_sprite(instance.sprite_idx),
+                tint: instance.tint,
+            })
+            .collect();
+
+        let instance_buffer = InstanceBuffer::new(gfx, instance_data);
+
+        let mut entry = GenericDrawable::new(
+            gfx,
+            || {
+                vec![
+                    bindable::TextureBinding::new(tile_set.get_texture(), 1),
+                    instance_buffer.clone(),
+                ]
+            },
+            || {
+                let uvs = tile_set.get_uv_of_sprite(0);
+                let vertices = vec![
+                    VertexT {
+                        pos: [0.0, 0.0],
+                        uv: uvs[0],
+                    },
+                    VertexT {
+                        pos: [scale, 0.0],
+                        uv: uvs[1],
+                    },
+                    VertexT {
+                        pos: [0.0, -scale],
+                        uv: uvs[2],
+                    },
+                    VertexT {
+                        pos: [scale, -scale],
+                        uv: uvs[3],
+                    },
+                ];
+
+                let indices = vec![0, 1, 2, 2, 1, 3];
+
+                vec![
+                    bindable::VertexBuffer::new(gfx, vertices),
+                    bindable::IndexBuffer::new(gfx, indices),
+                    bindable::VertexShader::from_source(
+                        vert_instanced_tile::load(gfx.get_device()),
+                        vert_instanced_tile::SOURCE_PATH,
+                    ),
+                    bindable::FragmentShader::from_source(
+                        frag_tinted::load(gfx.get_device()),
+                        frag_tinted::SOURCE_PATH,
+                    ),
+                    bindable::UniformBufferBinding::new(
+                        gfx.get_utils().cartesian_to_normalized.clone(),
+                        0,
+                    ),
+                    bindable::UniformBufferBinding::new(camera.uniform_buffer(), CAMERA_SET),
+                ]
+            },
+        );
+
+        gfx.register_drawable(&mut entry);
+
+        Self {
+            instance_buffer: instance_buffer,
+            tile_set: tile_set,
+            entry: entry,
+        }
+    }
+
+    /// Only draws the first `instance_count` tiles.
+    pub fn set_instance_count(&self, instance_count: u32) {
+        self.instance_buffer.set_instance_count(instance_count);
+    }
+
+    pub fn get_tile_set(&self) -> Arc<TileSet> {
+        self.tile_set.clone()
+    }
+}
@@ -0,0 +1,131 @@
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnimationMode {
+    Loop,
+    /// Stops on the last frame.
+    Once,
+}
+
+/// Steps through a list of frames at a fixed rate. The frames are whatever the drawable uses to pick
+/// its sprite, like the `frame_offset` of an `AnimatedTile`.
+pub struct SpriteAnimator {
+    frames: Vec<u32>,
+    frame_duration: Duration,
+    mode: AnimationMode,
+    frame_idx: usize,
+    elapsed: Duration,
+    finished: bool,
+    on_finish: Option<Box<dyn FnMut()>>,
+}
+
+impl SpriteAnimator {
+    pub fn new(frames: Vec<u32>, frame_duration: Duration, mode: AnimationMode) -> Self {
+        Self {
+            frames: frames,
+            frame_duration: frame_duration,
+            mode: mode,
+            frame_idx: 0,
+            elapsed: Duration::ZERO,
+            finished: false,
+            on_finish: None,
+        }
+    }
+
+    /// Called once when a `Once` animation reaches its last frame, or every time a `Loop` animation wraps around.
+    pub fn set_on_finish(&mut self, on_finish: Box<dyn FnMut()>) {
+        self.on_finish = Some(on_finish);
+    }
+
+    /// Returns the new frame if it changed.
+    pub fn update(&mut self, delta_time: Duration) -> Option<u32> {
+        if self.finished || self.frames.len() < 2 || self.frame_duration.is_zero() {
+            return None;
+        }
+
+        self.elapsed += delta_time;
+        let previous_idx = self.frame_idx;
+        while self.elapsed >= self.frame_duration && !self.finished {
+            self.elapsed -= self.frame_duration;
+            self.frame_idx += 1;
+
+            if self.frame_idx == self.frames.len() {
+                self.frame_idx = 0;
+                if let Some(on_finish) = &mut self.on_finish {
+                    on_finish();
+                }
+            } else if self.frame_idx == self.frames.len() - 1 && self.mode == AnimationMode::Once {
+                self.finished = true;
+                if let Some(on_finish) = &mut self.on_finish {
+                    on_finish();
+                }
+            }
+        }
+
+        match self.frame_idx != previous_idx {
+            true => Some(self.frames[self.frame_idx]),
+            false => None,
+        }
+    }
+
+    /// Starts over from the first frame.
+    pub fn restart(&mut self) {
+        self.frame_idx = 0;
+        self.elapsed = Duration::ZERO;
+        self.finished = false;
+    }
+
+    pub fn get_frame(&self) -> u32 {
+        self.frames.get(self.frame_idx).copied().unwrap_or(0)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(100);
+
+    #[test]
+    fn four_frame_loop_wraps_around() {
+        let mut animator = SpriteAnimator::new(vec![10, 11, 12, 13], FRAME, AnimationMode::Loop);
+        let loops = Rc::new(Cell::new(0));
+        let counter = loops.clone();
+        animator.set_on_finish(Box::new(move || counter.set(counter.get() + 1)));
+
+        assert_eq!(animator.update(Duration::from_millis(50)), None);
+        assert_eq!(animator.get_frame(), 10);
+        assert_eq!(animator.update(Duration::from_millis(60)), Some(11));
+        // a long tick skips frames instead of falling behind
+        assert_eq!(animator.update(Duration::from_millis(250)), Some(13));
+        assert_eq!(loops.get(), 0);
+
+        assert_eq!(animator.update(Duration::from_millis(40)), Some(10));
+        assert_eq!(loops.get(), 1);
+        assert!(!animator.is_finished());
+    }
+
+    #[test]
+    fn once_stops_on_the_last_frame() {
+        let mut animator = SpriteAnimator::new(vec![0, 1, 2], FRAME, AnimationMode::Once);
+        let finishes = Rc::new(Cell::new(0));
+        let counter = finishes.clone();
+        animator.set_on_finish(Box::new(move || counter.set(counter.get() + 1)));
+
+        assert_eq!(animator.update(Duration::from_secs(1)), Some(2));
+        assert!(animator.is_finished());
+        assert_eq!(animator.update(FRAME), None);
+        assert_eq!(animator.get_frame(), 2);
+        assert_eq!(finishes.get(), 1);
+
+        animator.restart();
+        assert_eq!(animator.get_frame(), 0);
+        assert_eq!(animator.update(FRAME), Some(1));
+    }
+}
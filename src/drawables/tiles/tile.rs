@@ -1,16 +1,16 @@
 use std::sync::Arc;
 
+use cgmath::{Matrix4, SquareMatrix};
 use vulkano::{
-    buffer::BufferContents, image::ImageViewAbstract, pipeline::graphics::vertex_input::Vertex,
-    shader::ShaderStages,
+    buffer::BufferContents, pipeline::graphics::vertex_input::Vertex, shader::ShaderStages,
 };
 
 use crate::graphics::{
-    bindable::{self, PushConstant},
+    bindable::{self, PushConstant, UniformBuffer},
     camera::Camera,
     drawable::{DrawableEntry, GenericDrawable},
-    shaders::{frag_textured, vert_animated_tile, vert_tile},
-    Graphics,
+    shaders::{frag_textured, frag_tinted_textured, vert_animated_tile, vert_tile},
+    Graphics, CAMERA_SET,
 };
 
 use super::TileSet;
@@ -37,16 +37,14 @@ impl AnimatedTile {
         camera: &Camera,
     ) -> Self {
         let data = bindable::PushConstant::new(
-            gfx,
             0,
             vert_animated_tile::ObjectData {
                 object_position: [
                     tile_desc.tile_position[0] as f32 * scale,
                     tile_desc.tile_position[1] as f32 * -scale,
                 ],
-                base_uv_offset: tile_set.get_uv_of_sprite(tile_desc.first_sprite_idx)[0],
-                frame_uv_stride: tile_desc.frame_stride as f32 * tile_set.tile_width as f32
-                    / tile_set.get_texture().image.dimensions().width() as f32,
+                base_uv_offset: tile_set.get_uv_offset_of_sprite(tile_desc.first_sprite_idx),
+                frame_uv_stride: tile_desc.frame_stride as f32 * tile_set.get_uv_stride(),
                 frame_offset: 0,
             },
             ShaderStages::VERTEX,
@@ -95,17 +93,19 @@ impl AnimatedTile {
                 vec![
                     bindable::VertexBuffer::new(gfx, vertices),
                     bindable::IndexBuffer::new(gfx, indices),
-                    bindable::VertexShader::from_module(
-                        vert_animated_tile::load(gfx.get_device()).unwrap(),
+                    bindable::VertexShader::from_source(
+                        vert_animated_tile::load(gfx.get_device()),
+                        vert_animated_tile::SOURCE_PATH,
                     ),
-                    bindable::FragmentShader::from_module(
-                        frag_textured::load(gfx.get_device()).unwrap(),
+                    bindable::FragmentShader::from_source(
+                        frag_textured::load(gfx.get_device()),
+                        frag_textured::SOURCE_PATH,
                     ),
                     bindable::UniformBufferBinding::new(
                         gfx.get_utils().cartesian_to_normalized.clone(),
                         0,
                     ),
-                    bindable::UniformBufferBinding::new(camera.uniform_buffer(), 2),
+                    bindable::UniformBufferBinding::new(camera.uniform_buffer(), CAMERA_SET),
                 ]
             },
         );
@@ -119,29 +119,62 @@ impl AnimatedTile {
             descriptor: tile_desc,
         }
     }
+
+    /// Shows the sprite `frame` strides after the first one.
+    pub fn set_frame(&self, frame: u32) {
+        self.data.access_data(|data| data.frame_offset = frame);
+    }
 }
 
-pub struct Tile {
-    descriptor: TileDesc,
+pub struct DynamicTile {
+    descriptor: DynamicTileDesc,
     tile_set: Arc<TileSet>,
+    tint: Arc<UniformBuffer<frag_tinted_textured::TintUbo>>,
     entry: DrawableEntry,
 }
 
-pub struct TileDesc {
+/// The descriptor set the tint of a tile is bound to.
+const TINT_SET: u32 = 3;
+
+pub struct DynamicTileDesc {
     pub tile_position: [u32; 2],
     pub sprite_idx: u32,
 }
 
-impl Tile {
+impl DynamicTile {
     pub fn new(
         gfx: &mut Graphics,
         tile_set: Arc<TileSet>,
-        tile_desc: TileDesc,
+        tile_desc: DynamicTileDesc,
         camera: &Camera,
     ) -> Self {
+        let group_data = bindable::PushConstant::new(
+            0,
+            vert_tile::GroupData {
+                model: Matrix4::identity().into(),
+                parallax_factor: [1.0, 1.0],
+            },
+            ShaderStages::VERTEX,
+        );
+
+        let tint = UniformBuffer::new(
+            gfx,
+            0,
+            frag_tinted_textured::TintUbo {
+                tint: [1.0, 1.0, 1.0, 1.0],
+            },
+            ShaderStages::FRAGMENT,
+        );
+
         let mut entry = GenericDrawable::new(
             gfx,
-            || vec![bindable::TextureBinding::new(tile_set.get_texture(), 1)],
+            || {
+                vec![
+                    bindable::TextureBinding::new(tile_set.get_texture(), 1),
+                    group_data.clone(),
+                    bindable::UniformBufferBinding::new(tint.clone(), TINT_SET),
+                ]
+            },
             || {
                 #[derive(BufferContents, Vertex)]
                 #[repr(C)]
@@ -177,15 +210,19 @@ impl Tile {
                 vec![
                     bindable::VertexBuffer::new(gfx, vertices),
                     bindable::IndexBuffer::new(gfx, indices),
-                    bindable::VertexShader::from_module(vert_tile::load(gfx.get_device()).unwrap()),
-                    bindable::FragmentShader::from_module(
-                        frag_textured::load(gfx.get_device()).unwrap(),
+                    bindable::VertexShader::from_source(
+                        vert_tile::load(gfx.get_device()),
+                        vert_tile::SOURCE_PATH,
+                    ),
+                    bindable::FragmentShader::from_source(
+                        frag_tinted_textured::load(gfx.get_device()),
+                        frag_tinted_textured::SOURCE_PATH,
                     ),
                     bindable::UniformBufferBinding::new(
                         gfx.get_utils().cartesian_to_normalized.clone(),
                         0,
                     ),
-                    bindable::UniformBufferBinding::new(camera.uniform_buffer(), 2),
+                    bindable::UniformBufferBinding::new(camera.uniform_buffer(), CAMERA_SET),
                 ]
             },
         );
@@ -194,8 +231,15 @@ impl Tile {
 
         Self {
             tile_set: tile_set,
+            tint: tint,
             entry: entry,
             descriptor: tile_desc,
         }
     }
+
+    /// Multiplies the color of the sprite, `[1.0, 1.0, 1.0, 1.0]` shows it as it is.
+    /// Values above 1.0 brighten it, like for a hit flash.
+    pub fn set_tint(&self, tint: [f32; 4]) {
+        self.tint.access_data(|data| data.tint = tint);
+    }
 }
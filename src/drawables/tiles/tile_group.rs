@@ -1,18 +1,27 @@
 use std::{collections::HashMap, ops::Mul, sync::Arc};
 
+use cgmath::{Matrix4, SquareMatrix, Vector3};
+
 use vulkano::{
     buffer::BufferContents, image::ImageViewAbstract, pipeline::graphics::vertex_input::Vertex,
+    shader::ShaderStages,
 };
 
-use crate::graphics::{
-    bindable,
-    camera::Camera,
-    drawable::{DrawableEntry, GenericDrawable},
-    shaders::{frag_textured, vert_textured, vert_tile},
-    Graphics,
+use crate::{
+    graphics::{
+        bindable::{self, PushConstant},
+        camera::Camera,
+        drawable::{DrawableEntry, GenericDrawable},
+        shaders::{frag_textured, vert_textured, vert_tile},
+        Graphics, CAMERA_SET,
+    },
+    math::Rect,
 };
 
-use super::{tile::AnimatedTile, AnimationDesc, TileSet};
+use super::{tile::AnimatedTile, tileset::FLIP_FLAGS, AnimationDesc, TileSet};
+
+// Width and height of a chunk in tiles.
+const CHUNK_SIZE: u32 = 16;
 
 #[derive(BufferContents, Vertex)]
 #[repr(C)]
@@ -23,11 +32,26 @@ struct VertexT {
     uv: [f32; 2],
 }
 
+struct TileChunk {
+    bounds: Rect,
+    drawable: DrawableEntry,
+}
+
+/// A grid of static tiles. The tiles are split into square chunks that each
+/// get their own mesh, so chunks outside of the camera's view can be skipped.
 pub struct StaticTileGroup {
     tile_set: Arc<TileSet>,
     dimensions: [u32; 2],
+    // sprite indices, optionally with FLIP_HORIZONTAL_FLAG or FLIP_VERTICAL_FLAG set
     tiles: Vec<Option<u32>>,
-    drawable: DrawableEntry,
+    tile_scale: f32,
+    // where the top left corner of the group is in world space
+    position: [f32; 2],
+    scale: f32,
+    group_data: Arc<PushConstant<vert_tile::GroupData>>,
+    parallax_factor: [f32; 2],
+    chunks: Vec<TileChunk>,
+    cull_enabled: bool,
 }
 
 impl StaticTileGroup {
@@ -39,78 +63,429 @@ impl StaticTileGroup {
         tile_scale: f32,
         camera: &Camera,
     ) -> Self {
-        let tile_count = u32::min(tiles.len() as u32, dimensions[0] * dimensions[1]);
-        let mut vertices = Vec::with_capacity(4 * tile_count as usize);
-        let mut indices = Vec::with_capacity(6 * tile_count as usize);
-
-        for y in 0..dimensions[1] {
-            for x in 0..dimensions[0] {
-                if let Some(tile_idx) = tiles[(y * dimensions[0] + x) as usize] {
-                    let left = x as f32 * tile_scale;
-                    let right = (x + 1) as f32 * tile_scale;
-                    let bottom = (y + 1) as f32 * -tile_scale;
-                    let top = y as f32 * -tile_scale;
-
-                    let uvs = tile_set.get_uv_of_sprite(tile_idx);
-
-                    vertices.push(VertexT {
-                        pos: [left, top],
-                        uv: uvs[0],
-                    });
-                    vertices.push(VertexT {
-                        pos: [right, top],
-                        uv: uvs[1],
-                    });
-                    vertices.push(VertexT {
-                        pos: [left, bottom],
-                        uv: uvs[2],
-                    });
-                    vertices.push(VertexT {
-                        pos: [right, bottom],
-                        uv: uvs[3],
-                    });
-
-                    let index_offset = 4 * (y * dimensions[0] + x);
-                    indices.extend(
-                        [0, 1, 2, 2, 1, 3]
-                            .into_iter()
-                            .map(|elem| elem + index_offset),
-                    );
-                }
-            }
-        }
-
-        let mut drawable_entry = GenericDrawable::new(
-            gfx,
-            || {
-                vec![
-                    bindable::VertexBuffer::new(gfx, vertices),
-                    bindable::IndexBuffer::new(gfx, indices),
-                ]
-            },
-            || {
-                vec![
-                    bindable::VertexShader::from_module(vert_tile::load(gfx.get_device()).unwrap()),
-                    bindable::FragmentShader::from_module(
-                        frag_textured::load(gfx.get_device()).unwrap(),
-                    ),
-                    bindable::UniformBufferBinding::new(
-                        gfx.get_utils().cartesian_to_normalized.clone(),
-                        0,
-                    ),
-                    bindable::TextureBinding::new(tile_set.get_texture(), 1),
-                    bindable::UniformBufferBinding::new(camera.uniform_buffer(), 2),
-                ]
+        let group_data = PushConstant::new(
+            0,
+            vert_tile::GroupData {
+                model: Matrix4::identity().into(),
+                parallax_factor: [1.0, 1.0],
             },
+            ShaderStages::VERTEX,
         );
 
-        gfx.register_drawable(&mut drawable_entry);
+        let chunk_count = [
+            (dimensions[0] + CHUNK_SIZE - 1) / CHUNK_SIZE,
+            (dimensions[1] + CHUNK_SIZE - 1) / CHUNK_SIZE,
+        ];
+
+        let mut chunks = Vec::with_capacity((chunk_count[0] * chunk_count[1]) as usize);
+
+        for chunk_y in 0..chunk_count[1] {
+            for chunk_x in 0..chunk_count[0] {
+                let first_tile = [chunk_x * CHUNK_SIZE, chunk_y * CHUNK_SIZE];
+                let last_tile = [
+                    u32::min(first_tile[0] + CHUNK_SIZE, dimensions[0]),
+                    u32::min(first_tile[1] + CHUNK_SIZE, dimensions[1]),
+                ];
+
+                let (vertices, indices) = build_chunk_mesh(
+                    &tiles,
+                    dimensions,
+                    first_tile,
+                    last_tile,
+                    tile_scale,
+                    |tile_idx| tile_set.get_flipped_uv_of_sprite(tile_idx),
+                );
+
+                if indices.is_empty() {
+                    continue;
+                }
+
+                let bounds = Rect::new(
+                    [
+                        first_tile[0] as f32 * tile_scale,
+                        last_tile[1] as f32 * -tile_scale,
+                    ],
+                    [
+                        last_tile[0] as f32 * tile_scale,
+                        first_tile[1] as f32 * -tile_scale,
+                    ],
+                );
+
+                let mut drawable_entry = GenericDrawable::new(
+                    gfx,
+                    || {
+                        vec![
+                            bindable::VertexBuffer::new(gfx, vertices),
+                            bindable::IndexBuffer::new(gfx, indices),
+                            group_data.clone(),
+                        ]
+                    },
+                    || {
+                        vec![
+                            bindable::VertexShader::from_source(
+                                vert_tile::load(gfx.get_device()),
+                                vert_tile::SOURCE_PATH,
+                            ),
+                            bindable::FragmentShader::from_source(
+                                frag_textured::load(gfx.get_device()),
+                                frag_textured::SOURCE_PATH,
+                            ),
+                            bindable::UniformBufferBinding::new(
+                                gfx.get_utils().cartesian_to_normalized.clone(),
+                                0,
+                            ),
+                            bindable::TextureBinding::new(tile_set.get_texture(), 1),
+                            bindable::UniformBufferBinding::new(
+                                camera.uniform_buffer(),
+                                CAMERA_SET,
+                            ),
+                        ]
+                    },
+                );
+
+                gfx.register_drawable(&mut drawable_entry);
+
+                chunks.push(TileChunk {
+                    bounds: bounds,
+                    drawable: drawable_entry,
+                });
+            }
+        }
 
         Self {
             tile_set: tile_set,
             dimensions: dimensions,
             tiles: tiles,
-            drawable: drawable_entry,
+            tile_scale: tile_scale,
+            position: [0.0, 0.0],
+            scale: 1.0,
+            group_data: group_data,
+            parallax_factor: [1.0, 1.0],
+            chunks: chunks,
+            cull_enabled: true,
         }
     }
+
+    pub fn dimensions(&self) -> [u32; 2] {
+        self.dimensions
+    }
+
+    /// Moves the top left corner of the group to `position` in world space.
+    /// Only the push constant changes, the chunk meshes are kept as they are.
+    pub fn set_position(&mut self, position: [f32; 2]) {
+        self.position = position;
+        self.group_data.access_data(|data| {
+            data.model = group_model(position, self.scale).into();
+        });
+    }
+
+    pub fn get_position(&self) -> [f32; 2] {
+        self.position
+    }
+
+    /// Scales the group around its top left corner.
+    /// Like `set_position` this only updates the push constant.
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale;
+        self.group_data.access_data(|data| {
+            data.model = group_model(self.position, scale).into();
+        });
+    }
+
+    pub fn get_scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// The area covered by the group in world space, e.g. for `Camera::set_bounds`.
+    pub fn world_bounds(&self) -> Rect {
+        local_to_world(
+            Rect::new(
+                [0.0, self.dimensions[1] as f32 * -self.tile_scale],
+                [self.dimensions[0] as f32 * self.tile_scale, 0.0],
+            ),
+            self.position,
+            self.scale,
+        )
+    }
+
+    /// Whether the tile position lies inside of the group.
+    pub fn in_bounds(&self, tile_position: [i32; 2]) -> bool {
+        in_bounds(tile_position, self.dimensions)
+    }
+
+    /// The sprite index of a tile without the flip flags,
+    /// `None` for empty tiles and positions outside of the group.
+    pub fn get_tile(&self, tile_position: [i32; 2]) -> Option<u32> {
+        sprite_index_at(&self.tiles, self.dimensions, tile_position)
+    }
+
+    /// The tile under a world position, `None` if the position is outside of the group.
+    pub fn world_to_tile(&self, world_position: [f32; 2]) -> Option<[u32; 2]> {
+        world_to_tile(
+            world_position,
+            self.position,
+            self.tile_scale * self.scale,
+            self.dimensions,
+        )
+    }
+
+    /// Makes the group scroll at a fraction of the camera speed on each axis.
+    /// `[1.0, 1.0]` moves with the camera, `[0.5, 0.5]` moves half as fast.
+    pub fn set_parallax_factor(&mut self, parallax_factor: [f32; 2]) {
+        self.parallax_factor = parallax_factor;
+        self.group_data.access_data(|data| {
+            data.parallax_factor = parallax_factor;
+        });
+    }
+
+    /// When disabled every chunk is drawn regardless of the camera.
+    pub fn set_cull_enabled(&mut self, cull_enabled: bool) {
+        self.cull_enabled = cull_enabled;
+        if !cull_enabled {
+            for chunk in &self.chunks {
+                chunk.drawable.set_visible(true);
+            }
+        }
+    }
+
+    /// Hides the chunks that are outside of the camera's view.
+    /// Call this every frame after the camera has moved.
+    pub fn cull(&self, gfx: &Graphics, camera: &Camera) {
+        if !self.cull_enabled {
+            return;
+        }
+
+        let visible_area = camera.visible_area(gfx, self.parallax_factor);
+        for chunk in &self.chunks {
+            chunk.drawable.set_visible(is_chunk_visible(
+                chunk.bounds,
+                self.position,
+                self.scale,
+                &visible_area,
+            ));
+        }
+    }
+
+    /// The number of chunks that will be drawn this frame.
+    pub fn visible_chunk_count(&self) -> usize {
+        self.chunks
+            .iter()
+            .filter(|chunk| chunk.drawable.is_visible())
+            .count()
+    }
+}
+
+// the model matrix of a group, the chunk meshes themselves never move
+fn group_model(position: [f32; 2], scale: f32) -> Matrix4<f32> {
+    Matrix4::from_translation(Vector3::new(position[0], position[1], 0.0))
+        * Matrix4::from_scale(scale)
+}
+
+// the meshes are built around the origin and moved by the model matrix
+fn local_to_world(rect: Rect, position: [f32; 2], scale: f32) -> Rect {
+    Rect::new(
+        [
+            rect.min[0] * scale + position[0],
+            rect.min[1] * scale + position[1],
+        ],
+        [
+            rect.max[0] * scale + position[0],
+            rect.max[1] * scale + position[1],
+        ],
+    )
+}
+
+fn is_chunk_visible(
+    chunk_bounds: Rect,
+    position: [f32; 2],
+    scale: f32,
+    visible_area: &Rect,
+) -> bool {
+    local_to_world(chunk_bounds, position, scale).intersects(visible_area)
+}
+
+fn in_bounds(tile_position: [i32; 2], dimensions: [u32; 2]) -> bool {
+    tile_position[0] >= 0
+        && tile_position[1] >= 0
+        && (tile_position[0] as u32) < dimensions[0]
+        && (tile_position[1] as u32) < dimensions[1]
+}
+
+fn sprite_index_at(
+    tiles: &[Option<u32>],
+    dimensions: [u32; 2],
+    tile_position: [i32; 2],
+) -> Option<u32> {
+    if !in_bounds(tile_position, dimensions) {
+        return None;
+    }
+    let index = tile_position[1] as usize * dimensions[0] as usize + tile_position[0] as usize;
+    tiles
+        .get(index)
+        .copied()
+        .flatten()
+        .map(|tile| tile & !FLIP_FLAGS)
+}
+
+fn world_to_tile(
+    world_position: [f32; 2],
+    position: [f32; 2],
+    world_tile_size: f32,
+    dimensions: [u32; 2],
+) -> Option<[u32; 2]> {
+    // rows grow downwards from the top left corner
+    let tile_position = [
+        ((world_position[0] - position[0]) / world_tile_size).floor() as i32,
+        (-(world_position[1] - position[1]) / world_tile_size).floor() as i32,
+    ];
+
+    match in_bounds(tile_position, dimensions) {
+        true => Some([tile_position[0] as u32, tile_position[1] as u32]),
+        false => None,
+    }
+}
+
+// One quad per non empty tile between `first_tile` and `last_tile`, relative to the top left corner of the group.
+fn build_chunk_mesh(
+    tiles: &[Option<u32>],
+    dimensions: [u32; 2],
+    first_tile: [u32; 2],
+    last_tile: [u32; 2],
+    tile_scale: f32,
+    uvs_of_tile: impl Fn(u32) -> [[f32; 2]; 4],
+) -> (Vec<VertexT>, Vec<u32>) {
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+
+    for y in first_tile[1]..last_tile[1] {
+        for x in first_tile[0]..last_tile[0] {
+            let tile = tiles
+                .get((y * dimensions[0] + x) as usize)
+                .copied()
+                .flatten();
+
+            if let Some(tile_idx) = tile {
+                let left = x as f32 * tile_scale;
+                let right = (x + 1) as f32 * tile_scale;
+                let bottom = (y + 1) as f32 * -tile_scale;
+                let top = y as f32 * -tile_scale;
+
+                let uvs = uvs_of_tile(tile_idx);
+
+                let index_offset = vertices.len() as u32;
+
+                vertices.push(VertexT {
+                    pos: [left, top],
+                    uv: uvs[0],
+                });
+                vertices.push(VertexT {
+                    pos: [right, top],
+                    uv: uvs[1],
+                });
+                vertices.push(VertexT {
+                    pos: [left, bottom],
+                    uv: uvs[2],
+                });
+                vertices.push(VertexT {
+                    pos: [right, bottom],
+                    uv: uvs[3],
+                });
+
+                indices.extend(
+                    [0, 1, 2, 2, 1, 3]
+                        .into_iter()
+                        .map(|elem| elem + index_offset),
+                );
+            }
+        }
+    }
+
+    (vertices, indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector4;
+
+    use super::*;
+    use crate::drawables::tiles::FLIP_HORIZONTAL_FLAG;
+
+    fn mesh_positions(tiles: &[Option<u32>], dimensions: [u32; 2]) -> Vec<[f32; 2]> {
+        let (vertices, _) = build_chunk_mesh(tiles, dimensions, [0, 0], dimensions, 1.0, |_| {
+            [[0.0; 2]; 4]
+        });
+        vertices.iter().map(|vertex| vertex.pos).collect()
+    }
+
+    #[test]
+    fn moving_a_group_leaves_the_mesh_untouched() {
+        let tiles = vec![Some(0), None, Some(1), Some(2)];
+        let mesh = mesh_positions(&tiles, [2, 2]);
+        assert_eq!(mesh.len(), 12);
+
+        // the mesh doesn't depend on where the group is, only the model matrix does
+        let moved = group_model([10.0, -4.0], 1.0);
+        let scaled = group_model([10.0, -4.0], 2.0);
+
+        let corner = Vector4::new(mesh[3][0], mesh[3][1], 0.0, 1.0);
+        assert_eq!(moved * corner, Vector4::new(11.0, -5.0, 0.0, 1.0));
+        assert_eq!(scaled * corner, Vector4::new(12.0, -6.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn chunks_outside_the_visible_area_are_culled() {
+        let chunk_size = CHUNK_SIZE as f32;
+        let first_chunk = Rect::new([0.0, -chunk_size], [chunk_size, 0.0]);
+        let far_chunk = Rect::new([10.0 * chunk_size, -chunk_size], [11.0 * chunk_size, 0.0]);
+        let visible_area = Rect::from_center([8.0, -8.0], [12.0, 8.0]);
+
+        let origin = [0.0, 0.0];
+        assert!(is_chunk_visible(first_chunk, origin, 1.0, &visible_area));
+        assert!(!is_chunk_visible(far_chunk, origin, 1.0, &visible_area));
+
+        // moving the group brings the far chunk into view and pushes the first one out
+        let position = [-10.0 * chunk_size, 0.0];
+        assert!(!is_chunk_visible(first_chunk, position, 1.0, &visible_area));
+        assert!(is_chunk_visible(far_chunk, position, 1.0, &visible_area));
+    }
+
+    #[test]
+    fn in_bounds_rejects_negative_and_too_large_positions() {
+        assert!(in_bounds([0, 0], [3, 2]));
+        assert!(in_bounds([2, 1], [3, 2]));
+        assert!(!in_bounds([-1, 0], [3, 2]));
+        assert!(!in_bounds([0, -1], [3, 2]));
+        assert!(!in_bounds([3, 0], [3, 2]));
+        assert!(!in_bounds([0, 2], [3, 2]));
+    }
+
+    #[test]
+    fn get_tile_strips_flip_flags() {
+        let tiles = vec![Some(5 | FLIP_HORIZONTAL_FLAG), None, Some(7)];
+        assert_eq!(sprite_index_at(&tiles, [3, 1], [0, 0]), Some(5));
+        assert_eq!(sprite_index_at(&tiles, [3, 1], [1, 0]), None);
+        assert_eq!(sprite_index_at(&tiles, [3, 1], [2, 0]), Some(7));
+        assert_eq!(sprite_index_at(&tiles, [3, 1], [3, 0]), None);
+    }
+
+    #[test]
+    fn world_to_tile_counts_rows_downwards_from_the_position() {
+        let position = [10.0, 5.0];
+        assert_eq!(
+            world_to_tile([10.5, 4.5], position, 1.0, [4, 4]),
+            Some([0, 0])
+        );
+        assert_eq!(
+            world_to_tile([13.5, 1.5], position, 1.0, [4, 4]),
+            Some([3, 3])
+        );
+        assert_eq!(world_to_tile([14.5, 4.5], position, 1.0, [4, 4]), None);
+        // above the top edge
+        assert_eq!(world_to_tile([10.5, 5.5], position, 1.0, [4, 4]), None);
+        // larger tiles cover more of the world
+        assert_eq!(
+            world_to_tile([13.5, 1.5], position, 2.0, [4, 4]),
+            Some([1, 1])
+        );
+    }
 }
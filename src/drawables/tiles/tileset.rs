@@ -1,5 +1,11 @@
 use core::f32;
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
 
 use cgmath::Vector2;
 use vulkano::{
@@ -8,13 +14,57 @@ use vulkano::{
 };
 
 use crate::graphics::{
-    bindable::{self, PushConstant, Texture, UniformBuffer},
+    bindable::{self, LoadError, PushConstant, Texture, UniformBuffer},
     camera::Camera,
     drawable::{DrawableEntry, GenericDrawable},
     shaders::{frag_textured, vert_textured},
     Graphics,
 };
 
+/// Set in a tile index to mirror the sprite horizontally, the same bit Tiled uses.
+pub const FLIP_HORIZONTAL_FLAG: u32 = 0x8000_0000;
+/// Set in a tile index to mirror the sprite vertically.
+pub const FLIP_VERTICAL_FLAG: u32 = 0x4000_0000;
+pub(super) const FLIP_FLAGS: u32 = FLIP_HORIZONTAL_FLAG | FLIP_VERTICAL_FLAG;
+
+/// Swaps the uvs of a quad in the order top left, top right, bottom left, bottom right,
+/// so one sprite can be drawn mirrored instead of needing a mirrored copy in the atlas.
+pub fn flip_uvs(uvs: [[f32; 2]; 4], flip_x: bool, flip_y: bool) -> [[f32; 2]; 4] {
+    let [top_left, top_right, bottom_left, bottom_right] = uvs;
+    match (flip_x, flip_y) {
+        (false, false) => uvs,
+        (true, false) => [top_right, top_left, bottom_right, bottom_left],
+        (false, true) => [bottom_left, bottom_right, top_left, top_right],
+        (true, true) => [bottom_right, bottom_left, top_right, top_left],
+    }
+}
+
+// The uvs of a sprite in the order top left, top right, bottom left, bottom right.
+// `atlas_width` is in tiles, the rest in pixels.
+fn sprite_uvs(
+    sprite_idx: u32,
+    atlas_width: u32,
+    tile_width: u32,
+    spacing: u32,
+    margin: u32,
+    texture_size: [f32; 2],
+    texel_inset: f32,
+) -> [[f32; 2]; 4] {
+    let y = sprite_idx / atlas_width;
+    let x = sprite_idx % atlas_width;
+
+    let tile_stride = tile_width + spacing;
+    let left_px = margin + x * tile_stride;
+    let top_px = margin + y * tile_stride;
+
+    let left = (left_px as f32 + texel_inset) / texture_size[0];
+    let right = ((left_px + tile_width) as f32 - texel_inset) / texture_size[0];
+    let top = (top_px as f32 + texel_inset) / texture_size[1];
+    let bottom = ((top_px + tile_width) as f32 - texel_inset) / texture_size[1];
+
+    [[left, top], [right, top], [left, bottom], [right, bottom]]
+}
+
 #[derive(Clone, Copy, Debug, Default)]
 pub struct AnimationDesc {
     pub length: u32,
@@ -25,42 +75,190 @@ pub struct AnimationDesc {
 pub struct TileSet {
     atlas: Arc<Texture>,
     pub tile_width: u32,
+    pub spacing: u32,
+    pub margin: u32,
     pub atlas_width: u32,
     pub atlas_height: u32,
+    // f32 bits, in texels
+    texel_inset: AtomicU32,
 }
 
 impl TileSet {
     pub fn new(gfx: &Graphics, sheet_texture: &str, tile_width: u32) -> Arc<Self> {
-        let atlas = Texture::new(gfx, sheet_texture, 0, true);
-        let atlas_dimensions = atlas.image.dimensions().width_height();
+        Self::with_spacing(gfx, sheet_texture, tile_width, 0, 0)
+    }
+
+    /// For atlases where the tiles are padded to avoid bleeding.
+    /// `spacing` is the gap in pixels between neighbouring tiles and `margin` the
+    /// border in pixels around the whole atlas, the same as in Tiled.
+    pub fn with_spacing(
+        gfx: &Graphics,
+        sheet_texture: &str,
+        tile_width: u32,
+        spacing: u32,
+        margin: u32,
+    ) -> Arc<Self> {
+        match Self::load(gfx, sheet_texture, tile_width, spacing, margin) {
+            Ok(tile_set) => tile_set,
+            Err(e) => panic!("Failed to load tile set {sheet_texture}: {e}"),
+        }
+    }
+
+    /// Same as `TileSet::with_spacing` but returns the error instead of panicking.
+    pub fn load(
+        gfx: &Graphics,
+        sheet_texture: &str,
+        tile_width: u32,
+        spacing: u32,
+        margin: u32,
+    ) -> Result<Arc<Self>, LoadError> {
+        if tile_width == 0 {
+            return Err(LoadError::Malformed("tile width of 0".to_string()));
+        }
 
-        let atlas_width = atlas_dimensions[0] / tile_width;
-        let atlas_height = atlas_dimensions[1] / tile_width;
+        let atlas = Texture::load(gfx, sheet_texture, 0, true)?;
+        let atlas_dimensions = atlas.get_image().dimensions().width_height();
 
-        Arc::new(Self {
+        // the last tile in a row or column has no spacing after it
+        let tile_stride = tile_width + spacing;
+        let atlas_width = (atlas_dimensions[0].saturating_sub(2 * margin) + spacing) / tile_stride;
+        let atlas_height = (atlas_dimensions[1].saturating_sub(2 * margin) + spacing) / tile_stride;
+
+        if atlas_width == 0 || atlas_height == 0 {
+            return Err(LoadError::Malformed(format!(
+                "{sheet_texture} is smaller than a single {tile_width}px tile"
+            )));
+        }
+
+        Ok(Arc::new(Self {
             atlas: atlas,
             tile_width: tile_width,
+            spacing: spacing,
+            margin: margin,
             atlas_width: atlas_width,
             atlas_height: atlas_height,
-        })
+            texel_inset: AtomicU32::new(0.0f32.to_bits()),
+        }))
     }
 
     pub fn get_uv_of_sprite(&self, sprite_idx: u32) -> [[f32; 2]; 4] {
-        let y = sprite_idx / self.atlas_width;
-        let x = sprite_idx % self.atlas_width;
+        let texture_size = self.atlas.get_image().dimensions().width_height();
+        sprite_uvs(
+            sprite_idx,
+            self.atlas_width,
+            self.tile_width,
+            self.spacing,
+            self.margin,
+            texture_size.map(|x| x as f32),
+            self.get_texel_inset(),
+        )
+    }
+
+    /// Same as `get_uv_of_sprite` but `tile_idx` can contain `FLIP_HORIZONTAL_FLAG` and `FLIP_VERTICAL_FLAG`.
+    pub fn get_flipped_uv_of_sprite(&self, tile_idx: u32) -> [[f32; 2]; 4] {
+        flip_uvs(
+            self.get_uv_of_sprite(tile_idx & !FLIP_FLAGS),
+            tile_idx & FLIP_HORIZONTAL_FLAG != 0,
+            tile_idx & FLIP_VERTICAL_FLAG != 0,
+        )
+    }
 
-        let uv_width = self.tile_width as f32 / self.atlas.image.dimensions().width() as f32;
-        let uv_height = self.tile_width as f32 / self.atlas.image.dimensions().height() as f32;
+    /// How far the uvs of `sprite_idx` are from the uvs of the first sprite.
+    /// Used by drawables that build their quad from the first sprite and offset it in the shader.
+    pub fn get_uv_offset_of_sprite(&self, sprite_idx: u32) -> [f32; 2] {
+        let first = self.get_uv_of_sprite(0)[0];
+        let sprite = self.get_uv_of_sprite(sprite_idx)[0];
+        [sprite[0] - first[0], sprite[1] - first[1]]
+    }
 
-        let left = (x) as f32 * uv_width;
-        let right = (x + 1) as f32 * uv_width;
-        let top = (y) as f32 * uv_height;
-        let bottom = (y + 1) as f32 * uv_height;
+    /// Moves the uvs of every sprite inwards by this many texels to stop neighbouring tiles from bleeding in
+    /// at fractional zoom levels. 0.5 samples the centers of the edge texels, 0 disables it.
+    /// Only affects drawables created afterwards.
+    pub fn set_texel_inset(&self, texel_inset: f32) {
+        self.texel_inset
+            .store(texel_inset.max(0.0).to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn get_texel_inset(&self) -> f32 {
+        f32::from_bits(self.texel_inset.load(Ordering::Relaxed))
+    }
 
-        [[left, top], [right, top], [left, bottom], [right, bottom]]
+    /// The distance in uv space between the left edges of two neighbouring tiles.
+    pub fn get_uv_stride(&self) -> f32 {
+        (self.tile_width + self.spacing) as f32 / self.atlas.get_image().dimensions().width() as f32
     }
 
     pub fn get_texture(&self) -> Arc<Texture> {
         self.atlas.clone()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn texel_inset_keeps_samples_inside_the_tile() {
+        // a 4x4 checkerboard of 8 pixel tiles, each tile a different color from its neighbours
+        let texture_size = [32.0, 32.0];
+        for sprite_idx in [0, 5, 15] {
+            let uvs = sprite_uvs(sprite_idx, 4, 8, 0, 0, texture_size, 0.5);
+            let texels = uvs.map(|uv| [uv[0] * texture_size[0], uv[1] * texture_size[1]]);
+
+            // the outermost samples land on the centers of the edge texels, so linear filtering
+            // at any zoom never mixes in the neighbouring tile
+            let left_px = (sprite_idx % 4 * 8) as f32;
+            let top_px = (sprite_idx / 4 * 8) as f32;
+            assert_eq!(texels[0], [left_px + 0.5, top_px + 0.5]);
+            assert_eq!(texels[3], [left_px + 7.5, top_px + 7.5]);
+        }
+
+        // without an inset neighbouring rows share their edge
+        let row_0 = sprite_uvs(0, 4, 8, 0, 0, texture_size, 0.0);
+        let row_1 = sprite_uvs(4, 4, 8, 0, 0, texture_size, 0.0);
+        assert_eq!(row_0[2][1], row_1[0][1]);
+        let row_0 = sprite_uvs(0, 4, 8, 0, 0, texture_size, 0.5);
+        let row_1 = sprite_uvs(4, 4, 8, 0, 0, texture_size, 0.5);
+        assert!(row_0[2][1] < row_1[0][1]);
+    }
+
+    #[test]
+    fn spacing_and_margin_are_skipped() {
+        // 2x2 tiles of 8 pixels with a 1 pixel gap between them and a 1 pixel border
+        let texture_size = [19.0, 19.0];
+        let uvs = |sprite_idx| sprite_uvs(sprite_idx, 2, 8, 1, 1, texture_size, 0.0);
+
+        assert_eq!(uvs(0)[0], [1.0 / 19.0, 1.0 / 19.0]);
+        assert_eq!(uvs(0)[3], [9.0 / 19.0, 9.0 / 19.0]);
+        assert_eq!(uvs(1)[0], [10.0 / 19.0, 1.0 / 19.0]);
+        assert_eq!(uvs(1)[3], [18.0 / 19.0, 9.0 / 19.0]);
+        assert_eq!(uvs(2)[0], [1.0 / 19.0, 10.0 / 19.0]);
+        assert_eq!(uvs(3)[3], [18.0 / 19.0, 18.0 / 19.0]);
+    }
+
+    #[test]
+    fn flipping_swaps_the_quad_uvs() {
+        let top_left = [0.0, 0.0];
+        let top_right = [1.0, 0.0];
+        let bottom_left = [0.0, 1.0];
+        let bottom_right = [1.0, 1.0];
+        let uvs = [top_left, top_right, bottom_left, bottom_right];
+
+        assert_eq!(flip_uvs(uvs, false, false), uvs);
+        assert_eq!(
+            flip_uvs(uvs, true, false),
+            [top_right, top_left, bottom_right, bottom_left]
+        );
+        assert_eq!(
+            flip_uvs(uvs, false, true),
+            [bottom_left, bottom_right, top_left, top_right]
+        );
+        assert_eq!(
+            flip_uvs(uvs, true, true),
+            [bottom_right, bottom_left, top_right, top_left]
+        );
+
+        // flipping twice gets the original back
+        assert_eq!(flip_uvs(flip_uvs(uvs, true, true), true, true), uvs);
+    }
+}
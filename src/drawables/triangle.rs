@@ -39,8 +39,14 @@ pub fn new(gfx: &mut Graphics, create_registered: bool) -> DrawableEntry {
             let indices: Vec<u32> = vec![0, 1, 2];
 
             vec![
-                bindable::VertexShader::from_module(vert_first::load(gfx.get_device()).unwrap()),
-                bindable::FragmentShader::from_module(frag_first::load(gfx.get_device()).unwrap()),
+                bindable::VertexShader::from_source(
+                    vert_first::load(gfx.get_device()),
+                    vert_first::SOURCE_PATH,
+                ),
+                bindable::FragmentShader::from_source(
+                    frag_first::load(gfx.get_device()),
+                    frag_first::SOURCE_PATH,
+                ),
                 bindable::IndexBuffer::new(&gfx, indices),
                 bindable::VertexBuffer::new(&gfx, vertices),
             ]
@@ -19,9 +19,9 @@ impl UboTestDrawable
         let uniform =
             bindable::UniformBuffer::new(gfx, 0, Ubo{ brightness: 1.0 }, ShaderStages::FRAGMENT);
 
-        let mut entry = GenericDrawable::new(&gfx, 0, || {
+        let mut entry = GenericDrawable::new(&gfx, || {
 
-            vec![ uniform.clone() ]
+            vec![ bindable::UniformBufferBinding::new(uniform.clone(), 0) ]
         }, || {
             #[derive(BufferContents, Vertex)]
             #[repr(C)]
@@ -41,8 +41,8 @@ impl UboTestDrawable
             ];
 
             vec![
-                bindable::VertexShader::from_module(vert_first::load(gfx.get_device()).unwrap()),
-                bindable::FragmentShader::from_module(frag_uniform_test::load(gfx.get_device()).unwrap()),
+                bindable::VertexShader::from_source(vert_first::load(gfx.get_device()), vert_first::SOURCE_PATH),
+                bindable::FragmentShader::from_source(frag_uniform_test::load(gfx.get_device()), frag_uniform_test::SOURCE_PATH),
                 bindable::IndexBuffer::new(&gfx, indices),
                 bindable::VertexBuffer::new(&gfx, vertices),
             ]
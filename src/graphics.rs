@@ -1,63 +1,102 @@
 pub mod bindable;
 pub mod camera;
+mod draw_order;
 pub mod drawable;
+#[cfg(feature = "hot-reload")]
+mod hot_reload;
+pub mod light;
+pub mod memory;
+mod persistent;
 pub mod pipeline;
+mod render_target;
+mod screenshot;
 pub mod shaders;
+pub mod ui;
 pub mod utils;
 
 use std::cell::UnsafeCell;
 use std::cmp::min;
 use std::collections::HashMap;
+use std::fmt::Display;
 use std::panic::Location;
-use std::sync::{Arc, OnceLock, Weak};
-use vulkano::command_buffer::allocator::StandardCommandBufferAlloc;
-use vulkano::command_buffer::{PrimaryAutoCommandBuffer, RenderPassBeginInfo};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+use vulkano::buffer::Subbuffer;
+use vulkano::command_buffer::{
+    ClearAttachment, ClearRect, CopyImageToBufferInfo, RenderPassBeginInfo,
+};
 use vulkano::descriptor_set::allocator::StandardDescriptorSetAllocator;
 use vulkano::format::{ClearValue, FormatFeatures};
-use vulkano::image::{AttachmentImage, ImageTiling};
-use vulkano::render_pass::SubpassDependency;
+use vulkano::image::{AttachmentImage, ImageAccess, ImageTiling};
+use vulkano::render_pass::{Subpass, SubpassDependency};
 
+use self::bindable::{
+    Bindable, CommandBufferBuilder, FlushPending, UniformBuffer, UniformBufferBinding,
+};
 use self::drawable::{Drawable, DrawableEntry, DrawableSharedPart, GenericDrawable};
+use self::shaders::vert_tile::CameraUbo;
+use self::memory::HostMemoryCounter;
+pub use self::persistent::DrawHandle;
+use self::persistent::PersistentDrawables;
+use self::render_target::RenderTarget;
 use vulkano::sync::{AccessFlags, PipelineStages};
 use vulkano::{
     command_buffer::{
-        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
+        allocator::{StandardCommandBufferAlloc, StandardCommandBufferAllocator},
+        AutoCommandBufferBuilder, CommandBufferUsage, PrimaryAutoCommandBuffer,
     },
     device::{
-        physical::{PhysicalDevice, PhysicalDeviceType},
-        Device, DeviceCreateInfo, DeviceExtensions, Features, Queue, QueueCreateInfo, QueueFlags,
+        physical::{PhysicalDevice, PhysicalDeviceError, PhysicalDeviceType},
+        Device, DeviceCreateInfo, DeviceCreationError, DeviceExtensions, Features, Queue,
+        QueueCreateInfo, QueueFlags,
     },
     format::Format,
     image::{
-        view::{ImageView, ImageViewCreateInfo},
+        view::{ImageView, ImageViewAbstract, ImageViewCreateInfo},
         ImageAspects, ImageLayout, ImageSubresourceRange, ImageUsage, SampleCount, SwapchainImage,
     },
     instance::{
         debug::{DebugUtilsMessenger, DebugUtilsMessengerCreateInfo, ValidationFeatureEnable},
-        Instance, InstanceCreateInfo, InstanceExtensions,
+        Instance, InstanceCreateInfo, InstanceCreationError, InstanceExtensions,
     },
     memory::allocator::StandardMemoryAllocator,
-    pipeline::graphics::viewport::Viewport,
+    pipeline::{
+        cache::PipelineCache,
+        graphics::{depth_stencil::CompareOp, viewport::Viewport},
+        GraphicsPipeline,
+    },
     render_pass::{
-        AttachmentDescription, AttachmentReference, Framebuffer, FramebufferCreateInfo, LoadOp,
-        RenderPass, RenderPassCreateInfo, StoreOp, SubpassDescription,
+        AttachmentDescription, AttachmentReference, Framebuffer, FramebufferCreateInfo,
+        FramebufferCreationError, LoadOp, RenderPass, RenderPassCreateInfo,
+        RenderPassCreationError, StoreOp, SubpassDescription,
     },
     sampler::ComponentMapping,
     swapchain::{
-        acquire_next_image, ColorSpace, CompositeAlpha, Surface, Swapchain, SwapchainCreateInfo,
-        SwapchainPresentInfo,
+        acquire_next_image, AcquireError, ColorSpace, CompositeAlpha, Surface, Swapchain,
+        SwapchainCreateInfo, SwapchainCreationError, SwapchainPresentInfo,
     },
     sync::{FlushError, GpuFuture, Sharing},
-    Version, VulkanLibrary,
+    LoadingError, OomError, Version, VulkanLibrary,
 };
 use vulkano_win::VkSurfaceBuild;
 use winit::{
     dpi::LogicalSize,
-    event_loop::EventLoop,
-    window::{Window, WindowBuilder},
+    event_loop::{EventLoop, EventLoopWindowTarget},
+    window::{Window, WindowBuilder, WindowId},
 };
 
-const IN_FLIGHT_COUNT: usize = 2;
+// How many frames the CPU can record ahead of the GPU, every window has a future per frame in flight.
+// This is independent of the swapchain image count, acquiring an image already waits
+// while every image is in use, so more images than frames in flight only add latency.
+const DEFAULT_IN_FLIGHT_COUNT: usize = 2;
+
+/// Where `Graphics::new` loads the pipeline cache from.
+pub const PIPELINE_CACHE_PATH: &str = "pipeline_cache.bin";
+
+// how often the files of loaded textures are checked for changes
+#[cfg(feature = "hot-reload")]
+const TEXTURE_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
 
 // If true MAILBOX will always be used if available.
 // If false FIFO will be preferred.
@@ -106,12 +145,163 @@ impl QueueIndices {
     }
 }
 
+/// Which end of the depth range is nearest to the camera.
+/// Reverse-Z spreads the precision of floating point depth buffers more evenly over the depth range.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DepthMode {
+    /// Depth is cleared to 1.0 and smaller values are closer.
+    Standard,
+    /// Depth is cleared to 0.0 and larger values are closer.
+    ReverseZ,
+}
+
+impl DepthMode {
+    pub fn clear_value(&self) -> f32 {
+        match self {
+            DepthMode::Standard => 1.0,
+            DepthMode::ReverseZ => 0.0,
+        }
+    }
+    pub fn compare_op(&self) -> CompareOp {
+        match self {
+            DepthMode::Standard => CompareOp::LessOrEqual,
+            DepthMode::ReverseZ => CompareOp::GreaterOrEqual,
+        }
+    }
+}
+
+/// Why `Graphics::new` could not set up rendering.
+#[derive(Debug)]
+pub enum GraphicsInitError {
+    /// Vulkan is not installed or the loader could not be found.
+    MissingLibrary(LoadingError),
+    Instance(InstanceCreationError),
+    /// The window or its surface could not be created.
+    SurfaceCreation(vulkano_win::CreationError),
+    /// No device supports dynamic rendering and presenting to the window.
+    NoSuitableDevice,
+    Device(DeviceCreationError),
+    /// The capabilities or formats of the window surface could not be read.
+    SurfaceQuery(PhysicalDeviceError),
+    /// The window surface offers nothing to create a swapchain with, says what is missing.
+    UnsupportedSurface(&'static str),
+    /// None of the depth formats is supported as an attachment.
+    NoDepthFormat,
+    PipelineCache(OomError),
+    RenderPass(RenderPassCreationError),
+    Swapchain(SwapchainCreationError),
+    Framebuffer(FramebufferError),
+}
+
+impl Display for GraphicsInitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GraphicsInitError::MissingLibrary(e) => write!(
+                f,
+                "Vulkan is not installed, updating the graphics driver usually fixes this ({e})"
+            ),
+            GraphicsInitError::Instance(e) => write!(f, "failed to initialize Vulkan: {e}"),
+            GraphicsInitError::SurfaceCreation(e) => write!(f, "failed to create the window: {e}"),
+            GraphicsInitError::NoSuitableDevice => write!(
+                f,
+                "no graphics card with Vulkan 1.3 or dynamic rendering support was found"
+            ),
+            GraphicsInitError::Device(e) => write!(f, "failed to open the graphics card: {e}"),
+            GraphicsInitError::SurfaceQuery(e) => {
+                write!(f, "failed to query the window surface: {e}")
+            }
+            GraphicsInitError::UnsupportedSurface(missing) => {
+                write!(f, "the window surface has {missing}")
+            }
+            GraphicsInitError::NoDepthFormat => {
+                write!(f, "the graphics card supports none of the depth formats")
+            }
+            GraphicsInitError::PipelineCache(e) => {
+                write!(f, "failed to create the pipeline cache: {e}")
+            }
+            GraphicsInitError::RenderPass(e) => write!(f, "failed to create the render pass: {e}"),
+            GraphicsInitError::Swapchain(e) => {
+                write!(f, "failed to create the swapchain for the window: {e}")
+            }
+            GraphicsInitError::Framebuffer(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for GraphicsInitError {}
+
+/// Why the framebuffers of a window could not be created.
+#[derive(Debug)]
+pub enum FramebufferError {
+    /// An attachment wasn't recreated along with the swapchain.
+    ExtentMismatch {
+        extent: [u32; 2],
+        color: [u32; 2],
+        depth: [u32; 2],
+    },
+    /// None of the depth formats is supported as an attachment.
+    NoDepthFormat,
+    Creation(FramebufferCreationError),
+}
+
+impl Display for FramebufferError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FramebufferError::ExtentMismatch {
+                extent,
+                color,
+                depth,
+            } => write!(
+                f,
+                "framebuffer attachments don't match the swapchain extent {extent:?}, \
+                color is {color:?} and depth is {depth:?}"
+            ),
+            FramebufferError::NoDepthFormat => {
+                write!(f, "the graphics card supports none of the depth formats")
+            }
+            FramebufferError::Creation(e) => write!(f, "failed to create a framebuffer: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for FramebufferError {}
+
+/// The descriptor set drawables bind their camera to.
+pub const CAMERA_SET: u32 = 2;
+
+/// What the last call to `Graphics::draw_frame` drew, summed over every window and viewport.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameStats {
+    /// One for every visible drawable, the world counts once per split screen viewport.
+    pub draw_calls: u32,
+    /// Counted as if every drawable was a triangle list. Indirect draws aren't counted.
+    pub triangles: u64,
+    /// Time spent recording and submitting, not counting the wait for the previous frame.
+    pub cpu_time: std::time::Duration,
+}
+
+impl FrameStats {
+    fn count_triangles(&mut self, index_count: u32, instance_count: u32) {
+        self.triangles += (index_count / 3) as u64 * instance_count as u64;
+    }
+}
+
+struct SplitViewport {
+    viewport: Viewport,
+    camera_buffer: Arc<UniformBuffer<CameraUbo>>,
+    camera: Arc<UniformBufferBinding<CameraUbo>>,
+}
+
+#[cfg(feature = "hot-reload")]
+struct TextureWatcher {
+    textures: Vec<Weak<bindable::Texture>>,
+    last_check: std::time::Instant,
+}
+
 pub struct Graphics {
     //library: Arc<VulkanLibrary>,
     //instance: Arc<Instance>,
     //debug_messenger: Option<DebugUtilsMessenger>,
-    surface: Arc<Surface>,
-    window: Arc<Window>,
     //physical_device: Arc<PhysicalDevice>,
     device: Arc<Device>,
     queues: Queues,
@@ -119,37 +309,77 @@ pub struct Graphics {
     allocator: StandardMemoryAllocator,
     cmd_allocator: StandardCommandBufferAllocator,
     descriptor_set_allocator: StandardDescriptorSetAllocator,
+    host_memory: Arc<HostMemoryCounter>,
+    pipeline_cache: Arc<PipelineCache>,
 
-    swapchain: Arc<Swapchain>,
     //swapchain_images: Vec<Arc<SwapchainImage>>,
     main_render_pass: Arc<RenderPass>,
+    // same as main_render_pass but keeps the previous contents of the color attachment
+    load_render_pass: Arc<RenderPass>,
+    clear_color_attachment: bool,
+    depth_mode: DepthMode,
+    msaa_samples: SampleCount,
     //depth_buffer: Vec<Arc<ImageView<AttachmentImage>>>,
-    framebuffers: Vec<Arc<Framebuffer>>,
+
+    // every window has its own swapchain, framebuffers and drawables
+    targets: HashMap<WindowId, RenderTarget>,
+    main_window_id: WindowId,
 
     shared_data_map: UnsafeCell<HashMap<Location<'static>, Weak<DrawableSharedPart>>>, // THIS SHOULD BE MOVED
-    registered_drawables: Vec<Weak<GenericDrawable>>, // THIS SHOULD BE MOVED
+    // lets call sites that build the same pipeline share it, keyed by PipelineBuilder::key
+    pipeline_map: Mutex<pipeline::PipelineMap<pipeline::PipelineKey, GraphicsPipeline>>,
 
     utils: OnceLock<utils::Utils>,
+    ui_renderer: ui::UiRenderer,
+    // screen space drawables for the current frame, drawn between the world and the ui
+    overlay_queue: Mutex<Vec<Arc<GenericDrawable>>>,
+    // owned by Graphics and drawn to the main window after the registered drawables
+    // with the layer they were added on
+    persistent_drawables: PersistentDrawables<(i32, Arc<GenericDrawable>)>,
+    // queued for the current frame, see add_light
+    lights: Mutex<Vec<light::Light>>,
+    split_viewports: Vec<SplitViewport>,
+    // uploaded once per frame instead of every time they are bound
+    uniform_buffers: Mutex<Vec<Weak<dyn FlushPending>>>,
+    // bits of an f32, see set_interpolation_alpha
+    interpolation_alpha: AtomicU32,
+    frame_stats: FrameStats,
+    // set by set_desired_image_count, None uses one more than the surface minimum
+    desired_image_count: Option<u32>,
+    // the main window is saved here after the next frame, see capture_screenshot
+    screenshot_path: Option<PathBuf>,
+
+    #[cfg(feature = "hot-reload")]
+    watched_textures: Mutex<TextureWatcher>,
 
-    main_command_buffer: Option<PrimaryAutoCommandBuffer<StandardCommandBufferAlloc>>,
-    futures: Vec<Option<Box<dyn GpuFuture>>>,
     inflight_index: u32,
-    framebuffer_index: u32,
+    in_flight_count: usize,
 }
 
 impl Graphics {
-    pub fn new() -> (Graphics, EventLoop<()>) {
-        let library = VulkanLibrary::new().expect("Vulkan library is not installed.");
+    pub fn new() -> Result<(Graphics, EventLoop<()>), GraphicsInitError> {
+        Self::with_in_flight_count(DEFAULT_IN_FLIGHT_COUNT)
+    }
 
-        let instance = create_instance(library.clone());
+    /// Like `new` but lets the CPU record up to `in_flight_count` frames ahead of the GPU.
+    /// More frames in flight smooth out uneven frame times at the cost of latency.
+    /// It can't be changed later, every `UniformBuffer` keeps one subbuffer per frame in flight.
+    pub fn with_in_flight_count(
+        in_flight_count: usize,
+    ) -> Result<(Graphics, EventLoop<()>), GraphicsInitError> {
+        let in_flight_count = in_flight_count.max(1);
+
+        let library = VulkanLibrary::new().map_err(GraphicsInitError::MissingLibrary)?;
+
+        let instance = create_instance(library.clone())?;
 
         //let debug_messenger = create_debug_messenger(instance.clone());
 
-        let (event_loop, surface) = create_window(instance.clone());
+        let (event_loop, surface) = create_window(instance.clone())?;
 
-        let physical_device = create_physical_device(instance.clone(), surface.clone());
+        let physical_device = create_physical_device(instance.clone(), surface.clone())?;
 
-        let (device, queues) = create_logical_device(physical_device.clone(), surface.clone());
+        let (device, queues) = create_logical_device(physical_device.clone(), surface.clone())?;
 
         let memory_allocator = StandardMemoryAllocator::new_default(device.clone());
 
@@ -157,36 +387,43 @@ impl Graphics {
 
         let descriptor_set_allocator = StandardDescriptorSetAllocator::new(device.clone());
 
-        let (swapchain, swapchain_images) = create_swapchain(device.clone(), surface.clone());
-
-        println!("Swapchain is using {:?} images.", swapchain.image_count());
+        let pipeline_cache = load_pipeline_cache(device.clone(), PIPELINE_CACHE_PATH)?;
 
-        let swapchain_image_views = create_image_views(&swapchain_images, swapchain.clone());
+        let swapchain_format = choose_surface_format(device.clone(), surface.clone())?.0;
+        let depth_format =
+            find_depth_format(device.clone()).ok_or(GraphicsInitError::NoDepthFormat)?;
 
-        let (depth_buffers, depth_format) =
-            create_depth_buffer(device.clone(), swapchain.clone(), &memory_allocator);
-
-        let main_render_pass =
-            create_main_render_pass(device.clone(), swapchain.image_format(), depth_format);
+        let main_render_pass = create_main_render_pass(
+            device.clone(),
+            swapchain_format,
+            depth_format,
+            LoadOp::Clear,
+            SampleCount::Sample1,
+        )
+        .map_err(GraphicsInitError::RenderPass)?;
+        let load_render_pass = create_main_render_pass(
+            device.clone(),
+            swapchain_format,
+            depth_format,
+            LoadOp::Load,
+            SampleCount::Sample1,
+        )
+        .map_err(GraphicsInitError::RenderPass)?;
 
-        let framebuffers = create_framebuffers(
-            &swapchain_image_views,
+        let main_target = RenderTarget::new(
+            device.clone(),
+            &memory_allocator,
+            surface,
             main_render_pass.clone(),
-            &depth_buffers,
-        );
-
-        let mut futures = Vec::with_capacity(IN_FLIGHT_COUNT);
-        futures.resize_with(IN_FLIGHT_COUNT, || None);
-
-        let window = surface.object().unwrap().clone().downcast().unwrap();
+            in_flight_count,
+        )?;
+        let main_window_id = main_target.window.id();
 
         #[allow(unused_mut)]
         let mut gfx = Graphics {
             //library: library,
             //instance: instance,
             //debug_messenger: None,
-            surface: surface,
-            window: window,
             //physical_device: physical_device,
             device: device,
             queues: queues,
@@ -194,26 +431,47 @@ impl Graphics {
             allocator: memory_allocator,
             cmd_allocator: cmd_allocator,
             descriptor_set_allocator: descriptor_set_allocator,
+            host_memory: HostMemoryCounter::new(),
+            pipeline_cache: pipeline_cache,
 
-            swapchain: swapchain,
             //swapchain_images: swapchain_images,
             main_render_pass: main_render_pass,
-            framebuffers: framebuffers,
+            load_render_pass: load_render_pass,
+            clear_color_attachment: true,
+            depth_mode: DepthMode::Standard,
+            msaa_samples: SampleCount::Sample1,
+
+            targets: HashMap::from([(main_window_id, main_target)]),
+            main_window_id: main_window_id,
 
             shared_data_map: UnsafeCell::new(HashMap::new()),
-            registered_drawables: Vec::new(),
+            pipeline_map: Mutex::new(pipeline::PipelineMap::new()),
 
             utils: OnceLock::new(),
+            ui_renderer: ui::UiRenderer::new(),
+            overlay_queue: Mutex::new(Vec::new()),
+            persistent_drawables: PersistentDrawables::new(),
+            lights: Mutex::new(Vec::new()),
+            split_viewports: Vec::new(),
+            uniform_buffers: Mutex::new(Vec::new()),
+            interpolation_alpha: AtomicU32::new(0.0f32.to_bits()),
+            frame_stats: FrameStats::default(),
+            desired_image_count: None,
+            screenshot_path: None,
+
+            #[cfg(feature = "hot-reload")]
+            watched_textures: Mutex::new(TextureWatcher {
+                textures: Vec::new(),
+                last_check: std::time::Instant::now(),
+            }),
 
-            main_command_buffer: None,
-            futures: futures,
             inflight_index: 0,
-            framebuffer_index: 0,
+            in_flight_count: in_flight_count,
         };
 
         _ = gfx.utils.set(utils::Utils::new(&gfx));
 
-        (gfx, event_loop)
+        Ok((gfx, event_loop))
     }
 
     pub fn get_device(&self) -> Arc<Device> {
@@ -222,20 +480,162 @@ impl Graphics {
     pub fn get_main_render_pass(&self) -> Arc<RenderPass> {
         self.main_render_pass.clone()
     }
+    /// Whether the color attachment is cleared to black at the start of every frame.
+    /// Disable it when something drawn first, like a full screen background, covers the whole screen.
+    pub fn set_clear_color_attachment(&mut self, clear: bool) {
+        self.clear_color_attachment = clear;
+    }
+    /// Pipelines read the depth mode when they are built,
+    /// so it has to be set before any drawables that test depth are created.
+    pub fn set_depth_mode(&mut self, depth_mode: DepthMode) {
+        self.depth_mode = depth_mode;
+    }
+    pub fn get_depth_mode(&self) -> DepthMode {
+        self.depth_mode
+    }
+
+    /// Multisamples the color and depth attachments to smooth out the edges of everything drawn.
+    /// Falls back to `SampleCount::Sample1` if the device doesn't support `samples`.
+    /// The render passes, framebuffers and every cached pipeline are rebuilt, so this is slow.
+    pub fn set_msaa(&mut self, samples: SampleCount) {
+        let properties = self.device.physical_device().properties();
+        let supported = properties
+            .framebuffer_color_sample_counts
+            .intersection(properties.framebuffer_depth_sample_counts);
+        let samples = match supported.contains_enum(samples) {
+            true => samples,
+            false => {
+                println!("[WARN] {samples:?} multisampling isn't supported, disabling it.");
+                SampleCount::Sample1
+            }
+        };
+
+        if samples == self.msaa_samples {
+            return;
+        }
+
+        let swapchain_format = self.get_swapchain_format();
+        // Graphics::new already failed if there is none
+        let depth_format = match find_depth_format(self.device.clone()) {
+            Some(depth_format) => depth_format,
+            None => return,
+        };
+        let render_passes = create_main_render_pass(
+            self.device.clone(),
+            swapchain_format,
+            depth_format,
+            LoadOp::Clear,
+            samples,
+        )
+        .and_then(|main_render_pass| {
+            let load_render_pass = create_main_render_pass(
+                self.device.clone(),
+                swapchain_format,
+                depth_format,
+                LoadOp::Load,
+                samples,
+            )?;
+            Ok((main_render_pass, load_render_pass))
+        });
+        let (main_render_pass, load_render_pass) = match render_passes {
+            Ok(render_passes) => render_passes,
+            Err(e) => {
+                println!("Failed to create render passes for {samples:?} multisampling! {e}");
+                return;
+            }
+        };
+
+        for target in self.targets.values_mut() {
+            target.wait_idle();
+        }
+        self.msaa_samples = samples;
+        self.main_render_pass = main_render_pass;
+        self.load_render_pass = load_render_pass;
+
+        let window_ids: Vec<WindowId> = self.targets.keys().copied().collect();
+        for window_id in window_ids {
+            self.recreate_swapchain(window_id);
+        }
+
+        // pipelines are made for one render pass and sample count
+        let subpass = Subpass::from(self.main_render_pass.clone(), 0).unwrap();
+        for shared_part in self
+            .get_shared_data_map()
+            .values()
+            .filter_map(|weak| weak.upgrade())
+        {
+            shared_part.rebuild_for_subpass(self.device.clone(), subpass.clone(), samples);
+        }
+        match self.pipeline_map.lock() {
+            Ok(mut pipeline_map) => pipeline_map.clear(),
+            Err(e) => println!("Failed to access pipeline map {e}"),
+        }
+    }
+    pub fn get_msaa_samples(&self) -> SampleCount {
+        self.msaa_samples
+    }
+    /// How far between the last game tick and the next one the frame being drawn is, in the range `0.0..1.0`.
+    /// Set by the app every frame so drawables can interpolate between tick states.
+    pub fn set_interpolation_alpha(&self, alpha: f32) {
+        self.interpolation_alpha
+            .store(alpha.to_bits(), Ordering::Relaxed);
+    }
+    pub fn get_interpolation_alpha(&self) -> f32 {
+        f32::from_bits(self.interpolation_alpha.load(Ordering::Relaxed))
+    }
     pub fn get_allocator(&self) -> &StandardMemoryAllocator {
         &self.allocator
     }
+    /// Tracks the host visible memory used by uniform and staging buffers.
+    pub fn get_host_memory(&self) -> &Arc<HostMemoryCounter> {
+        &self.host_memory
+    }
+    pub fn get_pipeline_cache(&self) -> Arc<PipelineCache> {
+        self.pipeline_cache.clone()
+    }
+    /// Writes every pipeline built so far to `path`, so the next run can load them from there
+    /// instead of compiling the shaders again. How much faster startup gets depends on the driver,
+    /// it is most noticeable with many pipelines and on drivers without a cache of their own.
+    pub fn save_pipeline_cache(&self, path: &str) -> std::io::Result<()> {
+        let data = self
+            .pipeline_cache
+            .get_data()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?;
+        std::fs::write(path, data)
+    }
     pub fn get_shared_data_map(&self) -> &HashMap<Location<'static>, Weak<DrawableSharedPart>> {
         unsafe { &self.shared_data_map.get().as_ref().unwrap() }
     }
     pub fn get_swapchain_format(&self) -> Format {
-        self.swapchain.image_format()
+        self.get_main_target().swapchain.image_format()
     }
     pub fn get_descriptor_set_allocator(&self) -> &StandardDescriptorSetAllocator {
         &self.descriptor_set_allocator
     }
+    /// The window that was opened together with `Graphics`.
     pub fn get_window(&self) -> Arc<Window> {
-        self.window.clone()
+        self.get_main_target().window.clone()
+    }
+    /// How many physical pixels one logical pixel covers on the main window.
+    pub fn get_scale_factor(&self) -> f64 {
+        self.get_window().scale_factor()
+    }
+    /// The size of the main window in logical pixels, the unit of `cartesian_to_normalized`.
+    /// Things sized in logical pixels keep their physical size on high DPI screens.
+    pub fn get_logical_extent(&self) -> [f32; 2] {
+        let window = self.get_window();
+        utils::logical_extent(window.inner_size(), window.scale_factor())
+    }
+    pub fn get_window_by_id(&self, window_id: WindowId) -> Option<Arc<Window>> {
+        self.targets
+            .get(&window_id)
+            .map(|target| target.window.clone())
+    }
+    pub fn is_main_window(&self, window_id: WindowId) -> bool {
+        self.main_window_id == window_id
+    }
+    fn get_main_target(&self) -> &RenderTarget {
+        self.targets.get(&self.main_window_id).unwrap()
     }
     pub fn graphics_queue(&self) -> Arc<Queue> {
         self.queues.graphics_queue.clone().unwrap()
@@ -243,8 +643,8 @@ impl Graphics {
     pub fn get_cmd_allocator(&self) -> &StandardCommandBufferAllocator {
         &self.cmd_allocator
     }
-    pub const fn get_in_flight_count(&self) -> usize {
-        IN_FLIGHT_COUNT
+    pub fn get_in_flight_count(&self) -> usize {
+        self.in_flight_count
     }
     pub fn get_in_flight_index(&self) -> usize {
         self.inflight_index as usize
@@ -252,8 +652,46 @@ impl Graphics {
     pub fn get_utils(&self) -> &utils::Utils {
         self.utils.get().unwrap()
     }
+    /// Drawables queued here are drawn to the main window for one frame only.
+    pub fn ui(&self) -> &ui::UiRenderer {
+        &self.ui_renderer
+    }
+    /// Queues the drawable to be drawn to the main window for one frame, after the world with the
+    /// depth buffer cleared and before the UI. Use it for screen space drawables like the HUD.
+    /// Overlay drawables are drawn in queue order and don't have to be registered.
+    pub fn queue_overlay(&self, drawable_entry: &DrawableEntry) {
+        match self.overlay_queue.lock() {
+            Ok(mut guard) => guard.push(drawable_entry.get_arc()),
+            Err(e) => println!("Failed to access overlay queue {e}"),
+        }
+    }
+    /// Queues a light for one frame, queue lights every tick like UI.
+    /// A `LightOverlay` has to be updated after the lights are queued for them to show up.
+    pub fn add_light(&self, light: light::Light) {
+        match self.lights.lock() {
+            Ok(mut guard) => guard.push(light),
+            Err(e) => println!("Failed to access lights {e}"),
+        }
+    }
+    /// The lights queued for the current frame, in queue order.
+    pub fn get_lights(&self) -> Vec<light::Light> {
+        match self.lights.lock() {
+            Ok(guard) => guard.clone(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Records the frame into the framebuffer of the acquired swapchain image. The image is only
+    /// known after acquiring, so the command buffer has to be recorded for every frame.
+    fn record_command_buffer(
+        &self,
+        window_id: WindowId,
+        framebuffer: Arc<Framebuffer>,
+        stats: &mut FrameStats,
+        screenshot_buffer: Option<Subbuffer<[u8]>>,
+    ) -> PrimaryAutoCommandBuffer<StandardCommandBufferAlloc> {
+        let target = &self.targets[&window_id];
 
-    pub fn recreate_command_buffer(&mut self) {
         let mut builder = AutoCommandBufferBuilder::primary(
             &self.cmd_allocator,
             self.queues
@@ -267,164 +705,662 @@ impl Graphics {
 
         let viewport = Viewport {
             origin: [0.0, 0.0],
-            dimensions: self.swapchain.image_extent().map(|int| int as f32),
+            dimensions: target.swapchain.image_extent().map(|int| int as f32),
             depth_range: 0.0..1.0,
         };
 
+        // the two render passes are compatible so the framebuffers and pipelines work with both
+        let (render_pass, color_clear_value) = match self.clear_color_attachment {
+            true => (
+                self.main_render_pass.clone(),
+                Some(ClearValue::Float([0.0, 0.0, 0.0, 1.0])),
+            ),
+            false => (self.load_render_pass.clone(), None),
+        };
+
+        let mut clear_values = vec![
+            color_clear_value,
+            Some(ClearValue::Depth(self.depth_mode.clear_value())),
+        ];
+        // the resolve attachment is overwritten entirely
+        if self.msaa_samples != SampleCount::Sample1 {
+            clear_values.push(None);
+        }
+
         builder
             .begin_render_pass(
                 RenderPassBeginInfo {
-                    render_pass: self.main_render_pass.clone(),
-                    clear_values: vec![
-                        Some(ClearValue::Float([0.0, 0.0, 0.0, 1.0])),
-                        Some(ClearValue::Depth(1.0)),
-                    ],
-                    ..RenderPassBeginInfo::framebuffer(
-                        self.framebuffers[self.framebuffer_index as usize].clone(),
-                    )
+                    render_pass: render_pass,
+                    clear_values: clear_values,
+                    ..RenderPassBeginInfo::framebuffer(framebuffer.clone())
                 },
                 vulkano::command_buffer::SubpassContents::Inline,
             )
-            .unwrap()
-            .set_viewport(0, [viewport.clone()]);
+            .unwrap();
+
+        let persistent = match self.is_main_window(window_id) {
+            true => self.persistent_drawables.visible().cloned().collect(),
+            false => Vec::new(),
+        };
+        let world =
+            draw_order::visible_world(&target.registered_drawables, persistent, |p| p.is_visible());
+
+        let (mut overlay, mut ui_elements) = match self.is_main_window(window_id) {
+            true => (
+                self.overlay_queue
+                    .lock()
+                    .map(|guard| guard.clone())
+                    .unwrap_or_default(),
+                self.ui_renderer.elements(),
+            ),
+            false => (Vec::new(), Vec::new()),
+        };
+        overlay.retain(|p| p.is_visible());
+        ui_elements.retain(|p| p.is_visible());
+
+        // split screen only applies to the world of the main window
+        let split_screen = self.is_main_window(window_id) && !self.split_viewports.is_empty();
+        let world_viewports = match split_screen {
+            true => self.split_viewports.len(),
+            false => 1,
+        };
 
-        for drawable in self.registered_drawables.iter().filter_map(|p| p.upgrade()) {
-            for bindable in drawable.get_bindables() {
-                bindable.bind(&self, &mut builder, drawable.get_pipeline_layout());
+        let mut depth_cleared = false;
+        let passes = draw_order::frame_draw_order(world, overlay, ui_elements);
+        stats.draw_calls += draw_order::draw_call_count(&passes, world_viewports);
+        for (pass, drawables) in passes {
+            // cleared once so nothing drawn in screen space is hidden behind the world
+            if pass.is_screen_space() && !depth_cleared {
+                builder
+                    .clear_attachments(
+                        [ClearAttachment::Depth(self.depth_mode.clear_value())],
+                        [ClearRect {
+                            offset: [0, 0],
+                            extent: target.swapchain.image_extent(),
+                            array_layers: 0..1,
+                        }],
+                    )
+                    .unwrap();
+                depth_cleared = true;
             }
 
-            for bindable in drawable.get_shared_bindables() {
-                bindable.bind(&self, &mut builder, drawable.get_pipeline_layout());
+            match pass == draw_order::DrawPass::World && split_screen {
+                true => {
+                    for split_viewport in &self.split_viewports {
+                        builder.set_viewport(0, [split_viewport.viewport.clone()]);
+                        for drawable in &drawables {
+                            self.record_drawable(
+                                &mut builder,
+                                drawable,
+                                Some(split_viewport),
+                                stats,
+                            );
+                        }
+                    }
+                }
+                false => {
+                    builder.set_viewport(0, [viewport.clone()]);
+                    for drawable in &drawables {
+                        self.record_drawable(&mut builder, drawable, None, stats);
+                    }
+                }
             }
+        }
 
-            builder.bind_pipeline_graphics(drawable.get_pipeline());
+        builder.end_render_pass().unwrap();
+
+        if let Some(screenshot_buffer) = screenshot_buffer {
+            let attachment_index =
+                swapchain_attachment_index(self.msaa_samples != SampleCount::Sample1);
+            let swapchain_image = framebuffer.attachments()[attachment_index].image();
             builder
-                .draw_indexed(drawable.get_index_count(), 1, 0, 0, 0)
+                .copy_image_to_buffer(CopyImageToBufferInfo::image_buffer(
+                    swapchain_image,
+                    screenshot_buffer,
+                ))
                 .unwrap();
         }
 
-        builder.end_render_pass().unwrap();
-        self.main_command_buffer = Some(builder.build().unwrap());
+        builder.build().unwrap()
     }
 
+    /// The camera of `split_viewport` replaces the one drawables bound at `CAMERA_SET`.
+    fn record_drawable(
+        &self,
+        builder: &mut CommandBufferBuilder,
+        drawable: &Arc<GenericDrawable>,
+        split_viewport: Option<&SplitViewport>,
+        stats: &mut FrameStats,
+    ) {
+        // the pipeline is bound first so bindables can set its dynamic state
+        builder.bind_pipeline_graphics(drawable.get_pipeline());
+
+        for bindable in drawable.get_bindables() {
+            bindable.bind(&self, builder, drawable.get_pipeline_layout());
+        }
+
+        for bindable in drawable.get_shared_bindables() {
+            bindable.bind(&self, builder, drawable.get_pipeline_layout());
+        }
+
+        if let Some(split_viewport) = split_viewport {
+            let pipeline_layout = drawable.get_pipeline_layout();
+            let uses_camera = pipeline_layout
+                .set_layouts()
+                .get(CAMERA_SET as usize)
+                .map_or(false, |set_layout| {
+                    set_layout.is_compatible_with(split_viewport.camera_buffer.get_layout())
+                });
+            if uses_camera {
+                split_viewport.camera.bind(&self, builder, pipeline_layout);
+            }
+        }
+
+        match drawable.get_indirect_buffer() {
+            // the index and instance counts are only known on the gpu
+            Some(indirect_buffer) => {
+                builder.draw_indexed_indirect(indirect_buffer).unwrap();
+            }
+            None => {
+                stats.count_triangles(drawable.get_index_count(), drawable.get_instance_count());
+                builder
+                    .draw_indexed(
+                        drawable.get_index_count(),
+                        drawable.get_instance_count(),
+                        0,
+                        0,
+                        0,
+                    )
+                    .unwrap();
+            }
+        }
+    }
+
+    /// Draws the scene of the main window once per viewport, each time with its own camera,
+    /// for split screen. The camera replaces whatever camera drawables bound at `CAMERA_SET`.
+    /// An empty slice goes back to a single viewport covering the window.
+    /// Culling, like `StaticTileGroup::cull`, only knows about one camera and should be disabled.
+    pub fn set_viewports(&mut self, viewports: &[(Viewport, Arc<UniformBuffer<CameraUbo>>)]) {
+        self.split_viewports = viewports
+            .iter()
+            .map(|(viewport, camera)| SplitViewport {
+                viewport: viewport.clone(),
+                camera_buffer: camera.clone(),
+                camera: UniformBufferBinding::new(camera.clone(), CAMERA_SET),
+            })
+            .collect();
+    }
+
+    /// Draws a frame to every window that isn't minimized.
     pub fn draw_frame(&mut self) {
-        if let Some(last_frame_future) = self.futures[self.inflight_index as usize].take() {
+        let window_ids: Vec<WindowId> = self
+            .targets
+            .iter()
+            .filter(|(_, target)| target.is_drawable())
+            .map(|(window_id, _)| *window_id)
+            .collect();
+
+        // the subbuffers of this in flight index can only be written once their last frame is done
+        for window_id in &window_ids {
+            self.wait_for_frame(*window_id);
+        }
+        let start = std::time::Instant::now();
+        self.flush_uniform_buffers();
+
+        let mut stats = FrameStats::default();
+        for window_id in window_ids {
+            self.draw_frame_to(window_id, &mut stats);
+        }
+        self.ui_renderer.clear();
+        if let Ok(mut guard) = self.overlay_queue.lock() {
+            guard.clear();
+        }
+        if let Ok(mut guard) = self.lights.lock() {
+            guard.clear();
+        }
+
+        stats.cpu_time = start.elapsed();
+        self.frame_stats = stats;
+
+        self.inflight_index = next_in_flight_index(self.inflight_index, self.in_flight_count);
+    }
+
+    pub fn get_frame_stats(&self) -> FrameStats {
+        self.frame_stats
+    }
+
+    /// Saves what the main window shows after the next frame as a png.
+    /// The frame is waited for before the file is written, so it takes a little longer than usual.
+    pub fn capture_screenshot(&mut self, path: &Path) {
+        let swapchain = &self.targets[&self.main_window_id].swapchain;
+        if !swapchain.image_usage().intersects(ImageUsage::TRANSFER_SRC) {
+            println!("The window surface doesn't support copying from its images, can't take a screenshot.");
+            return;
+        }
+        self.screenshot_path = Some(path.to_path_buf());
+    }
+
+    /// Waits until the last frame that used the current in flight index on this window is done.
+    fn wait_for_frame(&mut self, window_id: WindowId) {
+        let inflight_index = self.inflight_index as usize;
+        let target = self.targets.get_mut(&window_id).unwrap();
+
+        if let Some(last_frame_future) = target.futures[inflight_index].take() {
             match last_frame_future.then_signal_fence_and_flush() {
                 Ok(mut future) => {
                     future.wait(None).unwrap();
                     future.cleanup_finished();
                 }
                 Err(FlushError::OutOfDate) => {
-                    self.recreate_swapchain();
+                    self.recreate_swapchain(window_id);
                 }
                 Err(e) => {
                     println!("failed to flush future: {e}");
                 }
             };
         }
+    }
 
-        let (image_index, suboptimal, acquire_future) =
-            acquire_next_image(self.swapchain.clone(), None).unwrap();
+    fn flush_uniform_buffers(&self) {
+        match self.uniform_buffers.lock() {
+            Ok(mut uniform_buffers) => {
+                let inflight_index = self.inflight_index as usize;
+                uniform_buffers.retain(|weak| match weak.upgrade() {
+                    Some(uniform_buffer) => {
+                        uniform_buffer.flush_pending(inflight_index);
+                        true
+                    }
+                    None => false,
+                });
+            }
+            Err(e) => println!("Failed to access uniform buffers {e}"),
+        }
+    }
 
-        self.framebuffer_index = image_index;
+    fn draw_frame_to(&mut self, window_id: WindowId, stats: &mut FrameStats) {
+        let inflight_index = self.inflight_index as usize;
+        let target = self.targets.get_mut(&window_id).unwrap();
+        // The swapchain goes out of date while the window is being resized. The frame is skipped
+        // and the next one is drawn to the recreated swapchain.
+        let (image_index, suboptimal, acquire_future) =
+            match acquire_next_image(target.swapchain.clone(), None) {
+                Ok(acquired) => acquired,
+                Err(AcquireError::OutOfDate) => {
+                    self.recreate_swapchain(window_id);
+                    return;
+                }
+                Err(e) => {
+                    println!("failed to acquire next image: {e}");
+                    return;
+                }
+            };
+        let framebuffer = match acquired_framebuffer(&target.framebuffers, image_index) {
+            Some(framebuffer) => framebuffer.clone(),
+            None => {
+                println!("acquired image {image_index} has no framebuffer");
+                self.recreate_swapchain(window_id);
+                return;
+            }
+        };
 
-        match self.main_command_buffer.as_ref() {
-            None => self.recreate_command_buffer(),
-            _ => {}
+        let screenshot = match self.is_main_window(window_id) {
+            true => self.screenshot_path.take().map(|path| {
+                let extent = self.targets[&window_id].swapchain.image_extent();
+                let buffer = screenshot::create_screenshot_buffer(&self.allocator, extent);
+                (path, extent, buffer)
+            }),
+            false => None,
         };
 
+        let command_buffer = self.record_command_buffer(
+            window_id,
+            framebuffer,
+            stats,
+            screenshot.as_ref().map(|(_, _, buffer)| buffer.clone()),
+        );
+
+        let target = self.targets.get_mut(&window_id).unwrap();
         let new_future = acquire_future
-            .then_execute(
-                self.queues.graphics_queue.clone().unwrap(),
-                self.main_command_buffer.take().unwrap(),
-            )
+            .then_execute(self.queues.graphics_queue.clone().unwrap(), command_buffer)
             .unwrap()
             .then_swapchain_present(
                 self.queues.graphics_queue.clone().unwrap(),
-                SwapchainPresentInfo::swapchain_image_index(self.swapchain.clone(), image_index),
+                SwapchainPresentInfo::swapchain_image_index(target.swapchain.clone(), image_index),
             );
 
-        self.futures[self.inflight_index as usize] = Some(new_future.boxed());
+        match screenshot {
+            // the buffer can only be read once the frame is done, so this frame is waited for right away
+            Some((path, extent, buffer)) => {
+                match new_future.then_signal_fence_and_flush() {
+                    Ok(future) => {
+                        future.wait(None).unwrap();
+                        let result = screenshot::save_screenshot(
+                            &path,
+                            extent,
+                            target.swapchain.image_format(),
+                            &buffer.read().unwrap(),
+                        );
+                        match result {
+                            Ok(()) => println!("Saved screenshot to {}.", path.display()),
+                            Err(e) => println!("Failed to save screenshot: {e}"),
+                        }
+                    }
+                    Err(e) => println!("failed to flush future: {e}"),
+                }
+                target.futures[inflight_index] = None;
+            }
+            None => target.futures[inflight_index] = Some(new_future.boxed()),
+        }
 
         if suboptimal {
-            self.recreate_swapchain();
+            self.recreate_swapchain(window_id);
         }
-        self.inflight_index = (self.inflight_index + 1) % IN_FLIGHT_COUNT as u32;
     }
 
+    /// Opens another window that shares the device, render passes and cached pipelines with the main window.
+    /// Drawables are only drawn to it once registered with `register_drawable_to`.
+    pub fn open_window(
+        &mut self,
+        event_loop: &EventLoopWindowTarget<()>,
+        title: &str,
+        size: [u32; 2],
+    ) -> Option<WindowId> {
+        let surface = match WindowBuilder::new()
+            .with_inner_size(LogicalSize::new(size[0], size[1]))
+            .with_resizable(true)
+            .with_title(title)
+            .build_vk_surface(event_loop, self.device.instance().clone())
+        {
+            Ok(surface) => surface,
+            Err(e) => {
+                println!("Failed to create window surface! {e}");
+                return None;
+            }
+        };
+
+        let indices = find_queue_indices(self.device.physical_device().clone(), surface.clone());
+        if indices.present_queue.is_none() {
+            println!("The new window can't be presented to by the current device.");
+            return None;
+        }
+
+        // every swapchain has to use the format the render passes were made for
+        let surface_format = match choose_surface_format(self.device.clone(), surface.clone()) {
+            Ok((surface_format, _)) => surface_format,
+            Err(e) => {
+                println!("Failed to choose a format for the new window! {e}");
+                return None;
+            }
+        };
+        if surface_format != self.get_swapchain_format() {
+            println!("The new window doesn't support the swapchain format {surface_format:?}.");
+            return None;
+        }
+
+        let mut target = match RenderTarget::new(
+            self.device.clone(),
+            &self.allocator,
+            surface,
+            self.main_render_pass.clone(),
+            self.in_flight_count,
+        ) {
+            Ok(target) => target,
+            Err(e) => {
+                println!("Failed to create the render target for the new window! {e}");
+                return None;
+            }
+        };
+        if let Some(image_count) = self.desired_image_count {
+            target.set_min_image_count(
+                self.device.clone(),
+                &self.allocator,
+                self.main_render_pass.clone(),
+                image_count,
+            );
+        }
+
+        let window_id = target.window.id();
+        self.targets.insert(window_id, target);
+
+        Some(window_id)
+    }
+
+    /// Closes a window opened with `open_window`. The main window can't be closed.
+    pub fn close_window(&mut self, window_id: WindowId) {
+        if self.is_main_window(window_id) {
+            println!("[WARN] Tried to close the main window.");
+            return;
+        }
+
+        if let Some(mut target) = self.targets.remove(&window_id) {
+            target.wait_idle();
+        }
+    }
+
+    /// Registers the drawable to be drawn to the main window on layer 0.
     pub fn register_drawable(&mut self, drawable_entry: &mut DrawableEntry) {
+        self.register_drawable_to(self.main_window_id, drawable_entry, 0);
+    }
+
+    /// Drawables on higher layers are drawn over lower ones, the same layer keeps the
+    /// registration order. Use this instead of depth for transparent sprites, like a
+    /// character over the map, depth testing between them breaks blending.
+    pub fn register_drawable_with_order(&mut self, drawable_entry: &mut DrawableEntry, layer: i32) {
+        self.register_drawable_to(self.main_window_id, drawable_entry, layer);
+    }
+
+    pub fn register_drawable_to(
+        &mut self,
+        window_id: WindowId,
+        drawable_entry: &mut DrawableEntry,
+        layer: i32,
+    ) {
         if drawable_entry.registered_uid.is_some() {
             return;
         }
 
-        drawable_entry.registered_uid = Some(self.registered_drawables.len() as u32);
-        self.registered_drawables.push(drawable_entry.get_weak());
+        let target = match self.targets.get_mut(&window_id) {
+            Some(target) => target,
+            None => {
+                _ = dbg!("[WARN] Tried to register an entry to a window that doesn't exist.");
+                return;
+            }
+        };
+
+        // slots of unregistered or dropped drawables are reused so the list doesn't keep growing
+        let uid = match target
+            .registered_drawables
+            .iter()
+            .position(|(_, weak)| weak.strong_count() == 0)
+        {
+            Some(free_slot) => {
+                target.registered_drawables[free_slot] = (layer, drawable_entry.get_weak());
+                free_slot
+            }
+            None => {
+                target
+                    .registered_drawables
+                    .push((layer, drawable_entry.get_weak()));
+                target.registered_drawables.len() - 1
+            }
+        };
+
+        drawable_entry.registered_uid = Some(uid as u32);
+        drawable_entry.registered_window = Some(window_id);
     }
 
     pub fn unregister_drawable(&mut self, drawable_entry: &mut DrawableEntry) {
-        match drawable_entry.registered_uid {
-            Some(idx) => match self.registered_drawables.get_mut(idx as usize) {
-                Some(weak) => *weak = Weak::new(),
+        let target = drawable_entry
+            .registered_window
+            .take()
+            .and_then(|window_id| self.targets.get_mut(&window_id));
+
+        match (drawable_entry.registered_uid.take(), target) {
+            (Some(idx), Some(target)) => match target.registered_drawables.get_mut(idx as usize) {
+                Some((_, weak)) => *weak = Weak::new(),
                 None => _ = dbg!("[WARN] Tried to unregister an entry that was out of bounds."),
             },
-            None => _ = dbg!("[WARN] Tried to unregister an entry that wasn't registered."),
+            (Some(_), None) => {
+                _ = dbg!("[WARN] Tried to unregister an entry from a closed window.")
+            }
+            (None, _) => _ = dbg!("[WARN] Tried to unregister an entry that wasn't registered."),
         }
     }
 
-    pub fn recreate_swapchain(&mut self) {
-        for future in &mut self.futures {
-            if let Some(future) = future.take() {
-                if let Ok(fence_future) = future.then_signal_fence_and_flush() {
-                    fence_future.wait(None).unwrap();
-                }
-            }
+    /// Keeps the drawable alive and draws it to the main window every frame until it is removed.
+    /// Unlike registered drawables the caller doesn't have to keep the `DrawableEntry` around.
+    pub fn add_persistent(&mut self, drawable: Arc<GenericDrawable>) -> DrawHandle {
+        self.add_persistent_with_order(drawable, 0)
+    }
+
+    /// Like `add_persistent`, ordered by `layer` like `register_drawable_with_order`.
+    pub fn add_persistent_with_order(
+        &mut self,
+        drawable: Arc<GenericDrawable>,
+        layer: i32,
+    ) -> DrawHandle {
+        self.persistent_drawables.insert((layer, drawable))
+    }
+
+    /// Hidden persistent drawables are kept but not drawn.
+    pub fn set_visible(&mut self, handle: DrawHandle, visible: bool) {
+        if !self.persistent_drawables.set_visible(handle, visible) {
+            _ = dbg!("[WARN] Tried to change the visibility of a removed drawable.");
         }
+    }
 
-        let capabilities = self
-            .device
-            .physical_device()
-            .surface_capabilities(self.surface.as_ref(), Default::default())
-            .unwrap();
+    pub fn is_visible(&self, handle: DrawHandle) -> bool {
+        self.persistent_drawables
+            .is_visible(handle)
+            .unwrap_or(false)
+    }
 
-        let extent: [u32; 2] = match capabilities.current_extent {
-            Some(current) => current,
-            None => {
-                let window: &Window = self.surface.object().unwrap().downcast_ref().unwrap();
-                let framebuffer_extent = window.inner_size();
-                let width = framebuffer_extent.width;
-                let height = framebuffer_extent.height;
-                [
-                    width.clamp(
-                        capabilities.min_image_extent[0],
-                        capabilities.max_image_extent[0],
-                    ),
-                    height.clamp(
-                        capabilities.min_image_extent[1],
-                        capabilities.max_image_extent[1],
-                    ),
-                ]
-            }
-        };
+    /// Stops drawing the drawable and gives it back, None if it was already removed.
+    pub fn remove(&mut self, handle: DrawHandle) -> Option<Arc<GenericDrawable>> {
+        self.persistent_drawables
+            .remove(handle)
+            .map(|(_, drawable)| drawable)
+    }
+
+    /// Recreates every swapchain with at least `image_count` images, clamped to what each surface supports.
+    /// 2 is double buffering with the lowest latency, 3 is triple buffering.
+    /// Returns how many images the swapchain of the main window ended up with.
+    pub fn set_desired_image_count(&mut self, image_count: u32) -> u32 {
+        self.desired_image_count = Some(image_count);
+        for target in self.targets.values_mut() {
+            target.set_min_image_count(
+                self.device.clone(),
+                &self.allocator,
+                self.main_render_pass.clone(),
+                image_count,
+            );
+        }
+        self.get_image_count()
+    }
 
-        let create_info = SwapchainCreateInfo {
-            image_extent: extent,
-            ..self.swapchain.create_info()
+    /// How many images the swapchain of the main window has.
+    pub fn get_image_count(&self) -> u32 {
+        self.targets[&self.main_window_id].swapchain.image_count()
+    }
+
+    pub fn recreate_swapchain(&mut self, window_id: WindowId) {
+        let target = match self.targets.get_mut(&window_id) {
+            Some(target) => target,
+            None => return,
         };
 
-        let (swapchain, swapchain_images) = self.swapchain.recreate(create_info).unwrap();
+        target.recreate_swapchain(
+            self.device.clone(),
+            &self.allocator,
+            self.main_render_pass.clone(),
+        );
+
+        // the utils only follow the size of the main window
+        if self.is_main_window(window_id) {
+            self.utils.get().unwrap().recreate(&self);
+        }
+    }
+
+    /// Recompiles every shader in `shaders/src` and rebuilds the cached pipelines that use them.
+    /// Drawables have to create their shaders with `from_source` to be reloaded.
+    /// Shaders that fail to compile are reported and keep running the previous version.
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_shaders(&self) {
+        let modules = hot_reload::compile_shaders(self.device.clone());
+        for shared_part in self
+            .get_shared_data_map()
+            .values()
+            .filter_map(|weak| weak.upgrade())
+        {
+            shared_part.replace_shaders(self.device.clone(), &modules);
+        }
+        // cached pipelines still use the old shader modules
+        match self.pipeline_map.lock() {
+            Ok(mut pipeline_map) => pipeline_map.clear(),
+            Err(e) => println!("Failed to access pipeline map {e}"),
+        }
+        println!("Reloaded {} shaders.", modules.len());
+    }
 
-        let image_views = create_image_views(&swapchain_images, swapchain.clone());
+    /// Every loaded texture is watched until it is dropped.
+    #[cfg(feature = "hot-reload")]
+    pub fn watch_texture(&self, texture: &Arc<bindable::Texture>) {
+        match self.watched_textures.lock() {
+            Ok(mut watcher) => watcher.textures.push(Arc::downgrade(texture)),
+            Err(e) => println!("Failed to watch texture {} {e}", texture.get_path()),
+        }
+    }
 
-        let (depth_buffers, _) =
-            create_depth_buffer(self.device.clone(), self.swapchain.clone(), &self.allocator);
+    /// Reloads the textures whose files were changed since they were loaded.
+    /// Cheap enough to call every frame, the files are only checked every `TEXTURE_CHECK_INTERVAL`.
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_changed_textures(&self) {
+        let textures: Vec<Arc<bindable::Texture>> = match self.watched_textures.lock() {
+            Ok(mut watcher) => {
+                if watcher.last_check.elapsed() < TEXTURE_CHECK_INTERVAL {
+                    return;
+                }
+                watcher.last_check = std::time::Instant::now();
+                watcher.textures.retain(|weak| weak.strong_count() > 0);
+                watcher
+                    .textures
+                    .iter()
+                    .filter_map(|weak| weak.upgrade())
+                    .collect()
+            }
+            Err(e) => {
+                println!("Failed to access watched textures {e}");
+                return;
+            }
+        };
 
-        let framebuffers =
-            create_framebuffers(&image_views, self.main_render_pass.clone(), &depth_buffers);
+        // checked without holding the lock, so other textures can be loaded meanwhile
+        for texture in textures {
+            texture.reload_if_changed(self);
+        }
+    }
 
-        self.swapchain = swapchain;
-        self.framebuffers = framebuffers;
+    /// Uniform buffers register themselves so their data is uploaded before every frame.
+    pub fn register_uniform_buffer(&self, uniform_buffer: Weak<dyn FlushPending>) {
+        match self.uniform_buffers.lock() {
+            Ok(mut uniform_buffers) => uniform_buffers.push(uniform_buffer),
+            Err(e) => println!("Failed to register uniform buffer {e}"),
+        }
+    }
 
-        self.utils.get().unwrap().recreate(&self);
+    /// The pipeline another call site already built for `key`, otherwise the one `build` makes.
+    pub fn get_or_build_pipeline(
+        &self,
+        key: pipeline::PipelineKey,
+        build: impl FnOnce() -> Arc<GraphicsPipeline>,
+    ) -> Arc<GraphicsPipeline> {
+        match self.pipeline_map.lock() {
+            Ok(mut pipeline_map) => pipeline_map.get_or_insert_with(key, build),
+            Err(e) => {
+                println!("Failed to access pipeline map {e}");
+                build()
+            }
+        }
     }
 
     pub fn cache_drawable_shared_part(
@@ -443,7 +1379,51 @@ impl Graphics {
     }
 }
 
-fn create_instance(library: Arc<VulkanLibrary>) -> Arc<Instance> {
+/// Starts with an empty cache if the file is missing or was made by another device or driver.
+fn load_pipeline_cache(
+    device: Arc<Device>,
+    path: &str,
+) -> Result<Arc<PipelineCache>, GraphicsInitError> {
+    let empty = |device| PipelineCache::empty(device).map_err(GraphicsInitError::PipelineCache);
+
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(_) => return empty(device),
+    };
+
+    if !is_pipeline_cache_compatible(device.physical_device(), &data) {
+        println!("Ignoring {path}, it was made by another device or driver version.");
+        return empty(device);
+    }
+
+    // the header was checked above, the driver validates the rest itself
+    match unsafe { PipelineCache::with_data(device.clone(), &data) } {
+        Ok(cache) => Ok(cache),
+        Err(e) => {
+            println!("Failed to load {path}: {e}");
+            empty(device)
+        }
+    }
+}
+
+/// Checks the header every pipeline cache starts with against the device.
+fn is_pipeline_cache_compatible(physical_device: &PhysicalDevice, data: &[u8]) -> bool {
+    const HEADER_SIZE: usize = 32;
+    if data.len() < HEADER_SIZE {
+        return false;
+    }
+
+    let read_u32 = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+    let properties = physical_device.properties();
+
+    read_u32(0) as usize >= HEADER_SIZE
+        && read_u32(4) == 1 // VK_PIPELINE_CACHE_HEADER_VERSION_ONE
+        && read_u32(8) == properties.vendor_id
+        && read_u32(12) == properties.device_id
+        && data[16..32] == properties.pipeline_cache_uuid
+}
+
+fn create_instance(library: Arc<VulkanLibrary>) -> Result<Arc<Instance>, GraphicsInitError> {
     let required_extensions = vulkano_win::required_extensions(&library);
 
     let create_info = InstanceCreateInfo {
@@ -456,7 +1436,7 @@ fn create_instance(library: Arc<VulkanLibrary>) -> Arc<Instance> {
         ..InstanceCreateInfo::default()
     };
 
-    Instance::new(library.clone(), create_info).expect("Failed to create instance!")
+    Instance::new(library.clone(), create_info).map_err(GraphicsInitError::Instance)
 }
 
 fn create_debug_messenger(instance: Arc<Instance>) -> DebugUtilsMessenger {
@@ -473,21 +1453,27 @@ fn create_debug_messenger(instance: Arc<Instance>) -> DebugUtilsMessenger {
     }
 }
 
-fn create_window(instance: Arc<Instance>) -> (EventLoop<()>, Arc<Surface>) {
+fn create_window(
+    instance: Arc<Instance>,
+) -> Result<(EventLoop<()>, Arc<Surface>), GraphicsInitError> {
     let event_loop = EventLoop::new();
     let surface = WindowBuilder::new()
         .with_inner_size(LogicalSize::new(600, 400))
         .with_resizable(true)
         .with_title("Batako")
         .build_vk_surface(&event_loop, instance.clone())
-        .expect("Failed to create window surface!");
-    (event_loop, surface)
+        .map_err(GraphicsInitError::SurfaceCreation)?;
+    Ok((event_loop, surface))
 }
 
-fn create_physical_device(instance: Arc<Instance>, surface: Arc<Surface>) -> Arc<PhysicalDevice> {
+fn create_physical_device(
+    instance: Arc<Instance>,
+    surface: Arc<Surface>,
+) -> Result<Arc<PhysicalDevice>, GraphicsInitError> {
+    // failing to enumerate the devices means there is no usable one either
     let physical_device = instance
         .enumerate_physical_devices()
-        .expect("No appropriate physical device found!")
+        .map_err(|_| GraphicsInitError::NoSuitableDevice)?
         .filter(|p| is_device_suitable(p.clone(), surface.clone()))
         .min_by_key(|p| {
             // We assign a lower score to device types that are likely to be faster/better.
@@ -500,7 +1486,7 @@ fn create_physical_device(instance: Arc<Instance>, surface: Arc<Surface>) -> Arc
                 _ => 5,
             }
         })
-        .expect("no suitable physical device found");
+        .ok_or(GraphicsInitError::NoSuitableDevice)?;
 
     // Some little debug infos.
     println!(
@@ -509,7 +1495,7 @@ fn create_physical_device(instance: Arc<Instance>, surface: Arc<Surface>) -> Arc
         physical_device.properties().device_type,
     );
 
-    physical_device
+    Ok(physical_device)
 }
 
 fn is_device_suitable(physical_device: Arc<PhysicalDevice>, surface: Arc<Surface>) -> bool {
@@ -555,7 +1541,7 @@ fn find_queue_indices(physical_device: Arc<PhysicalDevice>, surface: Arc<Surface
 fn create_logical_device(
     physical_device: Arc<PhysicalDevice>,
     surface: Arc<Surface>,
-) -> (Arc<Device>, Queues) {
+) -> Result<(Arc<Device>, Queues), GraphicsInitError> {
     let mut extensions = DEVICE_EXTENSIONS.clone();
 
     if physical_device.api_version() < Version::V1_3 {
@@ -563,14 +1549,23 @@ fn create_logical_device(
     }
 
     let indices = find_queue_indices(physical_device.clone(), surface.clone());
-    let mut index_set = vec![indices.graphics_queue.unwrap()];
+    let (graphics_index, present_index) = match (indices.graphics_queue, indices.present_queue) {
+        (Some(graphics_index), Some(present_index)) => (graphics_index, present_index),
+        _ => return Err(GraphicsInitError::NoSuitableDevice),
+    };
+    let mut index_set = vec![graphics_index];
 
-    if !index_set.contains(&indices.present_queue.unwrap()) {
-        index_set.push(indices.present_queue.unwrap());
+    let dedicated_present = !index_set.contains(&present_index);
+    if dedicated_present {
+        index_set.push(present_index);
     }
 
-    if indices.transfer_queue.is_some() && !index_set.contains(&indices.transfer_queue.unwrap()) {
-        index_set.push(indices.transfer_queue.unwrap());
+    let dedicated_transfer = match indices.transfer_queue {
+        Some(transfer_index) => !index_set.contains(&transfer_index),
+        None => false,
+    };
+    if let (true, Some(transfer_index)) = (dedicated_transfer, indices.transfer_queue) {
+        index_set.push(transfer_index);
     }
 
     let create_info = DeviceCreateInfo {
@@ -586,60 +1581,73 @@ fn create_logical_device(
         ..Default::default()
     };
 
-    let (device, mut queue_iter) = Device::new(physical_device.clone(), create_info)
-        .expect("Failed to create logical device!");
+    let (device, mut queue_iter) =
+        Device::new(physical_device.clone(), create_info).map_err(GraphicsInitError::Device)?;
 
     let mut queues = Queues::default();
 
     queues.graphics_queue = queue_iter.next();
 
-    if !index_set.contains(&indices.present_queue.unwrap()) {
+    if dedicated_present {
         dbg!("Forced to use a dedicated present queue,");
         queues.present_queue = queue_iter.next();
     } else {
         queues.present_queue = queues.graphics_queue.clone();
     }
 
-    if indices.transfer_queue.is_some() && !index_set.contains(&indices.transfer_queue.unwrap()) {
+    if dedicated_transfer {
         dbg!("Found support for dedicated transfer queue.");
         queues.transfer_queue = queue_iter.next();
     } else {
         queues.transfer_queue = queues.graphics_queue.clone();
     }
 
-    (device, queues)
+    Ok((device, queues))
+}
+
+fn choose_surface_format(
+    device: Arc<Device>,
+    surface: Arc<Surface>,
+) -> Result<(Format, ColorSpace), GraphicsInitError> {
+    let formats = device
+        .physical_device()
+        .surface_formats(surface.as_ref(), Default::default())
+        .map_err(GraphicsInitError::SurfaceQuery)?;
+
+    formats
+        .iter()
+        .find(|(format, color_space)| {
+            *format == Format::B8G8R8A8_SRGB && *color_space == ColorSpace::SrgbNonLinear
+        })
+        .or(formats.first())
+        .copied()
+        .ok_or(GraphicsInitError::UnsupportedSurface("no image formats"))
 }
 
 fn create_swapchain(
     device: Arc<Device>,
     surface: Arc<Surface>,
-) -> (Arc<Swapchain>, Vec<Arc<SwapchainImage>>) {
-    let (capabilities, formats, present_modes) = (
+) -> Result<(Arc<Swapchain>, Vec<Arc<SwapchainImage>>), GraphicsInitError> {
+    let (capabilities, present_modes) = (
         device
             .physical_device()
             .surface_capabilities(surface.as_ref(), Default::default())
-            .unwrap(),
-        device
-            .physical_device()
-            .surface_formats(surface.as_ref(), Default::default())
-            .unwrap(),
+            .map_err(GraphicsInitError::SurfaceQuery)?,
         device
             .physical_device()
             .surface_present_modes(surface.as_ref())
-            .unwrap(),
+            .map_err(GraphicsInitError::SurfaceQuery)?,
     );
 
-    let surface_format = formats
-        .iter()
-        .find(|(format, color_space)| {
-            *format == Format::B8G8R8A8_SRGB && *color_space == ColorSpace::SrgbNonLinear
-        })
-        .unwrap_or(formats.first().unwrap());
+    let surface_format = choose_surface_format(device.clone(), surface.clone())?;
 
     let extent: [u32; 2] = match capabilities.current_extent {
         Some(current) => current,
         None => {
-            let window: &Window = surface.object().unwrap().downcast_ref().unwrap();
+            let window: &Window = surface
+                .object()
+                .and_then(|object| object.downcast_ref())
+                .ok_or(GraphicsInitError::UnsupportedSurface("no window"))?;
             let framebuffer_extent = window.inner_size();
             let width = framebuffer_extent.width;
             let height = framebuffer_extent.height;
@@ -672,16 +1680,15 @@ fn create_swapchain(
             PresentMode::Immediate => 4,
             _ => 5,
         })
-        .unwrap();
+        .ok_or(GraphicsInitError::UnsupportedSurface("no present modes"))?;
 
     let indices = find_queue_indices(device.physical_device().clone(), surface.clone());
-    let image_sharing = if indices.graphics_queue == indices.present_queue {
-        Sharing::Exclusive
-    } else {
-        Sharing::Concurrent(smallvec::smallvec![
-            indices.graphics_queue.unwrap(),
-            indices.present_queue.unwrap()
-        ])
+    let image_sharing = match (indices.graphics_queue, indices.present_queue) {
+        (Some(graphics_index), Some(present_index)) if graphics_index != present_index => {
+            Sharing::Concurrent(smallvec::smallvec![graphics_index, present_index])
+        }
+        (Some(_), Some(_)) => Sharing::Exclusive,
+        _ => return Err(GraphicsInitError::NoSuitableDevice),
     };
 
     let create_info = SwapchainCreateInfo {
@@ -693,7 +1700,9 @@ fn create_swapchain(
         image_color_space: surface_format.1,
         image_extent: extent,
         image_array_layers: 1,
-        image_usage: ImageUsage::COLOR_ATTACHMENT,
+        // transfer source lets capture_screenshot copy the image, when the surface allows it
+        image_usage: ImageUsage::COLOR_ATTACHMENT
+            | (capabilities.supported_usage_flags & ImageUsage::TRANSFER_SRC),
         image_sharing: image_sharing,
         pre_transform: capabilities.current_transform,
         composite_alpha: capabilities
@@ -703,14 +1712,16 @@ fn create_swapchain(
                 CompositeAlpha::Opaque => 0,
                 _ => 1,
             })
-            .unwrap(),
+            .ok_or(GraphicsInitError::UnsupportedSurface(
+                "no composite alpha modes",
+            ))?,
         present_mode: present_mode,
         clipped: true,
         ..Default::default()
     };
 
     Swapchain::new(device.clone(), surface.clone(), create_info)
-        .expect("Failed to create Swapchain!")
+        .map_err(GraphicsInitError::Swapchain)
 }
 
 fn create_image_views(
@@ -744,22 +1755,38 @@ fn create_main_render_pass(
     device: Arc<Device>,
     swapchain_format: Format,
     depth_format: Format,
-) -> Arc<RenderPass> {
-    let attachments = vec![
+    color_load_op: LoadOp,
+    samples: SampleCount,
+) -> Result<Arc<RenderPass>, RenderPassCreationError> {
+    // With multisampling everything is drawn to a multisampled color attachment
+    // which is resolved into the swapchain image at the end of the subpass.
+    let multisampled = samples != SampleCount::Sample1;
+
+    // the image that is drawn to keeps its layout between frames, loading needs that
+    let color_final_layout = match multisampled {
+        true => ImageLayout::ColorAttachmentOptimal,
+        false => ImageLayout::PresentSrc,
+    };
+    let color_initial_layout = match color_load_op {
+        LoadOp::Load => color_final_layout,
+        _ => ImageLayout::Undefined,
+    };
+
+    let mut attachments = vec![
         AttachmentDescription {
             format: Some(swapchain_format),
-            samples: SampleCount::Sample1,
-            load_op: LoadOp::Clear,
+            samples: samples,
+            load_op: color_load_op,
             store_op: StoreOp::Store,
             stencil_load_op: LoadOp::DontCare,
             stencil_store_op: StoreOp::DontCare,
-            initial_layout: ImageLayout::Undefined,
-            final_layout: ImageLayout::PresentSrc,
+            initial_layout: color_initial_layout,
+            final_layout: color_final_layout,
             ..Default::default()
         },
         AttachmentDescription {
             format: Some(depth_format),
-            samples: SampleCount::Sample1,
+            samples: samples,
             load_op: LoadOp::Clear,
             store_op: StoreOp::Store,
             stencil_load_op: LoadOp::DontCare,
@@ -770,6 +1797,26 @@ fn create_main_render_pass(
         },
     ];
 
+    let mut resolve_attachment_refs = Vec::new();
+    if multisampled {
+        attachments.push(AttachmentDescription {
+            format: Some(swapchain_format),
+            samples: SampleCount::Sample1,
+            load_op: LoadOp::DontCare,
+            store_op: StoreOp::Store,
+            stencil_load_op: LoadOp::DontCare,
+            stencil_store_op: StoreOp::DontCare,
+            initial_layout: ImageLayout::Undefined,
+            final_layout: ImageLayout::PresentSrc,
+            ..Default::default()
+        });
+        resolve_attachment_refs.push(Some(AttachmentReference {
+            attachment: 2,
+            layout: ImageLayout::ColorAttachmentOptimal,
+            ..Default::default()
+        }));
+    }
+
     let color_attachment_refs = vec![Some(AttachmentReference {
         attachment: 0,
         layout: ImageLayout::ColorAttachmentOptimal,
@@ -786,6 +1833,7 @@ fn create_main_render_pass(
     let mut subpasses = Vec::new();
     subpasses.push(SubpassDescription {
         color_attachments: color_attachment_refs,
+        resolve_attachments: resolve_attachment_refs,
         depth_stencil_attachment: Some(depth_attachment_ref),
         ..Default::default()
     });
@@ -807,29 +1855,90 @@ fn create_main_render_pass(
         }],
         ..Default::default()
     };
-    RenderPass::new(device.clone(), create_info).expect("Failed to create render pass!")
+    RenderPass::new(device.clone(), create_info)
 }
 
+/// `extent` has to be the extent of the swapchain the images belong to.
+/// `msaa_buffers` is empty when the render pass isn't multisampled.
 fn create_framebuffers(
     image_views: &Vec<Arc<ImageView<SwapchainImage>>>,
     render_pass: Arc<RenderPass>,
     depth_buffers: &Vec<Arc<ImageView<AttachmentImage>>>,
-) -> Vec<Arc<Framebuffer>> {
+    msaa_buffers: &Vec<Arc<ImageView<AttachmentImage>>>,
+    extent: [u32; 2],
+) -> Result<Vec<Arc<Framebuffer>>, FramebufferError> {
     image_views
         .iter()
         .zip(depth_buffers)
-        .map(|(image, depth_buffer)| {
+        .enumerate()
+        .map(|(i, (image, depth_buffer))| {
+            check_attachment_extents(
+                extent,
+                image.image().dimensions().width_height(),
+                depth_buffer.image().dimensions().width_height(),
+            )?;
+
+            let attachments: Vec<Arc<dyn ImageViewAbstract>> = framebuffer_attachments(
+                image.clone() as _,
+                depth_buffer.clone() as _,
+                msaa_buffers.get(i).map(|buffer| buffer.clone() as _),
+            );
+
             let create_info = FramebufferCreateInfo {
-                attachments: vec![image.clone(), depth_buffer.clone()],
-                extent: [0, 0],
+                attachments: attachments,
+                extent: extent,
                 layers: 1,
                 ..Default::default()
             };
-            Framebuffer::new(render_pass.clone(), create_info).unwrap()
+            Framebuffer::new(render_pass.clone(), create_info).map_err(FramebufferError::Creation)
         })
         .collect()
 }
 
+// Every swapchain image has its own framebuffer. Images are acquired in whatever order the
+// presentation engine hands them out, so the framebuffer follows the acquired image and not
+// the frame in flight.
+fn acquired_framebuffer<F>(framebuffers: &[F], image_index: u32) -> Option<&F> {
+    framebuffers.get(image_index as usize)
+}
+
+fn next_in_flight_index(inflight_index: u32, in_flight_count: usize) -> u32 {
+    (inflight_index + 1) % in_flight_count as u32
+}
+
+// The attachment order has to match create_main_render_pass.
+fn framebuffer_attachments<T>(image: T, depth_buffer: T, msaa_buffer: Option<T>) -> Vec<T> {
+    match msaa_buffer {
+        Some(msaa_buffer) => vec![msaa_buffer, depth_buffer, image],
+        None => vec![image, depth_buffer],
+    }
+}
+
+// Where the swapchain image is in `framebuffer_attachments`, with msaa it is the resolve attachment.
+fn swapchain_attachment_index(msaa: bool) -> usize {
+    match msaa {
+        true => 2,
+        false => 0,
+    }
+}
+
+// Vulkan allows attachments larger than the framebuffer, which would hide a depth
+// buffer that wasn't recreated along with the swapchain.
+fn check_attachment_extents(
+    extent: [u32; 2],
+    color: [u32; 2],
+    depth: [u32; 2],
+) -> Result<(), FramebufferError> {
+    match color == extent && depth == extent {
+        true => Ok(()),
+        false => Err(FramebufferError::ExtentMismatch {
+            extent: extent,
+            color: color,
+            depth: depth,
+        }),
+    }
+}
+
 fn select_image_format(
     device: Arc<Device>,
     tiling: ImageTiling,
@@ -837,7 +1946,10 @@ fn select_image_format(
     candidates: &[Format],
 ) -> Option<Format> {
     for format in candidates {
-        let props = device.physical_device().format_properties(*format).unwrap();
+        let props = match device.physical_device().format_properties(*format) {
+            Ok(props) => props,
+            Err(_) => continue,
+        };
         if tiling == ImageTiling::Optimal && props.optimal_tiling_features.contains(features) {
             return Some(*format);
         }
@@ -848,11 +1960,7 @@ fn select_image_format(
     None
 }
 
-fn create_depth_buffer(
-    device: Arc<Device>,
-    swapchain: Arc<Swapchain>,
-    allocator: &StandardMemoryAllocator,
-) -> (Vec<Arc<ImageView<AttachmentImage>>>, Format) {
+fn find_depth_format(device: Arc<Device>) -> Option<Format> {
     let format_candidates = [
         Format::D16_UNORM,
         Format::D32_SFLOAT,
@@ -861,19 +1969,28 @@ fn create_depth_buffer(
         Format::D32_SFLOAT_S8_UINT,
     ];
 
-    let format = select_image_format(
-        device.clone(),
+    select_image_format(
+        device,
         ImageTiling::Optimal,
         FormatFeatures::DEPTH_STENCIL_ATTACHMENT,
         &format_candidates,
     )
-    .unwrap();
+}
+
+fn create_depth_buffer(
+    device: Arc<Device>,
+    swapchain: Arc<Swapchain>,
+    allocator: &StandardMemoryAllocator,
+    samples: SampleCount,
+) -> Result<(Vec<Arc<ImageView<AttachmentImage>>>, Format), FramebufferError> {
+    let format = find_depth_format(device).ok_or(FramebufferError::NoDepthFormat)?;
 
     let mut views = Vec::new();
     views.resize_with(swapchain.image_count() as usize, || {
-        let image = AttachmentImage::with_usage(
+        let image = AttachmentImage::multisampled_with_usage(
             allocator,
             swapchain.image_extent(),
+            samples,
             format,
             ImageUsage::DEPTH_STENCIL_ATTACHMENT,
         )
@@ -890,5 +2007,155 @@ fn create_depth_buffer(
         .unwrap()
     });
 
-    (views, format)
+    Ok((views, format))
+}
+
+/// The color attachments that are resolved into the swapchain images, none without multisampling.
+fn create_msaa_buffers(
+    swapchain: Arc<Swapchain>,
+    allocator: &StandardMemoryAllocator,
+    samples: SampleCount,
+) -> Vec<Arc<ImageView<AttachmentImage>>> {
+    if samples == SampleCount::Sample1 {
+        return Vec::new();
+    }
+
+    let mut views = Vec::new();
+    views.resize_with(swapchain.image_count() as usize, || {
+        let image = AttachmentImage::multisampled_with_usage(
+            allocator,
+            swapchain.image_extent(),
+            samples,
+            swapchain.image_format(),
+            ImageUsage::COLOR_ATTACHMENT,
+        )
+        .unwrap();
+        ImageView::new_default(image).unwrap()
+    });
+    views
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resized_framebuffer_extents_must_match() {
+        // after a resize from 800x600 to 1024x768
+        let resized = [1024, 768];
+        assert!(check_attachment_extents(resized, resized, resized).is_ok());
+
+        // a depth buffer left at the old size
+        match check_attachment_extents(resized, resized, [800, 600]) {
+            Err(FramebufferError::ExtentMismatch {
+                extent,
+                color,
+                depth,
+            }) => {
+                assert_eq!(extent, resized);
+                assert_eq!(color, resized);
+                assert_eq!(depth, [800, 600]);
+            }
+            _ => panic!("a stale depth buffer should not match the new extent"),
+        }
+
+        // a color attachment larger than the framebuffer is allowed by Vulkan but still an error here
+        assert!(check_attachment_extents(resized, [1280, 800], resized).is_err());
+    }
+
+    #[test]
+    fn recorded_framebuffer_targets_the_acquired_image() {
+        let images = ["image 0", "image 1", "image 2"];
+
+        for msaa in [false, true] {
+            // what create_framebuffers builds, one framebuffer per swapchain image
+            let framebuffers: Vec<Vec<&str>> = images
+                .iter()
+                .map(|image| framebuffer_attachments(*image, "depth", msaa.then_some("msaa")))
+                .collect();
+
+            // two frames in flight, while the images come back out of order
+            let mut inflight_index = 0;
+            for image_index in [2u32, 0, 1, 1, 0, 2] {
+                let recorded = acquired_framebuffer(&framebuffers, image_index).unwrap();
+                assert_eq!(
+                    recorded[swapchain_attachment_index(msaa)],
+                    images[image_index as usize]
+                );
+                // going by the frame in flight would draw to an image that isn't presented
+                if inflight_index != image_index {
+                    assert_ne!(recorded, &framebuffers[inflight_index as usize]);
+                }
+                inflight_index = next_in_flight_index(inflight_index, 2);
+            }
+        }
+    }
+
+    #[test]
+    fn images_of_a_shrunk_swapchain_have_no_framebuffer() {
+        let framebuffers = ["first", "second"];
+        assert_eq!(acquired_framebuffer(&framebuffers, 1), Some(&"second"));
+        assert_eq!(acquired_framebuffer(&framebuffers, 2), None);
+    }
+
+    #[test]
+    fn every_queued_drawable_is_one_draw_call() {
+        struct Sprite {
+            visible: bool,
+        }
+        let sprite = |visible| Arc::new(Sprite { visible: visible });
+
+        let map = sprite(true);
+        let hidden = sprite(false);
+        let registered = vec![
+            (0, Arc::downgrade(&map)),
+            (1, Arc::downgrade(&sprite(true))),
+            (1, Arc::downgrade(&hidden)),
+        ];
+        let persistent = vec![(0, sprite(true)), (2, sprite(false))];
+        let hud = vec![sprite(true)];
+        let ui = vec![sprite(true), sprite(true)];
+        let queued = registered.len() + persistent.len() + hud.len() + ui.len();
+        let (dropped_count, hidden_count) = (1, 2);
+
+        let world = draw_order::visible_world(&registered, persistent, |p| p.visible);
+        let passes = draw_order::frame_draw_order(world, hud, ui);
+
+        let mut stats = FrameStats::default();
+        stats.draw_calls += draw_order::draw_call_count(&passes, 1);
+        assert_eq!(
+            stats.draw_calls as usize,
+            queued - dropped_count - hidden_count
+        );
+
+        // the two visible world drawables are drawn in both halves of the screen
+        let split_screen_calls = draw_order::draw_call_count(&passes, 2);
+        assert_eq!(split_screen_calls, stats.draw_calls + 2);
+    }
+
+    #[test]
+    fn triangles_count_every_instance() {
+        // index and instance counts of a quad, an instanced quad and a tile chunk
+        let mut stats = FrameStats::default();
+        for (index_count, instance_count) in [(6, 1), (6, 50), (4 * 6 * 16, 1)] {
+            stats.count_triangles(index_count, instance_count);
+        }
+        assert_eq!(stats.triangles, 2 + 100 + 128);
+    }
+
+    #[test]
+    fn three_frames_in_flight_use_three_futures() {
+        let in_flight_count = 3;
+        let futures = render_target::empty_futures(in_flight_count);
+        assert_eq!(futures.len(), 3);
+
+        let mut inflight_index = 0;
+        let mut used = Vec::new();
+        for _ in 0..7 {
+            assert!(futures[inflight_index as usize].is_none());
+            used.push(inflight_index);
+            inflight_index = next_in_flight_index(inflight_index, in_flight_count);
+        }
+        assert_eq!(used, [0, 1, 2, 0, 1, 2, 0]);
+    }
 }
@@ -1,9 +1,10 @@
 use std::sync::Arc;
 
 use vulkano::{
+    buffer::Subbuffer,
     command_buffer::{
         allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder,
-        PrimaryAutoCommandBuffer,
+        DrawIndexedIndirectCommand, PrimaryAutoCommandBuffer,
     },
     pipeline::PipelineLayout,
     shader::ShaderModule,
@@ -11,30 +12,101 @@ use vulkano::{
 
 use super::{pipeline::PipelineBuilder, Graphics};
 
+mod blend;
 mod buffer;
 mod god_bindable;
 mod push_constant;
+mod rasterization;
+mod scissor;
 mod shader;
 mod texture;
+mod texture_atlas;
+mod topology;
 mod uniform;
 
+pub use blend::*;
 pub use buffer::*;
 pub use god_bindable::*;
 pub use push_constant::*;
+pub use rasterization::*;
+pub use scissor::*;
 pub use shader::*;
 pub use texture::*;
+pub use texture_atlas::*;
+pub use topology::*;
 pub use uniform::*;
 
+/// The command buffer every bindable records its commands into.
+pub type CommandBufferBuilder =
+    AutoCommandBufferBuilder<PrimaryAutoCommandBuffer, StandardCommandBufferAllocator>;
+
+/// Implemented by everything that makes up a drawable.
+/// `bind_to_pipeline` is called once when the pipeline is built, `bind` every time the drawable is drawn.
+/// `index_count` is an out parameter for bindables that decide how many indices are drawn.
+/// Bindables that decide how many instances are drawn override `instance_count`,
+/// bindables that make the drawable use indirect draws override `indirect_buffer`.
 pub trait Bindable {
     fn bind_to_pipeline(&self, builder: &mut PipelineBuilder, index_count: &mut u32);
+    fn instance_count(&self) -> Option<u32> {
+        None
+    }
+    fn indirect_buffer(&self) -> Option<Subbuffer<[DrawIndexedIndirectCommand]>> {
+        None
+    }
     fn bind(
         &self,
         _gfx: &Graphics,
-        _builder: &mut AutoCommandBufferBuilder<
-            PrimaryAutoCommandBuffer,
-            StandardCommandBufferAllocator,
-        >,
+        _builder: &mut CommandBufferBuilder,
         _pipeline_layout: Arc<PipelineLayout>,
     ) {
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use vulkano::{
+        buffer::BufferContents,
+        pipeline::graphics::{
+            color_blend::AttachmentBlend, input_assembly::PrimitiveTopology, vertex_input::Vertex,
+        },
+        shader::ShaderStages,
+    };
+
+    use super::*;
+
+    #[derive(BufferContents, Vertex)]
+    #[repr(C)]
+    struct TestVertex {
+        #[format(R32G32_SFLOAT)]
+        pos: [f32; 2],
+    }
+
+    // Only compiles while every bindable implements the one shared trait.
+    fn assert_bindable<B: Bindable>() {}
+
+    #[test]
+    fn every_bindable_implements_the_shared_trait() {
+        assert_bindable::<VertexBuffer<TestVertex>>();
+        assert_bindable::<InstanceBuffer<TestVertex>>();
+        assert_bindable::<IndexBuffer>();
+        assert_bindable::<IndirectBuffer>();
+        assert_bindable::<UniformBufferBinding<[f32; 4]>>();
+        assert_bindable::<TextureBinding>();
+        assert_bindable::<PushConstant<[f32; 4]>>();
+        assert_bindable::<VertexShader>();
+        assert_bindable::<FragmentShader>();
+
+        // the ones that don't need a device can be put in a drawable's list right away
+        let bindables: Vec<Arc<dyn Bindable>> = vec![
+            PushConstant::new(0, [1.0f32; 4], ShaderStages::VERTEX),
+            Topology::new(PrimitiveTopology::LineList),
+            Rasterization::no_culling(),
+            Blend::new(AttachmentBlend::alpha()),
+            ScissorRect::new([0, 0], [16, 16]),
+            GodBindable::new(|_, _| {}, |_, _| {}),
+        ];
+        assert!(bindables
+            .iter()
+            .all(|bindable| bindable.instance_count().is_none()));
+    }
+}
@@ -0,0 +1,37 @@
+use std::sync::Arc;
+
+use vulkano::pipeline::graphics::color_blend::{
+    AttachmentBlend, BlendFactor, BlendOp, ColorBlendState,
+};
+
+use super::*;
+
+/// Sets how the output of the fragment shader is combined with what was already drawn.
+/// Without it the output replaces the color attachment.
+pub struct Blend {
+    blend: AttachmentBlend,
+}
+
+impl Blend {
+    pub fn new(blend: AttachmentBlend) -> Arc<Self> {
+        Arc::new(Self { blend: blend })
+    }
+
+    /// Multiplies what was already drawn by the output, white leaves it as it is.
+    pub fn multiply() -> Arc<Self> {
+        Self::new(AttachmentBlend {
+            color_op: BlendOp::Add,
+            color_source: BlendFactor::DstColor,
+            color_destination: BlendFactor::Zero,
+            alpha_op: BlendOp::Add,
+            alpha_source: BlendFactor::Zero,
+            alpha_destination: BlendFactor::One,
+        })
+    }
+}
+
+impl Bindable for Blend {
+    fn bind_to_pipeline(&self, builder: &mut PipelineBuilder, _index_count: &mut u32) {
+        builder.color_blend_state = ColorBlendState::new(1).blend(self.blend);
+    }
+}
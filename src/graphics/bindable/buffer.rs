@@ -1,10 +1,16 @@
-use std::{mem::align_of, sync::Arc};
+use std::{
+    mem::align_of,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
 
 use vulkano::{
     buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
     command_buffer::{
-        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder, CommandBufferUsage,
-        CopyBufferInfoTyped, PrimaryAutoCommandBuffer, PrimaryCommandBufferAbstract,
+        AutoCommandBufferBuilder, CommandBufferUsage, CopyBufferInfoTyped,
+        DrawIndexedIndirectCommand, PrimaryCommandBufferAbstract,
     },
     memory::allocator::{AllocationCreateInfo, DeviceLayout},
     pipeline::{graphics::vertex_input::Vertex, PipelineLayout},
@@ -13,7 +19,74 @@ use vulkano::{
 
 use crate::graphics::{pipeline::PipelineBuilder, Graphics};
 
-use super::Bindable;
+use super::{Bindable, CommandBufferBuilder};
+
+/// Uploads the data to a device local buffer through a temporary staging buffer.
+/// Blocks until the upload has finished.
+fn create_device_local_buffer<T>(gfx: &Graphics, data: Vec<T>, usage: BufferUsage) -> Subbuffer<[T]>
+where
+    T: BufferContents,
+{
+    let staging_buffer = Buffer::from_iter(
+        gfx.get_allocator(),
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_SRC,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            usage: vulkano::memory::allocator::MemoryUsage::Upload,
+            ..Default::default()
+        },
+        data.into_iter(),
+    )
+    .expect("Failed to create staging buffer.");
+
+    // only counted until the upload has finished
+    let _host_memory = gfx.get_host_memory().allocate(staging_buffer.size());
+
+    let main_buffer = Buffer::new(
+        gfx.get_allocator(),
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_DST | usage,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            usage: vulkano::memory::allocator::MemoryUsage::DeviceOnly,
+            ..Default::default()
+        },
+        DeviceLayout::from_size_alignment(staging_buffer.size(), align_of::<T>() as u64).unwrap(),
+    )
+    .expect("Failed to create device local buffer.");
+
+    let main_subbuffer = Subbuffer::new(main_buffer).cast_aligned();
+
+    let mut builder = AutoCommandBufferBuilder::primary(
+        gfx.get_cmd_allocator(),
+        gfx.graphics_queue().queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap();
+
+    builder
+        .copy_buffer(CopyBufferInfoTyped::buffers(
+            staging_buffer,
+            main_subbuffer.clone(),
+        ))
+        .unwrap();
+
+    let fence = builder
+        .build()
+        .unwrap()
+        .execute(gfx.graphics_queue())
+        .unwrap()
+        .then_signal_fence_and_flush()
+        .unwrap();
+
+    fence.wait(None).unwrap();
+
+    main_subbuffer
+}
+
 pub struct VertexBuffer<T>
 where
     T: Vertex + BufferContents,
@@ -29,15 +102,7 @@ where
         builder.vertex_buffer_description = Some(T::per_vertex());
     }
 
-    fn bind(
-        &self,
-        _gfx: &Graphics,
-        builder: &mut AutoCommandBufferBuilder<
-            PrimaryAutoCommandBuffer,
-            StandardCommandBufferAllocator,
-        >,
-        _: Arc<PipelineLayout>,
-    ) {
+    fn bind(&self, _gfx: &Graphics, builder: &mut CommandBufferBuilder, _: Arc<PipelineLayout>) {
         builder.bind_vertex_buffers(0, self.subbuffer.clone());
     }
 }
@@ -50,63 +115,8 @@ where
     where
         T: Vertex + BufferContents,
     {
-        let staging_buffer = Buffer::from_iter(
-            gfx.get_allocator(),
-            BufferCreateInfo {
-                usage: BufferUsage::TRANSFER_SRC,
-                ..Default::default()
-            },
-            AllocationCreateInfo {
-                usage: vulkano::memory::allocator::MemoryUsage::Upload,
-                ..Default::default()
-            },
-            vertices.into_iter(),
-        )
-        .expect("Failed to create vertex buffer.");
-
-        let main_buffer = Buffer::new(
-            gfx.get_allocator(),
-            BufferCreateInfo {
-                usage: BufferUsage::TRANSFER_DST | BufferUsage::VERTEX_BUFFER,
-                ..Default::default()
-            },
-            AllocationCreateInfo {
-                usage: vulkano::memory::allocator::MemoryUsage::DeviceOnly,
-                ..Default::default()
-            },
-            DeviceLayout::from_size_alignment(staging_buffer.size(), align_of::<T>() as u64)
-                .unwrap(),
-        )
-        .expect("Failed to create index buffer.");
-
-        let main_subbuffer = Subbuffer::new(main_buffer).cast_aligned();
-
-        let mut builder = AutoCommandBufferBuilder::primary(
-            gfx.get_cmd_allocator(),
-            gfx.graphics_queue().queue_family_index(),
-            CommandBufferUsage::OneTimeSubmit,
-        )
-        .unwrap();
-
-        builder
-            .copy_buffer(CopyBufferInfoTyped::buffers(
-                staging_buffer,
-                main_subbuffer.clone(),
-            ))
-            .unwrap();
-
-        let fence = builder
-            .build()
-            .unwrap()
-            .execute(gfx.graphics_queue())
-            .unwrap()
-            .then_signal_fence_and_flush()
-            .unwrap();
-
-        fence.wait(None).unwrap();
-
         Arc::new(Self {
-            subbuffer: main_subbuffer,
+            subbuffer: create_device_local_buffer(gfx, vertices, BufferUsage::VERTEX_BUFFER),
         })
     }
 }
@@ -119,78 +129,93 @@ impl Bindable for IndexBuffer {
     fn bind_to_pipeline(&self, _builder: &mut PipelineBuilder, index_count: &mut u32) {
         *index_count = self.subbuffer.len().try_into().unwrap();
     }
-    fn bind(
-        &self,
-        _gfx: &Graphics,
-        builder: &mut AutoCommandBufferBuilder<
-            PrimaryAutoCommandBuffer,
-            StandardCommandBufferAllocator,
-        >,
-        _: Arc<PipelineLayout>,
-    ) {
+    fn bind(&self, _gfx: &Graphics, builder: &mut CommandBufferBuilder, _: Arc<PipelineLayout>) {
         builder.bind_index_buffer(self.subbuffer.clone());
     }
 }
 
 impl IndexBuffer {
     pub fn new(gfx: &Graphics, indices: Vec<u32>) -> Arc<Self> {
-        let staging_buffer = Buffer::from_iter(
-            gfx.get_allocator(),
-            BufferCreateInfo {
-                usage: BufferUsage::TRANSFER_SRC,
-                ..Default::default()
-            },
-            AllocationCreateInfo {
-                usage: vulkano::memory::allocator::MemoryUsage::Upload,
-                ..Default::default()
-            },
-            indices.into_iter(),
-        )
-        .expect("Failed to create index buffer.");
-
-        let main_buffer = Buffer::new(
-            gfx.get_allocator(),
-            BufferCreateInfo {
-                usage: BufferUsage::TRANSFER_DST | BufferUsage::INDEX_BUFFER,
-                ..Default::default()
-            },
-            AllocationCreateInfo {
-                usage: vulkano::memory::allocator::MemoryUsage::DeviceOnly,
-                ..Default::default()
-            },
-            DeviceLayout::from_size_alignment(staging_buffer.size(), align_of::<u32>() as u64)
-                .unwrap(),
-        )
-        .expect("Failed to create index buffer.");
-
-        let main_subbuffer = Subbuffer::new(main_buffer).cast_aligned();
-
-        let mut builder = AutoCommandBufferBuilder::primary(
-            gfx.get_cmd_allocator(),
-            gfx.graphics_queue().queue_family_index(),
-            CommandBufferUsage::OneTimeSubmit,
-        )
-        .unwrap();
+        Arc::new(Self {
+            subbuffer: create_device_local_buffer(gfx, indices, BufferUsage::INDEX_BUFFER),
+        })
+    }
+}
+
+/// Per instance vertex data, bound to vertex buffer binding 1.
+/// A drawable with an instance buffer is drawn once for every instance.
+pub struct InstanceBuffer<T>
+where
+    T: Vertex + BufferContents,
+{
+    subbuffer: Subbuffer<[T]>,
+    instance_count: AtomicU32,
+}
 
-        builder
-            .copy_buffer(CopyBufferInfoTyped::buffers(
-                staging_buffer,
-                main_subbuffer.clone(),
-            ))
-            .unwrap();
+impl<T> Bindable for InstanceBuffer<T>
+where
+    T: Vertex + BufferContents,
+{
+    fn bind_to_pipeline(&self, builder: &mut PipelineBuilder, _index_count: &mut u32) {
+        builder.instance_buffer_description = Some(T::per_instance());
+    }
 
-        let fence = builder
-            .build()
-            .unwrap()
-            .execute(gfx.graphics_queue())
-            .unwrap()
-            .then_signal_fence_and_flush()
-            .unwrap();
+    fn bind(&self, _gfx: &Graphics, builder: &mut CommandBufferBuilder, _: Arc<PipelineLayout>) {
+        builder.bind_vertex_buffers(1, self.subbuffer.clone());
+    }
 
-        fence.wait(None).unwrap();
+    fn instance_count(&self) -> Option<u32> {
+        Some(self.instance_count.load(Ordering::Relaxed))
+    }
+}
 
+impl<T> InstanceBuffer<T>
+where
+    T: Vertex + BufferContents,
+{
+    pub fn new(gfx: &Graphics, instances: Vec<T>) -> Arc<Self> {
+        let instance_count = instances.len() as u32;
         Arc::new(Self {
-            subbuffer: main_subbuffer,
+            subbuffer: create_device_local_buffer(gfx, instances, BufferUsage::VERTEX_BUFFER),
+            instance_count: AtomicU32::new(instance_count),
         })
     }
+
+    /// Only draws the first `instance_count` instances. Clamped to the size of the buffer.
+    pub fn set_instance_count(&self, instance_count: u32) {
+        let instance_count = u32::min(instance_count, self.subbuffer.len() as u32);
+        self.instance_count.store(instance_count, Ordering::Relaxed);
+    }
+}
+
+/// Draw parameters that live on the gpu. A drawable with an indirect buffer is drawn with
+/// `draw_indexed_indirect`, so the index and instance counts can be written by a compute shader
+/// without reading them back to the cpu.
+pub struct IndirectBuffer {
+    subbuffer: Subbuffer<[DrawIndexedIndirectCommand]>,
+}
+
+impl Bindable for IndirectBuffer {
+    fn bind_to_pipeline(&self, _builder: &mut PipelineBuilder, _index_count: &mut u32) {}
+
+    fn indirect_buffer(&self) -> Option<Subbuffer<[DrawIndexedIndirectCommand]>> {
+        Some(self.subbuffer.clone())
+    }
+}
+
+impl IndirectBuffer {
+    pub fn new(gfx: &Graphics, commands: Vec<DrawIndexedIndirectCommand>) -> Arc<Self> {
+        Arc::new(Self {
+            subbuffer: create_device_local_buffer(
+                gfx,
+                commands,
+                BufferUsage::INDIRECT_BUFFER | BufferUsage::STORAGE_BUFFER,
+            ),
+        })
+    }
+
+    /// The buffer the commands are read from, for binding it as a storage buffer.
+    pub fn get_subbuffer(&self) -> Subbuffer<[DrawIndexedIndirectCommand]> {
+        self.subbuffer.clone()
+    }
 }
@@ -5,17 +5,13 @@ use std::{
 
 use vulkano::{
     buffer::BufferContents,
-    command_buffer::{
-        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder,
-        PrimaryAutoCommandBuffer,
-    },
     pipeline::{layout::PushConstantRange, PipelineLayout},
     shader::ShaderStages,
 };
 
 use crate::graphics::{pipeline::PipelineBuilder, Graphics};
 
-use super::Bindable;
+use super::{Bindable, CommandBufferBuilder};
 
 pub struct PushConstant<T>
 where
@@ -29,7 +25,8 @@ impl<T> PushConstant<T>
 where
     T: BufferContents + Clone,
 {
-    pub fn new(_gfx: &Graphics, offset: u32, data: T, stages: ShaderStages) -> Arc<Self> {
+    /// Push constants are recorded straight into the command buffer, so no buffer is created.
+    pub fn new(offset: u32, data: T, stages: ShaderStages) -> Arc<Self> {
         let range = PushConstantRange {
             stages: stages,
             offset: offset,
@@ -62,10 +59,7 @@ where
     fn bind(
         &self,
         _gfx: &Graphics,
-        builder: &mut AutoCommandBufferBuilder<
-            PrimaryAutoCommandBuffer,
-            StandardCommandBufferAllocator,
-        >,
+        builder: &mut CommandBufferBuilder,
         pipeline_layout: Arc<PipelineLayout>,
     ) {
         builder.push_constants(
@@ -75,3 +69,21 @@ where
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_constant_round_trip() {
+        let push_constant = PushConstant::new(16, [0.0f32, 0.0, 0.0], ShaderStages::VERTEX);
+        assert_eq!(push_constant.push_constant_range.offset, 16);
+        assert_eq!(push_constant.push_constant_range.size, 12);
+
+        push_constant.access_data(|data| data[1] = 2.5);
+
+        let mut read_back = [0.0; 3];
+        push_constant.access_data(|data| read_back = *data);
+        assert_eq!(read_back, [0.0, 2.5, 0.0]);
+    }
+}
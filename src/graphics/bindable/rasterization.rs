@@ -0,0 +1,67 @@
+use std::sync::Arc;
+
+use vulkano::pipeline::{
+    graphics::rasterization::{CullMode, FrontFace, RasterizationState},
+    StateMode,
+};
+
+use super::*;
+
+/// Overrides which faces are culled and which winding faces the camera.
+/// Only needed for geometry that doesn't follow the clockwise convention of `PipelineBuilder`,
+/// or that should be visible from both sides.
+pub struct Rasterization {
+    cull_mode: CullMode,
+    front_face: FrontFace,
+}
+
+impl Rasterization {
+    pub fn new(cull_mode: CullMode, front_face: FrontFace) -> Arc<Self> {
+        Arc::new(Self {
+            cull_mode: cull_mode,
+            front_face: front_face,
+        })
+    }
+
+    /// Draws triangles regardless of their winding.
+    pub fn no_culling() -> Arc<Self> {
+        Self::new(CullMode::None, FrontFace::Clockwise)
+    }
+}
+
+impl Bindable for Rasterization {
+    fn bind_to_pipeline(&self, builder: &mut PipelineBuilder, _index_count: &mut u32) {
+        self.apply(&mut builder.rasterization_state);
+    }
+}
+
+impl Rasterization {
+    fn apply(&self, rasterization_state: &mut RasterizationState) {
+        rasterization_state.cull_mode = StateMode::Fixed(self.cull_mode);
+        rasterization_state.front_face = StateMode::Fixed(self.front_face);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constructors_write_fixed_culling_state() {
+        let mut state = RasterizationState::default();
+        Rasterization::new(CullMode::Front, FrontFace::CounterClockwise).apply(&mut state);
+        assert!(matches!(state.cull_mode, StateMode::Fixed(CullMode::Front)));
+        assert!(matches!(
+            state.front_face,
+            StateMode::Fixed(FrontFace::CounterClockwise)
+        ));
+
+        // overrides what was written before
+        Rasterization::no_culling().apply(&mut state);
+        assert!(matches!(state.cull_mode, StateMode::Fixed(CullMode::None)));
+        assert!(matches!(
+            state.front_face,
+            StateMode::Fixed(FrontFace::Clockwise)
+        ));
+    }
+}
@@ -0,0 +1,51 @@
+use std::sync::{Arc, Mutex};
+
+use vulkano::pipeline::graphics::viewport::{Scissor, ViewportState};
+
+use super::*;
+
+/// Clips everything the drawable draws to a rectangle.
+/// The rectangle is in framebuffer pixels with the origin in the top left corner.
+pub struct ScissorRect {
+    scissor: Mutex<Scissor>,
+}
+
+impl ScissorRect {
+    pub fn new(origin: [u32; 2], dimensions: [u32; 2]) -> Arc<Self> {
+        Arc::new(Self {
+            scissor: Mutex::new(Scissor {
+                origin: origin,
+                dimensions: dimensions,
+            }),
+        })
+    }
+
+    /// Takes effect the next time the command buffer is recorded.
+    pub fn set_rect(&self, origin: [u32; 2], dimensions: [u32; 2]) {
+        match self.scissor.lock() {
+            Ok(mut guard) => {
+                guard.origin = origin;
+                guard.dimensions = dimensions;
+            }
+            Err(e) => println!("Scissor rect mutex could not be locked! {e}"),
+        }
+    }
+}
+
+impl Bindable for ScissorRect {
+    fn bind_to_pipeline(&self, builder: &mut PipelineBuilder, _index_count: &mut u32) {
+        builder.viewport_state = ViewportState::viewport_dynamic_scissor_dynamic(1);
+    }
+    fn bind(
+        &self,
+        _gfx: &Graphics,
+        builder: &mut CommandBufferBuilder,
+        _pipeline_layout: Arc<PipelineLayout>,
+    ) {
+        let scissor = match self.scissor.lock() {
+            Ok(guard) => guard.clone(),
+            Err(_) => Scissor::irrelevant(),
+        };
+        builder.set_scissor(0, [scissor]);
+    }
+}
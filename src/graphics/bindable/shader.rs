@@ -1,35 +1,119 @@
-use std::sync::Arc;
+use std::{fmt::Display, sync::Arc};
 
 use super::*;
 
+/// `None` when the module failed to load, the pipeline then falls back to the error shaders.
 pub struct VertexShader {
-    module: Arc<ShaderModule>,
+    module: Option<Arc<ShaderModule>>,
+    // the glsl file the module was compiled from, used for hot reloading
+    source_path: Option<&'static str>,
 }
 
 impl Bindable for VertexShader {
     fn bind_to_pipeline(&self, builder: &mut PipelineBuilder, _index_count: &mut u32) {
-        builder.vertex_shader = Some(self.module.clone());
+        builder.vertex_shader_source = self.source_path;
+        match &self.module {
+            Some(module) => builder.vertex_shader = Some(module.clone()),
+            None => builder.shader_load_failed = true,
+        }
     }
 }
 
 impl VertexShader {
     pub fn from_module(module: Arc<ShaderModule>) -> Arc<Self> {
-        Arc::new(Self { module: module })
+        Arc::new(Self {
+            module: Some(module),
+            source_path: None,
+        })
+    }
+
+    /// Same as `from_result` but remembers the generated `SOURCE_PATH`, so `Graphics::reload_shaders`
+    /// can swap in a recompiled module.
+    pub fn from_source<E: Display>(
+        module: Result<Arc<ShaderModule>, E>,
+        source_path: &'static str,
+    ) -> Arc<Self> {
+        let module = Self::load_module(module);
+        Arc::new(Self {
+            module: module,
+            source_path: Some(source_path),
+        })
+    }
+
+    /// Takes the result of a generated `load` function.
+    /// A module that failed to load is reported and drawn with the error pipeline instead of panicking.
+    pub fn from_result<E: Display>(module: Result<Arc<ShaderModule>, E>) -> Arc<Self> {
+        Arc::new(Self {
+            module: Self::load_module(module),
+            source_path: None,
+        })
+    }
+
+    fn load_module<E: Display>(module: Result<Arc<ShaderModule>, E>) -> Option<Arc<ShaderModule>> {
+        match module {
+            Ok(module) => Some(module),
+            Err(e) => {
+                println!("Failed to load vertex shader: {e}");
+                None
+            }
+        }
     }
 }
 
+/// `None` when the module failed to load, the pipeline then falls back to the error shaders.
 pub struct FragmentShader {
-    module: Arc<ShaderModule>,
+    module: Option<Arc<ShaderModule>>,
+    // the glsl file the module was compiled from, used for hot reloading
+    source_path: Option<&'static str>,
 }
 
 impl Bindable for FragmentShader {
     fn bind_to_pipeline(&self, builder: &mut PipelineBuilder, _index_count: &mut u32) {
-        builder.fragment_shader = Some(self.module.clone());
+        builder.fragment_shader_source = self.source_path;
+        match &self.module {
+            Some(module) => builder.fragment_shader = Some(module.clone()),
+            None => builder.shader_load_failed = true,
+        }
     }
 }
 
 impl FragmentShader {
     pub fn from_module(module: Arc<ShaderModule>) -> Arc<Self> {
-        Arc::new(Self { module: module })
+        Arc::new(Self {
+            module: Some(module),
+            source_path: None,
+        })
+    }
+
+    /// Same as `from_result` but remembers the generated `SOURCE_PATH`, so `Graphics::reload_shaders`
+    /// can swap in a recompiled module.
+    pub fn from_source<E: Display>(
+        module: Result<Arc<ShaderModule>, E>,
+        source_path: &'static str,
+    ) -> Arc<Self> {
+        let module = Self::load_module(module);
+        Arc::new(Self {
+            module: module,
+            source_path: Some(source_path),
+        })
+    }
+
+    /// Takes the result of a generated `load` function.
+    /// A module that failed to load is reported and drawn with the error pipeline instead of panicking.
+    pub fn from_result<E: Display>(module: Result<Arc<ShaderModule>, E>) -> Arc<Self> {
+        Arc::new(Self {
+            module: Self::load_module(module),
+            source_path: None,
+        })
+    }
+
+    fn load_module<E: Display>(module: Result<Arc<ShaderModule>, E>) -> Option<Arc<ShaderModule>> {
+        match module {
+            Ok(module) => Some(module),
+            Err(e) => {
+                println!("Failed to load fragment shader: {e}");
+                None
+            }
+        }
     }
 }
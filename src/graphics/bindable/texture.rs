@@ -1,4 +1,12 @@
-use std::{io::Cursor, sync::Arc};
+use std::{
+    fmt::Display,
+    io::Cursor,
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Arc, RwLock,
+    },
+    time::SystemTime,
+};
 
 use vulkano::{
     command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, PrimaryCommandBufferAbstract},
@@ -18,64 +26,161 @@ use vulkano::{
 
 use crate::graphics::{pipeline::PipelineBuilder, Graphics};
 
-use super::Bindable;
+use super::{Bindable, CommandBufferBuilder};
+
+/// Why a texture or something built from one could not be loaded.
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    Decode(png::DecodingError),
+    /// An image in another format than png failed to decode.
+    DecodeImage(image::ImageError),
+    /// The file is valid but uses a feature that isn't supported.
+    Unsupported(String),
+    /// The file loaded but its contents don't make sense, like a tile set without tiles.
+    Malformed(String),
+    /// The image is larger than the device can handle.
+    TooLarge {
+        width: u32,
+        height: u32,
+        max_dimension: u32,
+    },
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "{e}"),
+            LoadError::Decode(e) => write!(f, "{e}"),
+            LoadError::DecodeImage(e) => write!(f, "{e}"),
+            LoadError::Unsupported(feature) => write!(f, "unsupported: {feature}"),
+            LoadError::Malformed(reason) => write!(f, "malformed: {reason}"),
+            LoadError::TooLarge {
+                width,
+                height,
+                max_dimension,
+            } => write!(
+                f,
+                "image is {width}x{height} but the device supports at most \
+                {max_dimension}x{max_dimension}, split it into several textures"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<std::io::Error> for LoadError {
+    fn from(e: std::io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+impl From<png::DecodingError> for LoadError {
+    fn from(e: png::DecodingError) -> Self {
+        LoadError::Decode(e)
+    }
+}
+
+impl From<image::ImageError> for LoadError {
+    fn from(e: image::ImageError) -> Self {
+        LoadError::DecodeImage(e)
+    }
+}
 
 pub struct Texture {
-    pub image: Arc<ImageView<ImmutableImage>>,
+    // both are swapped when the file is reloaded, which is why they sit behind a lock
+    image: RwLock<Arc<ImageView<ImmutableImage>>>,
+    descriptor_set: RwLock<Arc<PersistentDescriptorSet>>,
     pub sampler: Arc<Sampler>,
     layout: Arc<DescriptorSetLayout>,
-    descriptor_set: Arc<PersistentDescriptorSet>,
+    binding: u32,
+    path: String,
+    #[cfg(feature = "hot-reload")]
+    modified: std::sync::Mutex<Option<SystemTime>>,
 }
 
 impl Texture {
+    /// Panics if the texture can't be loaded, use `Texture::load` to handle the error.
     pub fn new(gfx: &Graphics, path: &str, binding: u32, use_nearest_neighbor: bool) -> Arc<Self> {
-        let mut uploads = AutoCommandBufferBuilder::primary(
-            gfx.get_cmd_allocator(),
-            gfx.graphics_queue().queue_family_index(),
-            CommandBufferUsage::OneTimeSubmit,
-        )
-        .unwrap();
+        match Self::load(gfx, path, binding, use_nearest_neighbor) {
+            Ok(texture) => texture,
+            Err(e) => panic!("Failed to load texture {path}: {e}"),
+        }
+    }
 
-        let image = {
-            let bytes = std::fs::read(path).expect("Texture file not found.");
-            let cursor = Cursor::new(bytes);
-            let decoder = png::Decoder::new(cursor);
-            let mut reader = decoder.read_info().unwrap();
-            let info = reader.info();
-            let dimensions = ImageDimensions::Dim2d {
-                width: info.width,
-                height: info.height,
-                array_layers: 1,
-            };
-
-            assert_eq!(
-                info.bit_depth,
-                png::BitDepth::Eight,
-                "Only 32bit colors are supported"
-            );
+    /// Loads an 8 bit RGBA png, or a jpeg, bmp, gif or tga which are converted to RGBA.
+    /// The format is detected from the contents of the file, not the extension.
+    pub fn load(
+        gfx: &Graphics,
+        path: &str,
+        binding: u32,
+        use_nearest_neighbor: bool,
+    ) -> Result<Arc<Self>, LoadError> {
+        let image = decode_image(path, max_image_dimension(gfx))?;
+        Ok(Self::from_decoded(
+            gfx,
+            path,
+            image,
+            binding,
+            use_nearest_neighbor,
+        ))
+    }
 
-            let mut image_data = vec![0; (info.width * info.height * 4) as usize];
-            reader.next_frame(&mut image_data).unwrap();
+    /// Same as `Texture::load` but the file is read and decoded on another thread.
+    /// Only the upload happens on the calling thread, once `PendingTexture::poll` sees the image is ready.
+    pub fn load_async(
+        gfx: &Graphics,
+        path: &str,
+        binding: u32,
+        use_nearest_neighbor: bool,
+    ) -> PendingTexture {
+        let (sender, receiver) = mpsc::channel();
+        let thread_path = path.to_string();
+        let max_dimension = max_image_dimension(gfx);
+        std::thread::spawn(move || {
+            // the receiver is gone if the pending texture was dropped, the image isn't needed then
+            _ = sender.send(decode_image(&thread_path, max_dimension));
+        });
 
-            let image = ImmutableImage::from_iter(
-                gfx.get_allocator(),
-                image_data,
-                dimensions,
-                vulkano::image::MipmapsCount::One,
-                Format::R8G8B8A8_SRGB,
-                &mut uploads,
-            )
-            .unwrap();
-            ImageView::new_default(image).unwrap()
+        PendingTexture {
+            path: path.to_string(),
+            binding: binding,
+            use_nearest_neighbor: use_nearest_neighbor,
+            receiver: receiver,
+        }
+    }
+
+    /// Creates a texture from 8 bit RGBA pixels made at runtime, like a `TextureAtlas`.
+    /// `name` takes the place of the path in messages.
+    pub fn from_pixels(
+        gfx: &Graphics,
+        name: &str,
+        width: u32,
+        height: u32,
+        data: Vec<u8>,
+        binding: u32,
+        use_nearest_neighbor: bool,
+    ) -> Arc<Self> {
+        let decoded = DecodedImage {
+            width: width,
+            height: height,
+            data: data,
+            modified: None,
         };
+        Self::from_decoded(gfx, name, decoded, binding, use_nearest_neighbor)
+    }
 
-        let fence = uploads
-            .build()
-            .unwrap()
-            .execute(gfx.graphics_queue())
-            .unwrap()
-            .then_signal_fence_and_flush()
-            .unwrap();
+    fn from_decoded(
+        gfx: &Graphics,
+        path: &str,
+        decoded: DecodedImage,
+        binding: u32,
+        use_nearest_neighbor: bool,
+    ) -> Arc<Self> {
+        #[cfg(feature = "hot-reload")]
+        let modified = decoded.modified;
+        let image = upload_image(gfx, decoded);
 
         let sampler = match use_nearest_neighbor {
             true => Sampler::new(
@@ -113,8 +218,6 @@ impl Texture {
         )
         .unwrap();
 
-        fence.wait(None).unwrap();
-
         let set = PersistentDescriptorSet::new(
             gfx.get_descriptor_set_allocator(),
             layout.clone(),
@@ -122,15 +225,244 @@ impl Texture {
         )
         .unwrap();
 
-        Arc::new(Self {
-            image: image,
+        let texture = Arc::new(Self {
+            image: RwLock::new(image),
+            descriptor_set: RwLock::new(set),
             sampler: sampler,
             layout: layout,
-            descriptor_set: set,
-        })
+            binding: binding,
+            path: path.to_string(),
+            #[cfg(feature = "hot-reload")]
+            modified: std::sync::Mutex::new(modified),
+        });
+
+        #[cfg(feature = "hot-reload")]
+        gfx.watch_texture(&texture);
+
+        texture
+    }
+
+    pub fn get_image(&self) -> Arc<ImageView<ImmutableImage>> {
+        self.image.read().unwrap().clone()
+    }
+
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+
+    /// Decodes the file again if it was changed since it was loaded.
+    /// The new image has to be the same size, tile sets and meshes built from the texture
+    /// depend on it. Frames that are still in flight keep drawing the old image.
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_if_changed(&self, gfx: &Graphics) {
+        let modified = std::fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        match self.modified.lock() {
+            Ok(mut last_modified) => {
+                if modified.is_none() || modified == *last_modified {
+                    return;
+                }
+                *last_modified = modified;
+            }
+            Err(e) => {
+                println!("Failed to access modification time of {} {e}", self.path);
+                return;
+            }
+        }
+
+        // editors often write the file in several steps, a failed decode is retried on the next change
+        let decoded = match decode_image(&self.path, max_image_dimension(gfx)) {
+            Ok(decoded) => decoded,
+            Err(e) => {
+                println!("Failed to reload texture {}: {e}", self.path);
+                return;
+            }
+        };
+
+        let [width, height] = self.get_image().dimensions().width_height();
+        if decoded.width != width || decoded.height != height {
+            println!(
+                "Failed to reload texture {}: it was resized from {width}x{height} to {}x{}, \
+                restart to load it",
+                self.path, decoded.width, decoded.height
+            );
+            return;
+        }
+
+        let image = upload_image(gfx, decoded);
+        let set = PersistentDescriptorSet::new(
+            gfx.get_descriptor_set_allocator(),
+            self.layout.clone(),
+            [WriteDescriptorSet::image_view(self.binding, image.clone())],
+        )
+        .unwrap();
+
+        *self.image.write().unwrap() = image;
+        *self.descriptor_set.write().unwrap() = set;
+        println!("Reloaded texture {}.", self.path);
     }
 }
 
+/// Uploads the image and waits until it is on the device.
+fn upload_image(gfx: &Graphics, decoded: DecodedImage) -> Arc<ImageView<ImmutableImage>> {
+    let mut uploads = AutoCommandBufferBuilder::primary(
+        gfx.get_cmd_allocator(),
+        gfx.graphics_queue().queue_family_index(),
+        CommandBufferUsage::OneTimeSubmit,
+    )
+    .unwrap();
+
+    let dimensions = ImageDimensions::Dim2d {
+        width: decoded.width,
+        height: decoded.height,
+        array_layers: 1,
+    };
+
+    let image = ImmutableImage::from_iter(
+        gfx.get_allocator(),
+        decoded.data,
+        dimensions,
+        vulkano::image::MipmapsCount::One,
+        Format::R8G8B8A8_SRGB,
+        &mut uploads,
+    )
+    .unwrap();
+
+    uploads
+        .build()
+        .unwrap()
+        .execute(gfx.graphics_queue())
+        .unwrap()
+        .then_signal_fence_and_flush()
+        .unwrap()
+        .wait(None)
+        .unwrap();
+
+    ImageView::new_default(image).unwrap()
+}
+
+/// A texture that is still being decoded by `Texture::load_async`.
+pub struct PendingTexture {
+    path: String,
+    binding: u32,
+    use_nearest_neighbor: bool,
+    receiver: Receiver<Result<DecodedImage, LoadError>>,
+}
+
+impl PendingTexture {
+    /// Returns None while the image is still being decoded. Once it returns Some the texture is finished,
+    /// polling again after that returns an error.
+    pub fn poll(&self, gfx: &Graphics) -> Option<Result<Arc<Texture>, LoadError>> {
+        match self.receiver.try_recv() {
+            Ok(decoded) => Some(decoded.map(|decoded| {
+                Texture::from_decoded(
+                    gfx,
+                    &self.path,
+                    decoded,
+                    self.binding,
+                    self.use_nearest_neighbor,
+                )
+            })),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(LoadError::Malformed(format!(
+                "{} was already loaded or the loading thread panicked",
+                self.path
+            )))),
+        }
+    }
+
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+}
+
+pub(super) struct DecodedImage {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+    modified: Option<SystemTime>,
+}
+
+pub(super) fn max_image_dimension(gfx: &Graphics) -> u32 {
+    gfx.get_device()
+        .physical_device()
+        .properties()
+        .max_image_dimension2_d
+}
+
+/// Only touches the file system, so it can run on any thread.
+pub(super) fn decode_image(path: &str, max_dimension: u32) -> Result<DecodedImage, LoadError> {
+    // read before the contents, so a change during the read is picked up by the next reload
+    let modified = std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok();
+    let bytes = std::fs::read(path)?;
+    let (width, height, data) = decode_bytes(bytes, max_dimension)?;
+
+    Ok(DecodedImage {
+        width: width,
+        height: height,
+        data: data,
+        modified: modified,
+    })
+}
+
+// The format is detected from the contents, file extensions are often wrong.
+fn decode_bytes(bytes: Vec<u8>, max_dimension: u32) -> Result<(u32, u32, Vec<u8>), LoadError> {
+    // pngs are decoded straight into the upload buffer, everything else goes through the image crate
+    match bytes.starts_with(PNG_SIGNATURE) {
+        true => decode_png(bytes, max_dimension),
+        false => decode_other(&bytes, max_dimension),
+    }
+}
+
+const PNG_SIGNATURE: &[u8] = &[0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+fn decode_png(bytes: Vec<u8>, max_dimension: u32) -> Result<(u32, u32, Vec<u8>), LoadError> {
+    let cursor = Cursor::new(bytes);
+    let decoder = png::Decoder::new(cursor);
+    let mut reader = decoder.read_info()?;
+    let info = reader.info();
+
+    // Without this the image creation fails with an error that doesn't say why.
+    if info.width > max_dimension || info.height > max_dimension {
+        return Err(LoadError::TooLarge {
+            width: info.width,
+            height: info.height,
+            max_dimension: max_dimension,
+        });
+    }
+
+    if info.bit_depth != png::BitDepth::Eight || info.color_type != png::ColorType::Rgba {
+        return Err(LoadError::Unsupported(format!(
+            "{:?} {:?} png, only 32bit RGBA colors are supported",
+            info.bit_depth, info.color_type
+        )));
+    }
+
+    let (width, height) = (info.width, info.height);
+    let mut data = vec![0; (width * height * 4) as usize];
+    reader.next_frame(&mut data)?;
+
+    Ok((width, height, data))
+}
+
+fn decode_other(bytes: &[u8], max_dimension: u32) -> Result<(u32, u32, Vec<u8>), LoadError> {
+    let image = image::load_from_memory(bytes)?;
+
+    if image.width() > max_dimension || image.height() > max_dimension {
+        return Err(LoadError::TooLarge {
+            width: image.width(),
+            height: image.height(),
+            max_dimension: max_dimension,
+        });
+    }
+
+    let image = image.into_rgba8();
+    Ok((image.width(), image.height(), image.into_raw()))
+}
+
 pub struct TextureBinding {
     texture_ref: Arc<Texture>,
     set_num: u32,
@@ -153,17 +485,77 @@ impl Bindable for TextureBinding {
     fn bind(
         &self,
         _gfx: &Graphics,
-        builder: &mut AutoCommandBufferBuilder<
-            vulkano::command_buffer::PrimaryAutoCommandBuffer,
-            vulkano::command_buffer::allocator::StandardCommandBufferAllocator,
-        >,
+        builder: &mut CommandBufferBuilder,
         pipeline_layout: Arc<vulkano::pipeline::PipelineLayout>,
     ) {
         builder.bind_descriptor_sets(
             vulkano::pipeline::PipelineBindPoint::Graphics,
             pipeline_layout,
             self.set_num,
-            self.texture_ref.descriptor_set.clone(),
+            self.texture_ref.descriptor_set.read().unwrap().clone(),
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: u32 = 4;
+    const HEIGHT: u32 = 2;
+
+    // a red to blue gradient over the columns, opaque
+    fn gradient() -> Vec<[u8; 3]> {
+        (0..WIDTH * HEIGHT)
+            .map(|i| {
+                let t = (i % WIDTH) as u8 * 80;
+                [255 - t, 0, t]
+            })
+            .collect()
+    }
+
+    fn encode_png(pixels: &[[u8; 3]]) -> Vec<u8> {
+        let rgba: Vec<u8> = pixels
+            .iter()
+            .flat_map(|&[r, g, b]| [r, g, b, 255])
+            .collect();
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, WIDTH, HEIGHT);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&rgba).unwrap();
+        writer.finish().unwrap();
+        bytes
+    }
+
+    fn encode_jpeg(pixels: &[[u8; 3]]) -> Vec<u8> {
+        let rgb: Vec<u8> = pixels.iter().flatten().copied().collect();
+        let mut bytes = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut bytes, 100)
+            .encode(&rgb, WIDTH, HEIGHT, image::ColorType::Rgb8)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn png_and_jpeg_of_the_same_image_decode_alike() {
+        let (png_width, png_height, png_data) = decode_bytes(encode_png(&gradient()), 64).unwrap();
+        let (jpeg_width, jpeg_height, jpeg_data) =
+            decode_bytes(encode_jpeg(&gradient()), 64).unwrap();
+
+        assert_eq!((png_width, png_height), (WIDTH, HEIGHT));
+        assert_eq!((jpeg_width, jpeg_height), (WIDTH, HEIGHT));
+        assert_eq!(png_data.len(), jpeg_data.len());
+        // jpeg is lossy, but it still has to come out as opaque RGBA
+        assert!(jpeg_data.chunks_exact(4).all(|pixel| pixel[3] == 255));
+    }
+
+    #[test]
+    fn oversized_images_are_rejected() {
+        match decode_bytes(encode_jpeg(&gradient()), 2) {
+            Err(LoadError::TooLarge { width, height, .. }) => assert_eq!((width, height), (4, 2)),
+            _ => panic!("a 4x2 image is larger than 2 pixels"),
+        }
+    }
+}
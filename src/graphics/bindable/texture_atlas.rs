@@ -0,0 +1,153 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{graphics::Graphics, math::Rect};
+
+use super::texture::{decode_image, max_image_dimension};
+use super::{LoadError, Texture};
+
+// Empty pixels between sprites so linear filtering doesn't bleed into the neighbours.
+const ATLAS_PADDING: u32 = 1;
+
+/// Packs separate images into one texture, for tile sets made of loose sprite files.
+pub struct TextureAtlas;
+
+impl TextureAtlas {
+    /// Loads every `(name, path)` sprite and packs them into one texture.
+    /// Returns the texture and the uv rect of every sprite by name,
+    /// with uvs going from the top left corner of the atlas to the bottom right.
+    pub fn build(
+        gfx: &Graphics,
+        sprites: &[(&str, &str)],
+        binding: u32,
+        use_nearest_neighbor: bool,
+    ) -> Result<(Arc<Texture>, HashMap<String, Rect>), LoadError> {
+        if sprites.is_empty() {
+            return Err(LoadError::Malformed(
+                "a texture atlas needs at least one sprite".to_string(),
+            ));
+        }
+        let max_dimension = max_image_dimension(gfx);
+
+        let mut images = Vec::with_capacity(sprites.len());
+        for (_, path) in sprites {
+            images.push(decode_image(path, max_dimension)?);
+        }
+
+        let sizes: Vec<[u32; 2]> = images
+            .iter()
+            .map(|image| [image.width, image.height])
+            .collect();
+        let (extent, positions) = pack_shelves(&sizes, max_dimension);
+        if extent[0] > max_dimension || extent[1] > max_dimension {
+            return Err(LoadError::TooLarge {
+                width: extent[0],
+                height: extent[1],
+                max_dimension: max_dimension,
+            });
+        }
+
+        let mut data = vec![0; (extent[0] * extent[1] * 4) as usize];
+        let mut uv_rects = HashMap::with_capacity(sprites.len());
+        for (((name, _), image), position) in sprites.iter().zip(&images).zip(&positions) {
+            let row_length = (image.width * 4) as usize;
+            for row in 0..image.height {
+                let source = (row * image.width * 4) as usize;
+                let target = (((position[1] + row) * extent[0] + position[0]) * 4) as usize;
+                data[target..target + row_length]
+                    .copy_from_slice(&image.data[source..source + row_length]);
+            }
+
+            uv_rects.insert(
+                name.to_string(),
+                uv_rect(*position, [image.width, image.height], extent),
+            );
+        }
+
+        let texture = Texture::from_pixels(
+            gfx,
+            "texture atlas",
+            extent[0],
+            extent[1],
+            data,
+            binding,
+            use_nearest_neighbor,
+        );
+        Ok((texture, uv_rects))
+    }
+}
+
+fn uv_rect(position: [u32; 2], size: [u32; 2], extent: [u32; 2]) -> Rect {
+    Rect::new(
+        [
+            position[0] as f32 / extent[0] as f32,
+            position[1] as f32 / extent[1] as f32,
+        ],
+        [
+            (position[0] + size[0]) as f32 / extent[0] as f32,
+            (position[1] + size[1]) as f32 / extent[1] as f32,
+        ],
+    )
+}
+
+/// Places the rects in rows from the top left, tallest first, wrapping at `max_width`.
+/// Returns the size of the atlas and the top left corner of every rect in the order they were given.
+pub fn pack_shelves(sizes: &[[u32; 2]], max_width: u32) -> ([u32; 2], Vec<[u32; 2]>) {
+    // about square atlases, but never narrower than the widest rect
+    let area: u64 = sizes
+        .iter()
+        .map(|size| (size[0] + ATLAS_PADDING) as u64 * (size[1] + ATLAS_PADDING) as u64)
+        .sum();
+    let widest = sizes.iter().map(|size| size[0]).max().unwrap_or(0);
+    let width = ((area as f64).sqrt().ceil() as u32)
+        .next_power_of_two()
+        .max(widest)
+        .min(max_width.max(widest));
+
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(sizes[i][1]));
+
+    let mut positions = vec![[0, 0]; sizes.len()];
+    let mut cursor = [0, 0];
+    let mut shelf_height = 0;
+    let mut used_width = 0;
+    for i in order {
+        let size = sizes[i];
+        if cursor[0] > 0 && cursor[0] + size[0] > width {
+            cursor = [0, cursor[1] + shelf_height + ATLAS_PADDING];
+            shelf_height = 0;
+        }
+
+        positions[i] = cursor;
+        used_width = used_width.max(cursor[0] + size[0]);
+        shelf_height = shelf_height.max(size[1]);
+        cursor[0] += size[0] + ATLAS_PADDING;
+    }
+
+    ([used_width, cursor[1] + shelf_height], positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packed_sprites_do_not_overlap() {
+        let sizes = [[16, 16], [32, 8], [8, 24]];
+        let (extent, positions) = pack_shelves(&sizes, 64);
+
+        let uv_rects: Vec<Rect> = positions
+            .iter()
+            .zip(sizes)
+            .map(|(&position, size)| uv_rect(position, size, extent))
+            .collect();
+
+        for (i, rect) in uv_rects.iter().enumerate() {
+            assert!(rect.min[0] >= 0.0 && rect.min[1] >= 0.0);
+            assert!(rect.max[0] <= 1.0 && rect.max[1] <= 1.0);
+            // touching counts as intersecting, the padding keeps them apart
+            for other in &uv_rects[i + 1..] {
+                assert!(!rect.intersects(other), "{rect:?} overlaps {other:?}");
+            }
+        }
+    }
+}
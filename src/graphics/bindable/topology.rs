@@ -0,0 +1,62 @@
+use std::sync::Arc;
+
+use vulkano::pipeline::graphics::input_assembly::{InputAssemblyState, PrimitiveTopology};
+
+use super::*;
+
+/// Sets how the vertices are assembled into primitives, triangle lists are used without it.
+pub struct Topology {
+    topology: PrimitiveTopology,
+}
+
+impl Topology {
+    pub fn new(topology: PrimitiveTopology) -> Arc<Self> {
+        Arc::new(Self { topology: topology })
+    }
+}
+
+impl Bindable for Topology {
+    fn bind_to_pipeline(&self, builder: &mut PipelineBuilder, _index_count: &mut u32) {
+        builder.input_assembly_state = input_assembly_state(self.topology);
+    }
+}
+
+fn input_assembly_state(topology: PrimitiveTopology) -> InputAssemblyState {
+    // Strips can be split with a u32::MAX index. Lists don't need that and
+    // restarting them requires a device feature.
+    let primitive_restart = matches!(
+        topology,
+        PrimitiveTopology::LineStrip
+            | PrimitiveTopology::TriangleStrip
+            | PrimitiveTopology::TriangleFan
+    );
+
+    match primitive_restart {
+        true => InputAssemblyState::new()
+            .topology(topology)
+            .primitive_restart_enable(),
+        false => InputAssemblyState::new().topology(topology),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vulkano::pipeline::{PartialStateMode, StateMode};
+
+    use super::*;
+
+    // whether the state assembles `topology`, with primitive restart set to `restart`
+    fn assembles(topology: PrimitiveTopology, restart: bool) -> bool {
+        let state = input_assembly_state(topology);
+        matches!(state.topology, PartialStateMode::Fixed(fixed) if fixed == topology)
+            && matches!(state.primitive_restart_enable, StateMode::Fixed(fixed) if fixed == restart)
+    }
+
+    #[test]
+    fn only_strips_enable_primitive_restart() {
+        assert!(assembles(PrimitiveTopology::LineList, false));
+        assert!(assembles(PrimitiveTopology::TriangleList, false));
+        assert!(assembles(PrimitiveTopology::LineStrip, true));
+        assert!(assembles(PrimitiveTopology::TriangleStrip, true));
+    }
+}
@@ -6,10 +6,6 @@ use std::{
 
 use vulkano::{
     buffer::{Buffer, BufferContents, BufferCreateInfo, BufferUsage, Subbuffer},
-    command_buffer::{
-        allocator::StandardCommandBufferAllocator, AutoCommandBufferBuilder,
-        PrimaryAutoCommandBuffer,
-    },
     descriptor_set::{
         layout::{
             DescriptorSetLayout, DescriptorSetLayoutBinding, DescriptorSetLayoutCreateInfo,
@@ -23,15 +19,49 @@ use vulkano::{
     sync::Sharing,
 };
 
-use crate::graphics::{pipeline::PipelineBuilder, Graphics};
+use crate::graphics::{memory::HostMemoryAllocation, pipeline::PipelineBuilder, Graphics};
+
+use super::{Bindable, CommandBufferBuilder};
 
-use super::Bindable;
+/// Implemented by buffers whose staged data is uploaded by `Graphics` once per frame.
+pub trait FlushPending: Send + Sync {
+    /// Writes the staged data to the subbuffer of `in_flight_index` if it is outdated.
+    /// Only call once the frame that last used that subbuffer has finished.
+    fn flush_pending(&self, in_flight_index: usize);
+}
 
 struct UniformBufferMutablePart<T> {
     pub subbuffer_validity: Vec<bool>,
     pub staging_buffer: T,
 }
 
+impl<T> UniformBufferMutablePart<T> {
+    fn new(data: T, in_flight_count: usize) -> Self {
+        Self {
+            subbuffer_validity: vec![true; in_flight_count],
+            staging_buffer: data,
+        }
+    }
+
+    fn access(&mut self, accessing_function: impl FnOnce(&mut T)) {
+        // invalidate all subbuffers
+        self.subbuffer_validity.iter_mut().for_each(|p| *p = false);
+        accessing_function(&mut self.staging_buffer);
+    }
+
+    // The staged data if the subbuffer of `in_flight_index` still holds an older value.
+    fn outdated_data(&self, in_flight_index: usize) -> Option<&T> {
+        match self.subbuffer_validity[in_flight_index] {
+            true => None,
+            false => Some(&self.staging_buffer),
+        }
+    }
+
+    fn mark_valid(&mut self, in_flight_index: usize) {
+        self.subbuffer_validity[in_flight_index] = true;
+    }
+}
+
 pub struct UniformBuffer<T>
 where
     T: BufferContents,
@@ -41,6 +71,7 @@ where
     descriptor_sets: Vec<Arc<PersistentDescriptorSet>>,
 
     mutable_part: Mutex<UniformBufferMutablePart<T>>,
+    _host_memory: HostMemoryAllocation,
 }
 
 impl<T> UniformBuffer<T>
@@ -106,27 +137,54 @@ where
             sets.push(set);
         }
 
-        Arc::new(Self {
+        let host_memory = gfx
+            .get_host_memory()
+            .allocate(subbuffers.iter().map(|subbuffer| subbuffer.size()).sum());
+
+        let uniform_buffer = Arc::new(Self {
             subbuffers: subbuffers,
             layout: layout,
             descriptor_sets: sets,
 
-            mutable_part: Mutex::new(UniformBufferMutablePart {
-                subbuffer_validity: vec![true; gfx.get_in_flight_count()],
-                staging_buffer: data,
-            }),
-        })
+            mutable_part: Mutex::new(UniformBufferMutablePart::new(
+                data,
+                gfx.get_in_flight_count(),
+            )),
+            _host_memory: host_memory,
+        });
+
+        gfx.register_uniform_buffer(Arc::downgrade(&uniform_buffer) as _);
+
+        uniform_buffer
+    }
+
+    pub fn get_layout(&self) -> &Arc<DescriptorSetLayout> {
+        &self.layout
     }
 
     pub fn access_data(&self, accessing_function: impl FnOnce(&mut T)) {
+        match self.mutable_part.lock() {
+            Ok(mut mutex_guard) => mutex_guard.access(accessing_function),
+            Err(e) => {
+                println!("Uniform buffer mutex could not be locked! {e}");
+            }
+        }
+    }
+}
+
+impl<T> FlushPending for UniformBuffer<T>
+where
+    T: BufferContents + Clone,
+{
+    fn flush_pending(&self, in_flight_index: usize) {
         match self.mutable_part.lock() {
             Ok(mut mutex_guard) => {
-                // invalidate all subbuffers
-                mutex_guard
-                    .subbuffer_validity
-                    .iter_mut()
-                    .for_each(|p| *p = false);
-                accessing_function(&mut mutex_guard.staging_buffer);
+                if let Some(data) = mutex_guard.outdated_data(in_flight_index) {
+                    if let Ok(mut buffer) = self.subbuffers[in_flight_index].write() {
+                        *buffer = data.clone();
+                        mutex_guard.mark_valid(in_flight_index);
+                    }
+                }
             }
             Err(e) => {
                 println!("Uniform buffer mutex could not be locked! {e}");
@@ -165,31 +223,12 @@ where
     fn bind(
         &self,
         gfx: &Graphics,
-        builder: &mut AutoCommandBufferBuilder<
-            PrimaryAutoCommandBuffer,
-            StandardCommandBufferAllocator,
-        >,
+        builder: &mut CommandBufferBuilder,
         pipeline_layout: Arc<PipelineLayout>,
     ) {
+        // the data was already uploaded by Graphics::draw_frame
         let in_flight_index = gfx.get_in_flight_index();
 
-        match self.uniform_buffer_ref.mutable_part.lock() {
-            Ok(mut mutex_guard) => {
-                let valid = mutex_guard.subbuffer_validity[in_flight_index];
-                if !valid {
-                    if let Ok(mut buffer) =
-                        self.uniform_buffer_ref.subbuffers[in_flight_index].write()
-                    {
-                        *buffer = mutex_guard.staging_buffer.clone();
-                        mutex_guard.subbuffer_validity[in_flight_index] = true;
-                    }
-                }
-            }
-            Err(e) => {
-                println!("Uniform buffer mutex could not be locked! {e}");
-            }
-        }
-
         builder.bind_descriptor_sets(
             vulkano::pipeline::PipelineBindPoint::Graphics,
             pipeline_layout.clone(),
@@ -198,3 +237,37 @@ where
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn setting_a_tint_updates_every_frame_in_flight() {
+        // the same access DynamicTile::set_tint goes through
+        let mut staging = UniformBufferMutablePart::new([1.0f32, 1.0, 1.0, 1.0], 2);
+        assert_eq!(staging.outdated_data(0), None);
+        assert_eq!(staging.outdated_data(1), None);
+
+        staging.access(|tint| *tint = [1.0, 0.0, 0.0, 0.5]);
+        assert_eq!(staging.outdated_data(0), Some(&[1.0, 0.0, 0.0, 0.5]));
+        assert_eq!(staging.outdated_data(1), Some(&[1.0, 0.0, 0.0, 0.5]));
+
+        // flushing one frame leaves the other one pending
+        staging.mark_valid(0);
+        assert_eq!(staging.outdated_data(0), None);
+        assert_eq!(staging.outdated_data(1), Some(&[1.0, 0.0, 0.0, 0.5]));
+    }
+
+    #[test]
+    fn three_frames_in_flight_flush_three_subbuffers() {
+        let mut staging = UniformBufferMutablePart::new(0u32, 3);
+        staging.access(|frame| *frame = 1);
+
+        for in_flight_index in 0..3 {
+            assert_eq!(staging.outdated_data(in_flight_index), Some(&1));
+            staging.mark_valid(in_flight_index);
+        }
+        assert!((0..3).all(|in_flight_index| staging.outdated_data(in_flight_index).is_none()));
+    }
+}
@@ -1,16 +1,35 @@
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
-use cgmath::{Deg, Vector3};
+use cgmath::{Deg, Matrix4, SquareMatrix, Vector3, Vector4};
 use vulkano::shader::ShaderStages;
 
+use crate::math::Rect;
+
 use super::{bindable::UniformBuffer, shaders::vert_tile::CameraUbo, Graphics};
 
+const DEFAULT_ZOOM_LIMITS: [f32; 2] = [0.1, 10.0];
+// How quickly the zoom catches up with the target, higher is faster.
+// Each second the remaining distance shrinks by a factor of e^ZOOM_SMOOTHING.
+const ZOOM_SMOOTHING: f32 = 15.0;
+
 pub struct Camera {
     pub position: [f32; 2],
+    /// Moves towards the target zoom with `update_zoom`, set the target with `set_target_zoom`.
     pub zoom: f32,
     pub rotation: f32,
 
+    target_zoom: f32,
+    zoom_limits: [f32; 2],
+
+    // the world space area the view is kept inside of
+    bounds: Option<Rect>,
+    // the position after clamping to the bounds, as of the last buffer update
+    effective_position: [f32; 2],
+
     buffer: Arc<UniformBuffer<CameraUbo>>,
+    // world to cursor space and back, as of the last buffer update
+    matrix: Matrix4<f32>,
+    inverse_matrix: Matrix4<f32>,
 }
 
 impl Camera {
@@ -18,24 +37,23 @@ impl Camera {
         let buffer = UniformBuffer::new(
             gfx,
             0,
-            CameraUbo {
-                camera: (cgmath::Matrix4::from_scale(zoom)
-                    * cgmath::Matrix4::from_angle_z(Deg(rotation))
-                    * cgmath::Matrix4::from_translation(Vector3::new(
-                        -position[0],
-                        position[1],
-                        0.0,
-                    )))
-                .into(),
-            },
+            Self::create_ubo(position, zoom, rotation),
             ShaderStages::VERTEX,
         );
 
+        let matrix = Self::create_matrix(position, zoom, rotation);
+
         Self {
             position: position,
             zoom: zoom,
             rotation: rotation,
+            target_zoom: zoom,
+            zoom_limits: DEFAULT_ZOOM_LIMITS,
+            bounds: None,
+            effective_position: position,
             buffer: buffer,
+            matrix: matrix,
+            inverse_matrix: matrix.invert().unwrap_or(Matrix4::identity()),
         }
     }
 
@@ -43,16 +61,251 @@ impl Camera {
         self.buffer.clone()
     }
 
-    pub fn update_buffer(&mut self) {
+    /// Keeps the view inside of `bounds`, in world space. When the bounds are smaller than the view
+    /// they are centered instead. `position` itself is left alone, only what is drawn is clamped.
+    /// Rotation is ignored when clamping.
+    pub fn set_bounds(&mut self, bounds: Option<Rect>) {
+        self.bounds = bounds;
+    }
+
+    /// Keeps the zoom and the target zoom between `min` and `max`.
+    pub fn set_zoom_limits(&mut self, min: f32, max: f32) {
+        self.zoom_limits = [min, max.max(min)];
+        self.zoom = self.clamp_zoom(self.zoom);
+        self.target_zoom = self.clamp_zoom(self.target_zoom);
+    }
+
+    pub fn get_zoom_limits(&self) -> [f32; 2] {
+        self.zoom_limits
+    }
+
+    /// The zoom `update_zoom` moves towards, clamped to the zoom limits.
+    pub fn set_target_zoom(&mut self, target_zoom: f32) {
+        self.target_zoom = self.clamp_zoom(target_zoom);
+    }
+
+    pub fn get_target_zoom(&self) -> f32 {
+        self.target_zoom
+    }
+
+    /// Moves the zoom towards the target zoom. Call it once per tick with the tick duration.
+    pub fn update_zoom(&mut self, delta_time: Duration) {
+        self.zoom = Self::smooth_zoom(self.zoom, self.target_zoom, delta_time);
+    }
+
+    fn smooth_zoom(zoom: f32, target_zoom: f32, delta_time: Duration) -> f32 {
+        let remaining = (-ZOOM_SMOOTHING * delta_time.as_secs_f32()).exp();
+        let zoom = target_zoom + (zoom - target_zoom) * remaining;
+
+        // close enough to stop moving instead of creeping towards it forever
+        match (zoom - target_zoom).abs() < target_zoom * 0.001 {
+            true => target_zoom,
+            false => zoom,
+        }
+    }
+
+    fn clamp_zoom(&self, zoom: f32) -> f32 {
+        Self::clamp_to_limits(zoom, self.zoom_limits)
+    }
+
+    fn clamp_to_limits(zoom: f32, zoom_limits: [f32; 2]) -> f32 {
+        zoom.clamp(zoom_limits[0], zoom_limits[1])
+    }
+
+    pub fn update_buffer(&mut self, gfx: &Graphics) {
+        let viewport_size = gfx.get_logical_extent();
+        self.effective_position = match self.bounds {
+            Some(bounds) => Self::clamp_position(self.position, self.zoom, viewport_size, bounds),
+            None => self.position,
+        };
+
+        let ubo = Self::create_ubo(self.effective_position, self.zoom, self.rotation);
         self.buffer.access_data(|data| {
-            data.camera = (cgmath::Matrix4::from_scale(self.zoom)
-                * cgmath::Matrix4::from_angle_z(Deg(self.rotation))
-                * cgmath::Matrix4::from_translation(Vector3::new(
-                    -self.position[0],
-                    self.position[1],
-                    0.0,
-                )))
-            .into();
+            *data = ubo;
         });
+
+        self.matrix = Self::create_matrix(self.effective_position, self.zoom, self.rotation);
+        // a zoom of 0 can't be undone, keep the last usable inverse
+        if let Some(inverse_matrix) = self.matrix.invert() {
+            self.inverse_matrix = inverse_matrix;
+        }
+    }
+
+    /// Converts a position in the same space as `Mouse::cursor_position` to world space.
+    /// Uses the camera as of the last `update_buffer` call.
+    pub fn screen_to_world(&self, cursor: [f64; 2]) -> [f32; 2] {
+        Self::transform(&self.inverse_matrix, [cursor[0] as f32, cursor[1] as f32])
+    }
+
+    /// The inverse of `screen_to_world`.
+    pub fn world_to_screen(&self, world: [f32; 2]) -> [f64; 2] {
+        let cursor = Self::transform(&self.matrix, world);
+        [cursor[0] as f64, cursor[1] as f64]
+    }
+
+    /// The area of the world that ends up on screen.
+    /// `parallax_factor` is the factor of the layer being looked at, use `[1.0, 1.0]` for regular layers.
+    pub fn visible_area(&self, gfx: &Graphics, parallax_factor: [f32; 2]) -> Rect {
+        Self::view_area(
+            self.effective_position,
+            self.zoom,
+            self.rotation,
+            gfx.get_logical_extent(),
+            parallax_factor,
+        )
+    }
+
+    fn view_area(
+        position: [f32; 2],
+        zoom: f32,
+        rotation: f32,
+        logical_extent: [f32; 2],
+        parallax_factor: [f32; 2],
+    ) -> Rect {
+        let mut half_extent = [
+            logical_extent[0] / 2.0 / zoom,
+            logical_extent[1] / 2.0 / zoom,
+        ];
+
+        // a rotated view is covered by the circle around it
+        if rotation % 360.0 != 0.0 {
+            let radius = (half_extent[0] * half_extent[0] + half_extent[1] * half_extent[1]).sqrt();
+            half_extent = [radius, radius];
+        }
+
+        // world space y points up while the camera position uses y down
+        let center = [
+            position[0] * parallax_factor[0],
+            -position[1] * parallax_factor[1],
+        ];
+
+        Rect::from_center(center, half_extent)
+    }
+
+    fn clamp_position(
+        position: [f32; 2],
+        zoom: f32,
+        viewport_size: [f32; 2],
+        bounds: Rect,
+    ) -> [f32; 2] {
+        // world space y points up while the camera position uses y down
+        let center = [position[0], -position[1]];
+        let bounds_center = bounds.center();
+
+        let mut clamped = [0.0; 2];
+        for axis in 0..2 {
+            let half_view = viewport_size[axis] / 2.0 / zoom;
+            clamped[axis] = match bounds.max[axis] - bounds.min[axis] <= half_view * 2.0 {
+                true => bounds_center[axis],
+                false => {
+                    center[axis].clamp(bounds.min[axis] + half_view, bounds.max[axis] - half_view)
+                }
+            };
+        }
+
+        [clamped[0], -clamped[1]]
+    }
+
+    fn create_ubo(position: [f32; 2], zoom: f32, rotation: f32) -> CameraUbo {
+        CameraUbo {
+            camera: Self::create_matrix(position, zoom, rotation).into(),
+            translation: Self::translation(position),
+        }
+    }
+
+    fn create_matrix(position: [f32; 2], zoom: f32, rotation: f32) -> Matrix4<f32> {
+        let translation = Self::translation(position);
+        Matrix4::from_scale(zoom)
+            * Matrix4::from_angle_z(Deg(rotation))
+            * Matrix4::from_translation(Vector3::new(translation[0], translation[1], 0.0))
+    }
+
+    fn transform(matrix: &Matrix4<f32>, point: [f32; 2]) -> [f32; 2] {
+        let transformed = *matrix * Vector4::new(point[0], point[1], 0.0, 1.0);
+        [transformed.x, transformed.y]
+    }
+
+    // world space y points up while the camera position uses y down
+    fn translation(position: [f32; 2]) -> [f32; 2] {
+        [-position[0], position[1]]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: [f32; 2], b: [f32; 2]) {
+        assert!(
+            (a[0] - b[0]).abs() < 1e-3 && (a[1] - b[1]).abs() < 1e-3,
+            "{a:?} != {b:?}"
+        );
+    }
+
+    #[test]
+    fn world_to_screen_to_world_round_trips() {
+        let world = [37.5, -12.25];
+        for zoom in [0.25, 1.0, 3.5] {
+            let matrix = Camera::create_matrix([120.0, 48.0], zoom, 30.0);
+            let inverse_matrix = matrix.invert().unwrap();
+
+            let screen = Camera::transform(&matrix, world);
+            assert_close(Camera::transform(&inverse_matrix, screen), world);
+        }
+    }
+
+    #[test]
+    fn clamping_keeps_the_view_inside_larger_bounds() {
+        let map = Rect::new([0.0, -1000.0], [2000.0, 0.0]);
+        // at a zoom of 2.0 the view is 400 by 300 world units
+        let viewport_size = [800.0, 600.0];
+
+        let clamped = Camera::clamp_position([50.0, 20.0], 2.0, viewport_size, map);
+        assert_eq!(clamped, [200.0, 150.0]);
+
+        let view = Rect::from_center([clamped[0], -clamped[1]], [200.0, 150.0]);
+        assert_eq!(view.min[0], map.min[0]);
+        assert_eq!(view.max[1], map.max[1]);
+
+        // positions that already keep the view inside are left alone
+        let inside = Camera::clamp_position([1000.0, 500.0], 2.0, viewport_size, map);
+        assert_eq!(inside, [1000.0, 500.0]);
+    }
+
+    #[test]
+    fn bounds_smaller_than_the_view_are_centered() {
+        let map = Rect::new([0.0, -100.0], [100.0, 0.0]);
+        let clamped = Camera::clamp_position([-400.0, 900.0], 2.0, [800.0, 600.0], map);
+        assert_eq!(clamped, [50.0, 50.0]);
+    }
+
+    #[test]
+    fn zoom_stays_within_the_limits() {
+        let zoom_limits = [0.5, 4.0];
+        assert_eq!(Camera::clamp_to_limits(10.0, zoom_limits), 4.0);
+        assert_eq!(Camera::clamp_to_limits(0.1, zoom_limits), 0.5);
+        assert_eq!(Camera::clamp_to_limits(2.0, zoom_limits), 2.0);
+    }
+
+    #[test]
+    fn smooth_zoom_converges_on_the_target() {
+        let tick = Duration::from_secs(1) / 60;
+        let mut zoom = 1.0;
+        let mut ticks = 0;
+        while zoom != 3.0 {
+            let next = Camera::smooth_zoom(zoom, 3.0, tick);
+            // never overshoots
+            assert!(next > zoom && next <= 3.0);
+            zoom = next;
+            ticks += 1;
+            assert!(
+                ticks < 120,
+                "zoom {zoom} didn't reach the target in two seconds"
+            );
+        }
+
+        // zooming out converges from the other side
+        assert!(Camera::smooth_zoom(3.0, 1.0, tick) < 3.0);
+        assert_eq!(Camera::smooth_zoom(3.0, 1.0, Duration::from_secs(5)), 1.0);
     }
 }
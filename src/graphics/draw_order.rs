@@ -0,0 +1,120 @@
+use std::sync::{Arc, Weak};
+
+/// The passes a frame is recorded in, in this order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DrawPass {
+    /// Registered and persistent drawables, drawn once for every split screen viewport.
+    World,
+    /// Drawables queued with `Graphics::queue_overlay`.
+    Overlay,
+    /// Drawables queued on the `UiRenderer`, on top of everything else.
+    Ui,
+}
+
+impl DrawPass {
+    /// Screen space passes start from a cleared depth buffer, so the world never hides them.
+    pub fn is_screen_space(&self) -> bool {
+        *self != DrawPass::World
+    }
+}
+
+/// The drawables of a frame grouped by the pass they are drawn in, in recording order.
+/// Each pass keeps the order it was given, passes without drawables are left out.
+pub fn frame_draw_order<T>(world: Vec<T>, overlay: Vec<T>, ui: Vec<T>) -> Vec<(DrawPass, Vec<T>)> {
+    [
+        (DrawPass::World, world),
+        (DrawPass::Overlay, overlay),
+        (DrawPass::Ui, ui),
+    ]
+    .into_iter()
+    .filter(|(_, drawables)| !drawables.is_empty())
+    .collect()
+}
+
+/// Drawables on higher layers are drawn over lower ones. The sort is stable,
+/// so drawables on the same layer keep the order they were given in.
+pub fn sort_by_layer<T>(mut drawables: Vec<(i32, T)>) -> Vec<T> {
+    drawables.sort_by_key(|(layer, _)| *layer);
+    drawables
+        .into_iter()
+        .map(|(_, drawable)| drawable)
+        .collect()
+}
+
+/// The world pass of a frame: the registered drawables that are still alive followed by
+/// the persistent ones, without the hidden ones, sorted by layer.
+pub fn visible_world<T>(
+    registered: &[(i32, Weak<T>)],
+    persistent: Vec<(i32, Arc<T>)>,
+    is_visible: impl Fn(&T) -> bool,
+) -> Vec<Arc<T>> {
+    let drawables = registered
+        .iter()
+        .filter_map(|(layer, drawable)| Some((*layer, drawable.upgrade()?)))
+        .chain(persistent)
+        .filter(|(_, drawable)| is_visible(drawable))
+        .collect();
+    sort_by_layer(drawables)
+}
+
+/// One draw call for every drawable in `passes`, the world is drawn once per viewport.
+pub fn draw_call_count<T>(passes: &[(DrawPass, Vec<T>)], world_viewports: usize) -> u32 {
+    passes
+        .iter()
+        .map(|(pass, drawables)| match pass {
+            DrawPass::World => drawables.len() * world_viewports,
+            _ => drawables.len(),
+        })
+        .sum::<usize>() as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlay_is_drawn_after_world_and_before_ui() {
+        let order = frame_draw_order(vec!["tiles", "water"], vec!["hud"], vec!["button"]);
+        assert_eq!(
+            order,
+            vec![
+                (DrawPass::World, vec!["tiles", "water"]),
+                (DrawPass::Overlay, vec!["hud"]),
+                (DrawPass::Ui, vec!["button"]),
+            ]
+        );
+        assert!(!order[0].0.is_screen_space());
+        assert!(order[1].0.is_screen_space());
+    }
+
+    #[test]
+    fn world_layers_draw_the_character_over_the_map() {
+        // the character was registered before the map, but on a higher layer
+        let world = sort_by_layer(vec![(1, "character"), (0, "map"), (0, "water")]);
+        let order = frame_draw_order(world, Vec::new(), Vec::new());
+        assert_eq!(
+            order,
+            vec![(DrawPass::World, vec!["map", "water", "character"])]
+        );
+    }
+
+    #[test]
+    fn ui_layers_draw_the_tooltip_over_the_button() {
+        // what `UiRenderer::elements` does with the queue
+        let ui = sort_by_layer(vec![(1, "tooltip"), (0, "button"), (0, "label")]);
+        let order = frame_draw_order(vec!["world"], Vec::new(), ui);
+        assert_eq!(
+            order,
+            vec![
+                (DrawPass::World, vec!["world"]),
+                (DrawPass::Ui, vec!["button", "label", "tooltip"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_passes_are_skipped() {
+        let order = frame_draw_order(Vec::new(), vec!["hud"], Vec::new());
+        assert_eq!(order, vec![(DrawPass::Overlay, vec!["hud"])]);
+    }
+}
@@ -1,5 +1,14 @@
-use std::sync::{Arc, Weak};
-use vulkano::pipeline::{GraphicsPipeline, PipelineLayout};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use vulkano::buffer::Subbuffer;
+use vulkano::command_buffer::DrawIndexedIndirectCommand;
+use vulkano::device::Device;
+use vulkano::image::SampleCount;
+use vulkano::pipeline::{GraphicsPipeline, Pipeline, PipelineLayout};
+use vulkano::render_pass::Subpass;
+use vulkano::shader::ShaderModule;
+use winit::window::WindowId;
 
 use super::bindable::Bindable;
 use super::pipeline::PipelineBuilder;
@@ -9,24 +18,118 @@ pub trait Drawable {
     fn get_shared_bindables(&self) -> &Vec<Arc<dyn Bindable>>;
     fn get_pipeline(&self) -> Arc<GraphicsPipeline>;
     fn get_index_count(&self) -> u32;
+    /// Changes how many indices are drawn from the next frame on, without rebuilding the drawable.
+    fn set_index_count(&self, index_count: u32);
+    /// Taken from the first bindable that has an instance count, 1 otherwise.
+    fn get_instance_count(&self) -> u32 {
+        self.get_bindables()
+            .iter()
+            .chain(self.get_shared_bindables().iter())
+            .find_map(|bindable| bindable.instance_count())
+            .unwrap_or(1)
+    }
+    /// When set the drawable is drawn with the commands in this buffer instead of
+    /// the index and instance counts.
+    fn get_indirect_buffer(&self) -> Option<Subbuffer<[DrawIndexedIndirectCommand]>> {
+        self.get_bindables()
+            .iter()
+            .chain(self.get_shared_bindables().iter())
+            .find_map(|bindable| bindable.indirect_buffer())
+    }
     fn get_pipeline_layout(&self) -> Arc<PipelineLayout>;
 }
 
 pub struct DrawableSharedPart {
     pub bindables: Vec<Arc<dyn Bindable>>,
-    pub pipeline: Arc<GraphicsPipeline>,
+    pipeline: RwLock<Arc<GraphicsPipeline>>,
     pub layout: Arc<PipelineLayout>,
     pub index_count: u32,
+    // what the pipeline was built from, so it can be rebuilt with other shaders
+    pipeline_builder: Mutex<PipelineBuilder>,
+}
+
+impl DrawableSharedPart {
+    pub fn get_pipeline(&self) -> Arc<GraphicsPipeline> {
+        self.pipeline.read().unwrap().clone()
+    }
+
+    /// Rebuilds the pipeline if one of its shaders was compiled from a source in `modules`.
+    /// The layout is kept, so the bindables of existing drawables stay valid.
+    pub fn replace_shaders(
+        &self,
+        device: Arc<Device>,
+        modules: &HashMap<String, Arc<ShaderModule>>,
+    ) {
+        let mut builder = match self.pipeline_builder.lock() {
+            Ok(guard) => guard,
+            Err(e) => {
+                println!("Failed to access pipeline builder {e}");
+                return;
+            }
+        };
+
+        let mut replaced = false;
+        if let Some(module) = builder
+            .vertex_shader_source
+            .and_then(|path| modules.get(path))
+        {
+            builder.vertex_shader = Some(module.clone());
+            replaced = true;
+        }
+        if let Some(module) = builder
+            .fragment_shader_source
+            .and_then(|path| modules.get(path))
+        {
+            builder.fragment_shader = Some(module.clone());
+            replaced = true;
+        }
+        if !replaced {
+            return;
+        }
+
+        // a shader that failed to load the first time may have been fixed since
+        builder.shader_load_failed =
+            builder.vertex_shader.is_none() || builder.fragment_shader.is_none();
+
+        let pipeline = builder.build_with_layout(device, self.layout.clone());
+        match self.pipeline.write() {
+            Ok(mut guard) => *guard = pipeline,
+            Err(e) => println!("Failed to replace pipeline {e}"),
+        }
+    }
+
+    /// Rebuilds the pipeline for another render pass, like after the msaa sample count changed.
+    pub fn rebuild_for_subpass(&self, device: Arc<Device>, subpass: Subpass, samples: SampleCount) {
+        let mut builder = match self.pipeline_builder.lock() {
+            Ok(guard) => guard,
+            Err(e) => {
+                println!("Failed to access pipeline builder {e}");
+                return;
+            }
+        };
+
+        builder.subpass = subpass;
+        builder.multisample_state.rasterization_samples = samples;
+
+        let pipeline = builder.build_with_layout(device, self.layout.clone());
+        match self.pipeline.write() {
+            Ok(mut guard) => *guard = pipeline,
+            Err(e) => println!("Failed to replace pipeline {e}"),
+        }
+    }
 }
 
 pub struct GenericDrawable {
     bindables: Vec<Arc<dyn Bindable>>,
     shared_part: Arc<DrawableSharedPart>,
+    index_count: AtomicU32,
+    visible: AtomicBool,
 }
 
 pub struct DrawableEntry {
     entry: Arc<GenericDrawable>,
     pub registered_uid: Option<u32>,
+    pub registered_window: Option<WindowId>,
 }
 
 impl DrawableEntry {
@@ -36,9 +139,28 @@ impl DrawableEntry {
     pub fn get_arc(&self) -> Arc<GenericDrawable> {
         self.entry.clone()
     }
+    /// Hidden drawables stay registered but are skipped when recording the command buffer.
+    pub fn set_visible(&self, visible: bool) {
+        self.entry.visible.store(visible, Ordering::Relaxed);
+    }
+    pub fn is_visible(&self) -> bool {
+        self.entry.is_visible()
+    }
+    pub fn set_index_count(&self, index_count: u32) {
+        self.entry.set_index_count(index_count);
+    }
 }
 
 impl GenericDrawable {
+    /// Creates a drawable. This is the only way drawables are made, the lifecycle is:
+    ///
+    /// 1. `GenericDrawable::new` builds the per instance bindables every call. The shared
+    ///    bindables and the pipeline are only built the first time a call site is reached
+    ///    and are reused by later calls from the same place for as long as any instance lives.
+    /// 2. `Graphics::register_drawable` adds the returned entry to the draw list.
+    ///    Graphics only keeps a weak reference.
+    /// 3. The drawable is drawn every frame until it is unregistered with
+    ///    `Graphics::unregister_drawable` or the `DrawableEntry` is dropped.
     #[track_caller]
     pub fn new<Fn1, Fn2>(
         gfx: &super::Graphics,
@@ -59,35 +181,56 @@ impl GenericDrawable {
             None => None,
         };
 
+        // An index buffer in the per instance bindables takes precedence over a shared one,
+        // so instances sharing a pipeline can still draw different amounts of geometry.
         match shared_data {
-            Some(data) => DrawableEntry {
-                entry: Arc::new(Self {
-                    bindables: init_bindables(),
-                    shared_part: data,
-                }),
-                registered_uid: None,
-            },
-            None => {
-                let mut index_count = 0;
+            Some(data) => {
                 let bindables = init_bindables();
-                let shared_bindables = init_shared_bindables();
 
+                let mut index_count = data.index_count;
                 let mut pipeline_builder = PipelineBuilder::new(gfx);
-
                 for bindable in &bindables {
                     bindable.bind_to_pipeline(&mut pipeline_builder, &mut index_count);
                 }
+
+                DrawableEntry {
+                    entry: Arc::new(Self {
+                        bindables: bindables,
+                        shared_part: data,
+                        index_count: AtomicU32::new(index_count),
+                        visible: AtomicBool::new(true),
+                    }),
+                    registered_uid: None,
+                    registered_window: None,
+                }
+            }
+            None => {
+                let bindables = init_bindables();
+                let shared_bindables = init_shared_bindables();
+
+                let mut pipeline_builder = PipelineBuilder::new(gfx);
+
+                let mut shared_index_count = 0;
                 for bindable in &shared_bindables {
+                    bindable.bind_to_pipeline(&mut pipeline_builder, &mut shared_index_count);
+                }
+                let mut index_count = shared_index_count;
+                for bindable in &bindables {
                     bindable.bind_to_pipeline(&mut pipeline_builder, &mut index_count);
                 }
 
-                let (pipeline, layout) = pipeline_builder.build(gfx.get_device());
+                // another call site may already have built the same pipeline
+                let pipeline = gfx.get_or_build_pipeline(pipeline_builder.key(), || {
+                    pipeline_builder.build(gfx.get_device()).0
+                });
+                let layout = pipeline.layout().clone();
 
                 let shared_part = Arc::new(DrawableSharedPart {
-                    index_count: index_count,
+                    index_count: shared_index_count,
                     bindables: shared_bindables,
-                    pipeline: pipeline,
+                    pipeline: RwLock::new(pipeline),
                     layout: layout,
+                    pipeline_builder: Mutex::new(pipeline_builder),
                 });
 
                 gfx.cache_drawable_shared_part(caller_location, shared_part.clone());
@@ -96,12 +239,19 @@ impl GenericDrawable {
                     entry: Arc::new(Self {
                         bindables: bindables,
                         shared_part: shared_part,
+                        index_count: AtomicU32::new(index_count),
+                        visible: AtomicBool::new(true),
                     }),
                     registered_uid: None,
+                    registered_window: None,
                 }
             }
         }
     }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible.load(Ordering::Relaxed)
+    }
 }
 
 impl Drawable for GenericDrawable {
@@ -112,12 +262,65 @@ impl Drawable for GenericDrawable {
         &self.shared_part.bindables
     }
     fn get_pipeline(&self) -> Arc<GraphicsPipeline> {
-        self.shared_part.pipeline.clone()
+        self.shared_part.get_pipeline()
     }
     fn get_index_count(&self) -> u32 {
-        self.shared_part.index_count
+        self.index_count.load(Ordering::Relaxed)
+    }
+    fn set_index_count(&self, index_count: u32) {
+        self.index_count.store(index_count, Ordering::Relaxed);
     }
     fn get_pipeline_layout(&self) -> Arc<PipelineLayout> {
         self.shared_part.layout.clone()
     }
 }
+
+/// Implemented by drawables that own their `DrawableEntry`, so they can be kept in a `DrawablePool`.
+pub trait Poolable {
+    fn get_entry(&self) -> &DrawableEntry;
+}
+
+/// Keeps despawned drawables around so their buffers and descriptor sets can be reused
+/// by the next spawn instead of being allocated again.
+/// Released drawables stay registered but hidden, so they cost nothing to draw.
+pub struct DrawablePool<T>
+where
+    T: Poolable,
+{
+    free: Vec<T>,
+}
+
+impl<T> DrawablePool<T>
+where
+    T: Poolable,
+{
+    pub fn new() -> Self {
+        Self { free: Vec::new() }
+    }
+
+    /// Hands out a released drawable if there is one, otherwise creates a new one.
+    /// A reused drawable keeps the per instance data it had, reset it before use.
+    pub fn acquire(&mut self, create: impl FnOnce() -> T) -> T {
+        match self.free.pop() {
+            Some(item) => {
+                item.get_entry().set_visible(true);
+                item
+            }
+            None => create(),
+        }
+    }
+
+    pub fn release(&mut self, item: T) {
+        item.get_entry().set_visible(false);
+        self.free.push(item);
+    }
+
+    pub fn free_count(&self) -> usize {
+        self.free.len()
+    }
+
+    /// Drops released drawables until at most `count` are left.
+    pub fn shrink_to(&mut self, count: usize) {
+        self.free.truncate(count);
+    }
+}
@@ -0,0 +1,68 @@
+use std::{collections::HashMap, fs, path::Path, sync::Arc};
+
+use vulkano::{device::Device, shader::ShaderModule};
+
+const SOURCE_FOLDER: &str = "shaders/src";
+
+/// Compiles every shader in `shaders/src` the same way build.rs does.
+/// The modules are keyed by the `SOURCE_PATH` of their generated shader module.
+/// Shaders that fail to compile are reported and left out, so the pipelines using them keep their old module.
+pub fn compile_shaders(device: Arc<Device>) -> HashMap<String, Arc<ShaderModule>> {
+    let mut modules = HashMap::new();
+
+    let compiler = match shaderc::Compiler::new() {
+        Some(compiler) => compiler,
+        None => {
+            println!("Failed to create the shader compiler.");
+            return modules;
+        }
+    };
+
+    let shader_sources = match fs::read_dir(SOURCE_FOLDER) {
+        Ok(shader_sources) => shader_sources,
+        Err(e) => {
+            println!("Failed to read {SOURCE_FOLDER}: {e}");
+            return modules;
+        }
+    };
+
+    for file in shader_sources.filter_map(Result::ok) {
+        let file_path = file.path();
+        let shader_kind = match file_path.extension().and_then(|ext| ext.to_str()) {
+            Some("vert") => shaderc::ShaderKind::Vertex,
+            Some("frag") => shaderc::ShaderKind::Fragment,
+            Some("glsl") => shaderc::ShaderKind::InferFromSource,
+            _ => continue,
+        };
+
+        let source_path = format!("{SOURCE_FOLDER}/{}", file.file_name().to_string_lossy());
+        match compile_shader(&compiler, device.clone(), &file_path, shader_kind) {
+            Ok(module) => _ = modules.insert(source_path, module),
+            Err(e) => println!("Failed to reload {source_path}:\n{e}"),
+        }
+    }
+
+    modules
+}
+
+fn compile_shader(
+    compiler: &shaderc::Compiler,
+    device: Arc<Device>,
+    file_path: &Path,
+    shader_kind: shaderc::ShaderKind,
+) -> Result<Arc<ShaderModule>, String> {
+    let source = fs::read_to_string(file_path).map_err(|e| e.to_string())?;
+
+    let binary = compiler
+        .compile_into_spirv(
+            &source,
+            shader_kind,
+            &file_path.to_string_lossy(),
+            "main",
+            None,
+        )
+        .map_err(|e| e.to_string())?;
+
+    // the spir-v comes straight from shaderc, the same as the modules built into the binary
+    unsafe { ShaderModule::from_bytes(device, binary.as_binary_u8()) }.map_err(|e| e.to_string())
+}
@@ -0,0 +1,65 @@
+/// How many lights the light overlay draws, the rest of the lights queued for a frame are ignored.
+pub const MAX_LIGHTS: usize = 32;
+
+/// A light that brightens everything within `radius` of `position`, fading out towards the edge.
+/// Queue lights with `Graphics::add_light` every frame, they are drawn by a `LightOverlay`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Light {
+    /// In world space.
+    pub position: [f32; 2],
+    /// In world units.
+    pub radius: f32,
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+impl Light {
+    /// The radius is kept above 0, like the unused light slots of `LightOverlay`,
+    /// since the falloff divides by it.
+    pub fn new(position: [f32; 2], radius: f32, color: [f32; 3], intensity: f32) -> Self {
+        Self {
+            position: position,
+            radius: radius.max(f32::EPSILON),
+            color: color,
+            intensity: intensity,
+        }
+    }
+
+    /// How much the light adds to the ambient color at `point`, the same falloff `LightOverlay`
+    /// draws. Handy for gameplay that depends on being lit.
+    pub fn contribution_at(&self, point: [f32; 2]) -> [f32; 3] {
+        let offset = [point[0] - self.position[0], point[1] - self.position[1]];
+        let distance = (offset[0] * offset[0] + offset[1] * offset[1]).sqrt();
+        let falloff = (1.0 - distance / self.radius).clamp(0.0, 1.0);
+        self.color
+            .map(|channel| channel * self.intensity * falloff * falloff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_light_brightens_the_region_around_it() {
+        let torch = Light::new([10.0, -5.0], 4.0, [1.0, 0.5, 0.0], 2.0);
+
+        assert_eq!(torch.contribution_at([10.0, -5.0]), [2.0, 1.0, 0.0]);
+        // half way to the edge is a quarter as bright
+        assert_eq!(torch.contribution_at([12.0, -5.0]), [0.5, 0.25, 0.0]);
+        assert_eq!(torch.contribution_at([10.0, -1.0]), [0.0; 3]);
+        assert_eq!(torch.contribution_at([-10.0, 20.0]), [0.0; 3]);
+    }
+
+    #[test]
+    fn lights_without_a_radius_add_nothing_around_them() {
+        let spark = Light::new([3.0, 3.0], 0.0, [1.0, 1.0, 1.0], 1.0);
+
+        assert!(spark.radius > 0.0);
+        assert!(spark
+            .contribution_at([3.0, 3.0])
+            .iter()
+            .all(|channel| !channel.is_nan()));
+        assert_eq!(spark.contribution_at([4.0, 3.0]), [0.0; 3]);
+    }
+}
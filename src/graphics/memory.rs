@@ -0,0 +1,59 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+/// Keeps count of the host visible buffer memory that is in use,
+/// uniform buffers and staging buffers register themselves here.
+pub struct HostMemoryCounter {
+    used: AtomicU64,
+    // 0 means no budget
+    budget: AtomicU64,
+}
+
+/// Removes its size from the counter when dropped.
+/// Store it next to the buffer it was made for.
+pub struct HostMemoryAllocation {
+    counter: Arc<HostMemoryCounter>,
+    size: u64,
+}
+
+impl HostMemoryCounter {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            used: AtomicU64::new(0),
+            budget: AtomicU64::new(0),
+        })
+    }
+
+    /// Bytes of host visible memory currently in use.
+    pub fn get_used(&self) -> u64 {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    /// A warning is printed every time an allocation goes over the budget.
+    /// `None` removes the budget.
+    pub fn set_budget(&self, budget: Option<u64>) {
+        self.budget.store(budget.unwrap_or(0), Ordering::Relaxed);
+    }
+
+    pub fn allocate(self: &Arc<Self>, size: u64) -> HostMemoryAllocation {
+        let used = self.used.fetch_add(size, Ordering::Relaxed) + size;
+
+        let budget = self.budget.load(Ordering::Relaxed);
+        if budget != 0 && used > budget {
+            println!("Host visible memory is over budget: {used} / {budget} bytes.");
+        }
+
+        HostMemoryAllocation {
+            counter: self.clone(),
+            size: size,
+        }
+    }
+}
+
+impl Drop for HostMemoryAllocation {
+    fn drop(&mut self) {
+        self.counter.used.fetch_sub(self.size, Ordering::Relaxed);
+    }
+}
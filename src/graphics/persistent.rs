@@ -0,0 +1,131 @@
+/// Identifies a drawable added with `Graphics::add_persistent`.
+/// A handle stays invalid after its drawable is removed, even when the slot is reused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DrawHandle {
+    index: u32,
+    generation: u32,
+}
+
+struct Slot<T> {
+    generation: u32,
+    // the drawable and whether it is visible, None once removed
+    entry: Option<(T, bool)>,
+}
+
+/// Drawables owned by `Graphics` until they are removed, drawn every frame while visible.
+pub(super) struct PersistentDrawables<T> {
+    slots: Vec<Slot<T>>,
+}
+
+impl<T> PersistentDrawables<T> {
+    pub fn new() -> Self {
+        Self { slots: Vec::new() }
+    }
+
+    /// New drawables are visible. Slots of removed drawables are reused.
+    pub fn insert(&mut self, drawable: T) -> DrawHandle {
+        let index = match self.slots.iter().position(|slot| slot.entry.is_none()) {
+            Some(index) => index,
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    entry: None,
+                });
+                self.slots.len() - 1
+            }
+        };
+
+        let slot = &mut self.slots[index];
+        slot.entry = Some((drawable, true));
+        DrawHandle {
+            index: index as u32,
+            generation: slot.generation,
+        }
+    }
+
+    /// Returns false if the handle was already removed.
+    pub fn set_visible(&mut self, handle: DrawHandle, visible: bool) -> bool {
+        match self.get_mut(handle) {
+            Some((_, is_visible)) => {
+                *is_visible = visible;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_visible(&self, handle: DrawHandle) -> Option<bool> {
+        let slot = self.slots.get(handle.index as usize)?;
+        match (&slot.entry, slot.generation == handle.generation) {
+            (Some((_, visible)), true) => Some(*visible),
+            _ => None,
+        }
+    }
+
+    /// Returns the drawable, None if the handle was already removed.
+    pub fn remove(&mut self, handle: DrawHandle) -> Option<T> {
+        let slot = self.slots.get_mut(handle.index as usize)?;
+        if slot.generation != handle.generation {
+            return None;
+        }
+        let (drawable, _) = slot.entry.take()?;
+        // handles to the removed drawable must not match whatever takes the slot next
+        slot.generation = slot.generation.wrapping_add(1);
+        Some(drawable)
+    }
+
+    /// The visible drawables in the order they were added, reused slots keep their place.
+    pub fn visible(&self) -> impl Iterator<Item = &T> {
+        self.slots.iter().filter_map(|slot| match &slot.entry {
+            Some((drawable, true)) => Some(drawable),
+            _ => None,
+        })
+    }
+
+    fn get_mut(&mut self, handle: DrawHandle) -> Option<&mut (T, bool)> {
+        let slot = self.slots.get_mut(handle.index as usize)?;
+        match slot.generation == handle.generation {
+            true => slot.entry.as_mut(),
+            false => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hiding_a_drawable_removes_it_from_the_draw_list() {
+        let mut drawables = PersistentDrawables::new();
+        let first = drawables.insert("first");
+        let second = drawables.insert("second");
+        assert_eq!(drawables.visible().count(), 2);
+
+        assert!(drawables.set_visible(first, false));
+        assert_eq!(drawables.visible().collect::<Vec<_>>(), vec![&"second"]);
+        assert_eq!(drawables.is_visible(first), Some(false));
+
+        assert!(drawables.set_visible(first, true));
+        assert_eq!(
+            drawables.visible().collect::<Vec<_>>(),
+            vec![&"first", &"second"]
+        );
+        assert_eq!(drawables.is_visible(second), Some(true));
+    }
+
+    #[test]
+    fn removed_handles_stay_invalid_when_the_slot_is_reused() {
+        let mut drawables = PersistentDrawables::new();
+        let removed = drawables.insert(1);
+        assert_eq!(drawables.remove(removed), Some(1));
+        assert_eq!(drawables.visible().count(), 0);
+
+        let reused = drawables.insert(2);
+        assert_ne!(removed, reused);
+        assert_eq!(drawables.remove(removed), None);
+        assert!(!drawables.set_visible(removed, false));
+        assert_eq!(drawables.is_visible(removed), None);
+        assert_eq!(drawables.visible().collect::<Vec<_>>(), vec![&2]);
+    }
+}
@@ -1,42 +1,62 @@
-use std::sync::Arc;
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    hash::Hash,
+    mem::Discriminant,
+    sync::{Arc, Weak},
+};
+
 use vulkano::{
-    descriptor_set::layout::DescriptorSetLayout,
+    descriptor_set::layout::{DescriptorSetLayout, DescriptorType},
     device::Device,
+    format::Format,
+    image::SampleCount,
     pipeline::{
+        cache::PipelineCache,
         graphics::{
-            color_blend::ColorBlendState,
-            depth_stencil::DepthStencilState,
+            color_blend::{BlendFactor, BlendOp, ColorBlendState, ColorComponents},
+            depth_stencil::{CompareOp, DepthState, DepthStencilState},
             discard_rectangle::DiscardRectangleState,
-            input_assembly::InputAssemblyState,
+            input_assembly::{InputAssemblyState, PrimitiveTopology},
             multisample::MultisampleState,
-            rasterization::{CullMode, FrontFace, RasterizationState},
+            rasterization::{CullMode, FrontFace, PolygonMode, RasterizationState},
             render_pass::PipelineRenderPassType,
             tessellation::TessellationState,
-            vertex_input::VertexBufferDescription,
+            vertex_input::{VertexBufferDescription, VertexInputRate},
             viewport::ViewportState,
+            GraphicsPipelineCreationError,
         },
         layout::{PipelineLayoutCreateInfo, PushConstantRange},
-        GraphicsPipeline, PipelineLayout, StateMode,
+        GraphicsPipeline, PartialStateMode, PipelineLayout, StateMode,
     },
     render_pass::Subpass,
-    shader::ShaderModule,
+    shader::{ShaderModule, ShaderStages},
 };
 
-use super::Graphics;
+use super::{
+    shaders::{frag_error, vert_error},
+    Graphics,
+};
 
 pub struct PipelineBuilder {
     pub subpass: Subpass,
     pub vertex_buffer_description: Option<VertexBufferDescription>,
+    pub instance_buffer_description: Option<VertexBufferDescription>,
     pub input_assembly_state: InputAssemblyState,
     pub vertex_shader: Option<Arc<ShaderModule>>,
     pub fragment_shader: Option<Arc<ShaderModule>>,
+    pub vertex_shader_source: Option<&'static str>,
+    pub fragment_shader_source: Option<&'static str>,
+    pub shader_load_failed: bool,
     pub viewport_state: ViewportState,
     pub color_blend_state: ColorBlendState,
     pub rasterization_state: RasterizationState,
     pub depth_stencil_state: DepthStencilState,
+    depth_compare_op: CompareOp,
     pub discard_rectangle_state: DiscardRectangleState,
     pub multisample_state: MultisampleState,
     pub tessellation_state: TessellationState,
+    pipeline_cache: Arc<PipelineCache>,
 
     descriptor_set_layouts: Vec<Option<Arc<DescriptorSetLayout>>>,
     pub push_constant_ranges: Vec<PushConstantRange>,
@@ -47,11 +67,18 @@ impl PipelineBuilder {
         Self {
             subpass: Subpass::from(gfx.get_main_render_pass(), 0).unwrap(),
             vertex_buffer_description: None,
+            instance_buffer_description: None,
             input_assembly_state: InputAssemblyState::new(),
             vertex_shader: None,
             fragment_shader: None,
+            vertex_shader_source: None,
+            fragment_shader_source: None,
+            shader_load_failed: false,
             viewport_state: ViewportState::viewport_dynamic_scissor_irrelevant(),
             color_blend_state: ColorBlendState::default(),
+            // Triangles are wound clockwise as seen on screen. With the y up coordinates of
+            // cartesian_to_normalized a quad is top left, top right, bottom left, then
+            // bottom left, top right, bottom right. Use the Rasterization bindable to change this.
             rasterization_state: RasterizationState {
                 cull_mode: StateMode::Fixed(CullMode::Back),
                 front_face: StateMode::Fixed(FrontFace::Clockwise),
@@ -59,15 +86,32 @@ impl PipelineBuilder {
                 ..Default::default()
             },
             depth_stencil_state: DepthStencilState::disabled(),
+            depth_compare_op: gfx.get_depth_mode().compare_op(),
             discard_rectangle_state: DiscardRectangleState::new(),
-            multisample_state: MultisampleState::new(),
+            multisample_state: MultisampleState {
+                rasterization_samples: gfx.get_msaa_samples(),
+                ..MultisampleState::new()
+            },
             tessellation_state: TessellationState::new(),
+            pipeline_cache: gfx.get_pipeline_cache(),
 
             descriptor_set_layouts: Vec::new(),
             push_constant_ranges: Vec::new(),
         }
     }
 
+    /// Enables depth testing and writing, comparing in the direction of the current `DepthMode`.
+    pub fn enable_depth_test(&mut self) {
+        self.depth_stencil_state = DepthStencilState {
+            depth: Some(DepthState {
+                enable_dynamic: false,
+                write_enable: StateMode::Fixed(true),
+                compare_op: StateMode::Fixed(self.depth_compare_op),
+            }),
+            ..DepthStencilState::disabled()
+        };
+    }
+
     pub fn add_descriptor_set_layout(&mut self, set_num: u32, layout: Arc<DescriptorSetLayout>) {
         let set_num = set_num as usize;
 
@@ -77,27 +121,100 @@ impl PipelineBuilder {
         self.descriptor_set_layouts[set_num] = Some(layout);
     }
 
-    pub fn build(self, device: Arc<Device>) -> (Arc<GraphicsPipeline>, Arc<PipelineLayout>) {
-        let vertex_shader_entry = self
-            .vertex_shader
-            .as_ref()
-            .expect("No vertex shader supplied.")
-            .entry_point("main")
-            .unwrap();
+    /// Identifies the pipeline this builder builds. Builders with the same key build pipelines
+    /// that can be used in place of each other, even if they were set up at different call sites.
+    pub fn key(&self) -> PipelineKey {
+        let color_blend = self
+            .color_blend_state
+            .attachments
+            .iter()
+            .map(|attachment| {
+                let blend = attachment.blend.as_ref().map(|blend| {
+                    [
+                        (blend.color_op, blend.color_source, blend.color_destination),
+                        (blend.alpha_op, blend.alpha_source, blend.alpha_destination),
+                    ]
+                });
+                (blend, attachment.color_write_mask)
+            })
+            .collect();
+
+        let depth = self.depth_stencil_state.depth.as_ref().map(|depth| {
+            (
+                depth.enable_dynamic,
+                fixed(&depth.write_enable),
+                fixed(&depth.compare_op),
+            )
+        });
+
+        let set_layouts = self
+            .descriptor_set_layouts
+            .iter()
+            .map(|layout| {
+                layout.as_ref().map(|layout| {
+                    layout
+                        .bindings()
+                        .iter()
+                        .map(|(binding_num, binding)| BindingKey {
+                            binding_num: *binding_num,
+                            descriptor_type: binding.descriptor_type,
+                            descriptor_count: binding.descriptor_count,
+                            variable_descriptor_count: binding.variable_descriptor_count,
+                            stages: binding.stages,
+                            immutable_samplers: binding
+                                .immutable_samplers
+                                .iter()
+                                .map(|sampler| Arc::as_ptr(sampler) as usize)
+                                .collect(),
+                        })
+                        .collect()
+                })
+            })
+            .collect();
 
-        let fragment_shader_entry = self
-            .fragment_shader
-            .as_ref()
-            .expect("No fragment shader supplied.")
-            .entry_point("main")
-            .unwrap();
+        PipelineKey {
+            vertex_shader: shader_key(&self.vertex_shader, self.vertex_shader_source),
+            fragment_shader: shader_key(&self.fragment_shader, self.fragment_shader_source),
+            shader_load_failed: self.shader_load_failed,
+            vertex_buffer: self
+                .vertex_buffer_description
+                .as_ref()
+                .map(vertex_buffer_key),
+            instance_buffer: self
+                .instance_buffer_description
+                .as_ref()
+                .map(vertex_buffer_key),
+            topology: match self.input_assembly_state.topology {
+                PartialStateMode::Fixed(topology) => Some(topology),
+                PartialStateMode::Dynamic(_) => None,
+            },
+            primitive_restart: fixed(&self.input_assembly_state.primitive_restart_enable),
+            viewport: std::mem::discriminant(&self.viewport_state),
+            color_blend: color_blend,
+            cull_mode: fixed(&self.rasterization_state.cull_mode),
+            front_face: fixed(&self.rasterization_state.front_face),
+            polygon_mode: self.rasterization_state.polygon_mode,
+            depth: depth,
+            samples: self.multisample_state.rasterization_samples,
+            set_layouts: set_layouts,
+            push_constant_ranges: self
+                .push_constant_ranges
+                .iter()
+                .map(|range| (range.stages, range.offset, range.size))
+                .collect(),
+        }
+    }
 
+    /// Builds the pipeline. If a shader failed to load or the pipeline can't be created
+    /// the error shaders are used instead, so the drawable shows up magenta rather than
+    /// taking the whole app down.
+    pub fn build(&self, device: Arc<Device>) -> (Arc<GraphicsPipeline>, Arc<PipelineLayout>) {
         let set_layouts = self
             .descriptor_set_layouts
-            .into_iter()
+            .iter()
             .enumerate()
             .map(|(set_num, opt)| match opt {
-                Some(v) => v,
+                Some(v) => v.clone(),
                 None => panic!("Descriptor set with set_num: {set_num} is missing!"),
             })
             .collect();
@@ -106,29 +223,337 @@ impl PipelineBuilder {
             device.clone(),
             PipelineLayoutCreateInfo {
                 set_layouts: set_layouts,
-                push_constant_ranges: self.push_constant_ranges,
+                push_constant_ranges: self.push_constant_ranges.clone(),
                 ..Default::default()
             },
         )
         .unwrap();
 
-        (
-            GraphicsPipeline::start()
-                .render_pass(PipelineRenderPassType::BeginRenderPass(self.subpass))
-                .vertex_input_state(self.vertex_buffer_description.unwrap())
-                .input_assembly_state(self.input_assembly_state)
-                .vertex_shader(vertex_shader_entry, ())
-                .fragment_shader(fragment_shader_entry, ())
-                .viewport_state(self.viewport_state)
-                .color_blend_state(self.color_blend_state)
-                .rasterization_state(self.rasterization_state)
-                .depth_stencil_state(self.depth_stencil_state)
-                .discard_rectangle_state(self.discard_rectangle_state)
-                .multisample_state(self.multisample_state)
-                .tessellation_state(self.tessellation_state)
-                .with_pipeline_layout(device.clone(), layout.clone())
-                .expect("Failed to create pipeline!"),
-            layout,
-        )
+        (self.build_with_layout(device, layout.clone()), layout)
+    }
+
+    /// Builds the pipeline for an existing layout, so descriptor sets made for it stay usable.
+    pub fn build_with_layout(
+        &self,
+        device: Arc<Device>,
+        layout: Arc<PipelineLayout>,
+    ) -> Arc<GraphicsPipeline> {
+        let (vertex_shader, fragment_shader) = match self.shader_load_failed {
+            false => (
+                self.vertex_shader
+                    .clone()
+                    .expect("No vertex shader supplied."),
+                self.fragment_shader
+                    .clone()
+                    .expect("No fragment shader supplied."),
+            ),
+            // the vertex shader is kept when it loaded so the geometry stays in place
+            true => (
+                self.vertex_shader
+                    .clone()
+                    .unwrap_or_else(|| vert_error::load(device.clone()).unwrap()),
+                frag_error::load(device.clone()).unwrap(),
+            ),
+        };
+
+        match self.create_pipeline(
+            device.clone(),
+            layout.clone(),
+            &vertex_shader,
+            &fragment_shader,
+        ) {
+            Ok(pipeline) => pipeline,
+            Err(e) => {
+                println!("Failed to create pipeline, using the error shaders instead: {e}");
+                self.create_pipeline(
+                    device.clone(),
+                    layout,
+                    &vert_error::load(device.clone()).unwrap(),
+                    &frag_error::load(device.clone()).unwrap(),
+                )
+                .expect("Failed to create error pipeline!")
+            }
+        }
+    }
+
+    fn create_pipeline(
+        &self,
+        device: Arc<Device>,
+        layout: Arc<PipelineLayout>,
+        vertex_shader: &Arc<ShaderModule>,
+        fragment_shader: &Arc<ShaderModule>,
+    ) -> Result<Arc<GraphicsPipeline>, GraphicsPipelineCreationError> {
+        let vertex_input_state = match &self.instance_buffer_description {
+            Some(instance_description) => vec![
+                self.vertex_buffer_description.clone().unwrap(),
+                instance_description.clone(),
+            ],
+            None => vec![self.vertex_buffer_description.clone().unwrap()],
+        };
+
+        GraphicsPipeline::start()
+            .render_pass(PipelineRenderPassType::BeginRenderPass(
+                self.subpass.clone(),
+            ))
+            .vertex_input_state(vertex_input_state)
+            .input_assembly_state(self.input_assembly_state.clone())
+            .vertex_shader(vertex_shader.entry_point("main").unwrap(), ())
+            .fragment_shader(fragment_shader.entry_point("main").unwrap(), ())
+            .viewport_state(self.viewport_state.clone())
+            .color_blend_state(self.color_blend_state.clone())
+            .rasterization_state(self.rasterization_state.clone())
+            .depth_stencil_state(self.depth_stencil_state.clone())
+            .discard_rectangle_state(self.discard_rectangle_state.clone())
+            .multisample_state(self.multisample_state.clone())
+            .tessellation_state(self.tessellation_state.clone())
+            .build_with_cache(self.pipeline_cache.clone())
+            .with_pipeline_layout(device, layout)
+    }
+}
+
+/// What a pipeline is built from, compared field by field so call sites that set up the same
+/// state share one pipeline. State no bindable changes, like tessellation and discard rectangles,
+/// is left out. Scissors and viewports only count as fixed or dynamic.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    vertex_shader: ShaderKey,
+    fragment_shader: ShaderKey,
+    shader_load_failed: bool,
+    vertex_buffer: Option<VertexBufferKey>,
+    instance_buffer: Option<VertexBufferKey>,
+    // None when dynamic
+    topology: Option<PrimitiveTopology>,
+    primitive_restart: Option<bool>,
+    viewport: Discriminant<ViewportState>,
+    // the blend and write mask of every attachment
+    color_blend: Vec<(Option<BlendKey>, ColorComponents)>,
+    cull_mode: Option<CullMode>,
+    front_face: Option<FrontFace>,
+    polygon_mode: PolygonMode,
+    // dynamic enable, write enable and compare op
+    depth: Option<(bool, Option<bool>, Option<CompareOp>)>,
+    samples: SampleCount,
+    set_layouts: Vec<Option<Vec<BindingKey>>>,
+    // stages, offset and size
+    push_constant_ranges: Vec<(ShaderStages, u32, u32)>,
+}
+
+// the op, source and destination of the color and then the alpha blend
+type BlendKey = [(BlendOp, BlendFactor, BlendFactor); 2];
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum ShaderKey {
+    Source(&'static str),
+    // the address of a module that wasn't loaded from a source file
+    Module(usize),
+    Missing,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct VertexBufferKey {
+    // name, offset, format and element count, sorted by name
+    members: Vec<(String, usize, Format, u32)>,
+    stride: u32,
+    // None for per vertex data, the divisor for per instance data
+    instance_divisor: Option<u32>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct BindingKey {
+    binding_num: u32,
+    descriptor_type: DescriptorType,
+    descriptor_count: u32,
+    variable_descriptor_count: bool,
+    stages: ShaderStages,
+    // samplers are compared by address, textures with their own sampler get their own pipeline
+    immutable_samplers: Vec<usize>,
+}
+
+fn vertex_buffer_key(description: &VertexBufferDescription) -> VertexBufferKey {
+    let mut members: Vec<_> = description
+        .members
+        .iter()
+        .map(|(name, member)| {
+            (
+                name.clone(),
+                member.offset,
+                member.format,
+                member.num_elements,
+            )
+        })
+        .collect();
+    members.sort_by(|a, b| a.0.cmp(&b.0));
+
+    VertexBufferKey {
+        members: members,
+        stride: description.stride,
+        instance_divisor: match description.input_rate {
+            VertexInputRate::Vertex => None,
+            VertexInputRate::Instance { divisor } => Some(divisor),
+        },
+    }
+}
+
+// shaders are loaded again for every call site, so the source tells more than the module
+fn shader_key(module: &Option<Arc<ShaderModule>>, source: Option<&'static str>) -> ShaderKey {
+    match (source, module) {
+        (Some(source), Some(_)) => ShaderKey::Source(source),
+        (None, Some(module)) => ShaderKey::Module(Arc::as_ptr(module) as usize),
+        (_, None) => ShaderKey::Missing,
+    }
+}
+
+// None when the state is dynamic
+fn fixed<T: Copy>(state: &StateMode<T>) -> Option<T> {
+    match state {
+        StateMode::Fixed(value) => Some(*value),
+        StateMode::Dynamic => None,
+    }
+}
+
+/// Pipelines shared between call sites, by the key of the builder that made them.
+/// Only weak references are kept, so a pipeline nothing uses anymore is built again next time.
+/// Holds whatever the key builds, `Graphics` keeps `GraphicsPipeline`s by `PipelineKey`.
+pub struct PipelineMap<K, P> {
+    pipelines: HashMap<K, Weak<P>>,
+}
+
+impl<K, P> PipelineMap<K, P>
+where
+    K: Eq + Hash,
+{
+    pub fn new() -> Self {
+        Self {
+            pipelines: HashMap::new(),
+        }
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<Arc<P>>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.pipelines.get(key).and_then(|weak| weak.upgrade())
+    }
+
+    pub fn insert(&mut self, key: K, pipeline: &Arc<P>) {
+        // pipelines nobody uses anymore are only removed here, there are few of them
+        self.pipelines.retain(|_, weak| weak.strong_count() > 0);
+        self.pipelines.insert(key, Arc::downgrade(pipeline));
+    }
+
+    /// The pipeline cached for `key`, or the one `build` makes, which is cached for the next caller.
+    pub fn get_or_insert_with(&mut self, key: K, build: impl FnOnce() -> Arc<P>) -> Arc<P> {
+        match self.get(&key) {
+            Some(pipeline) => pipeline,
+            None => {
+                let pipeline = build();
+                self.insert(key, &pipeline);
+                pipeline
+            }
+        }
+    }
+
+    /// Forgets every pipeline, for when the shaders or the render pass they were built for change.
+    /// Pipelines that are still in use keep working, they just aren't shared with new call sites.
+    pub fn clear(&mut self) {
+        self.pipelines.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reload_invalidates_cached_pipelines() {
+        let mut pipeline_map: PipelineMap<String, u32> = PipelineMap::new();
+        let pipeline = Arc::new(1);
+        pipeline_map.insert("textured".to_string(), &pipeline);
+        assert!(Arc::ptr_eq(
+            &pipeline_map.get("textured").unwrap(),
+            &pipeline
+        ));
+
+        // what Graphics::reload_shaders does after rebuilding the pipelines
+        pipeline_map.clear();
+        assert!(pipeline_map.get("textured").is_none());
+
+        // the next call site builds a new pipeline with the reloaded shaders and caches that
+        let reloaded = Arc::new(2);
+        pipeline_map.insert("textured".to_string(), &reloaded);
+        assert_eq!(pipeline_map.get("textured"), Some(reloaded));
+    }
+
+    // what the call sites of textured tiles set up, without needing a device
+    fn textured_tile_key() -> PipelineKey {
+        PipelineKey {
+            vertex_shader: ShaderKey::Source("shaders/src/tile.vert"),
+            fragment_shader: ShaderKey::Source("shaders/src/textured.frag"),
+            shader_load_failed: false,
+            vertex_buffer: Some(VertexBufferKey {
+                members: vec![
+                    ("pos".to_string(), 0, Format::R32G32_SFLOAT, 1),
+                    ("uv".to_string(), 8, Format::R32G32_SFLOAT, 1),
+                ],
+                stride: 16,
+                instance_divisor: None,
+            }),
+            instance_buffer: None,
+            topology: Some(PrimitiveTopology::TriangleList),
+            primitive_restart: Some(false),
+            viewport: std::mem::discriminant(&ViewportState::viewport_dynamic_scissor_irrelevant()),
+            color_blend: vec![(None, ColorComponents::empty())],
+            cull_mode: Some(CullMode::Back),
+            front_face: Some(FrontFace::Clockwise),
+            polygon_mode: PolygonMode::Fill,
+            depth: None,
+            samples: SampleCount::Sample1,
+            set_layouts: vec![Some(vec![BindingKey {
+                binding_num: 0,
+                descriptor_type: DescriptorType::UniformBuffer,
+                descriptor_count: 1,
+                variable_descriptor_count: false,
+                stages: ShaderStages::VERTEX,
+                immutable_samplers: Vec::new(),
+            }])],
+            push_constant_ranges: vec![(ShaderStages::VERTEX, 0, 80)],
+        }
+    }
+
+    #[test]
+    fn identical_call_sites_share_one_pipeline() {
+        let mut pipeline_map = PipelineMap::new();
+        let mut builds = 0;
+
+        let first = pipeline_map.get_or_insert_with(textured_tile_key(), || {
+            builds += 1;
+            Arc::new(builds)
+        });
+        let second = pipeline_map.get_or_insert_with(textured_tile_key(), || {
+            builds += 1;
+            Arc::new(builds)
+        });
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(builds, 1);
+
+        // a call site that only differs in culling gets a pipeline of its own
+        let mut no_culling = textured_tile_key();
+        no_culling.cull_mode = Some(CullMode::None);
+        let third = pipeline_map.get_or_insert_with(no_culling, || {
+            builds += 1;
+            Arc::new(builds)
+        });
+        assert!(!Arc::ptr_eq(&first, &third));
+        assert_eq!(builds, 2);
+    }
+
+    #[test]
+    fn dropped_pipelines_are_not_returned() {
+        let mut pipeline_map: PipelineMap<String, u32> = PipelineMap::new();
+        let pipeline = Arc::new(1);
+        pipeline_map.insert("textured".to_string(), &pipeline);
+        drop(pipeline);
+        assert!(pipeline_map.get("textured").is_none());
     }
 }
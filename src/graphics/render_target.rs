@@ -0,0 +1,239 @@
+use std::sync::{Arc, Weak};
+
+use vulkano::{
+    device::Device,
+    image::SwapchainImage,
+    memory::allocator::StandardMemoryAllocator,
+    render_pass::{Framebuffer, RenderPass},
+    swapchain::{Surface, Swapchain, SwapchainCreateInfo, SwapchainCreationError},
+    sync::GpuFuture,
+};
+use winit::window::Window;
+
+use super::{
+    create_depth_buffer, create_framebuffers, create_image_views, create_msaa_buffers,
+    create_swapchain, drawable::GenericDrawable, FramebufferError, GraphicsInitError,
+};
+
+/// Everything that is needed to present to one window.
+/// The device, allocators and render passes are shared between all windows.
+pub(super) struct RenderTarget {
+    pub surface: Arc<Surface>,
+    pub window: Arc<Window>,
+    pub swapchain: Arc<Swapchain>,
+    pub framebuffers: Vec<Arc<Framebuffer>>,
+    // with the layer they were registered on
+    pub registered_drawables: Vec<(i32, Weak<GenericDrawable>)>,
+
+    pub futures: Vec<Option<Box<dyn GpuFuture>>>,
+}
+
+impl RenderTarget {
+    pub fn new(
+        device: Arc<Device>,
+        allocator: &StandardMemoryAllocator,
+        surface: Arc<Surface>,
+        render_pass: Arc<RenderPass>,
+        in_flight_count: usize,
+    ) -> Result<Self, GraphicsInitError> {
+        let (swapchain, swapchain_images) = create_swapchain(device.clone(), surface.clone())?;
+
+        println!("Swapchain is using {:?} images.", swapchain.image_count());
+
+        let framebuffers = create_target_framebuffers(
+            device,
+            allocator,
+            &swapchain,
+            &swapchain_images,
+            render_pass,
+        )
+        .map_err(GraphicsInitError::Framebuffer)?;
+
+        let window = surface
+            .object()
+            .and_then(|object| object.clone().downcast().ok())
+            .ok_or(GraphicsInitError::UnsupportedSurface("no window"))?;
+
+        Ok(Self {
+            surface: surface,
+            window: window,
+            swapchain: swapchain,
+            framebuffers: framebuffers,
+            registered_drawables: Vec::new(),
+
+            futures: empty_futures(in_flight_count),
+        })
+    }
+
+    /// Minimized windows can't be presented to.
+    pub fn is_drawable(&self) -> bool {
+        let extent = self.window.inner_size();
+        let minimized = self.window.is_minimized().unwrap_or(false);
+        !minimized && extent.width != 0 && extent.height != 0
+    }
+
+    /// Waits for every frame that is still in flight.
+    pub fn wait_idle(&mut self) {
+        for future in &mut self.futures {
+            if let Some(future) = future.take() {
+                if let Ok(fence_future) = future.then_signal_fence_and_flush() {
+                    fence_future.wait(None).unwrap();
+                }
+            }
+        }
+    }
+
+    pub fn recreate_swapchain(
+        &mut self,
+        device: Arc<Device>,
+        allocator: &StandardMemoryAllocator,
+        render_pass: Arc<RenderPass>,
+    ) {
+        self.wait_idle();
+
+        let capabilities = device
+            .physical_device()
+            .surface_capabilities(self.surface.as_ref(), Default::default())
+            .unwrap();
+
+        let extent: [u32; 2] = match capabilities.current_extent {
+            Some(current) => current,
+            None => {
+                let framebuffer_extent = self.window.inner_size();
+                [
+                    framebuffer_extent.width.clamp(
+                        capabilities.min_image_extent[0],
+                        capabilities.max_image_extent[0],
+                    ),
+                    framebuffer_extent.height.clamp(
+                        capabilities.min_image_extent[1],
+                        capabilities.max_image_extent[1],
+                    ),
+                ]
+            }
+        };
+
+        let create_info = SwapchainCreateInfo {
+            image_extent: extent,
+            ..self.swapchain.create_info()
+        };
+
+        self.recreate_with(device, allocator, render_pass, create_info);
+    }
+
+    /// Recreates the swapchain with at least `min_image_count` images, clamped to what the surface supports.
+    /// Returns how many images the new swapchain has.
+    pub fn set_min_image_count(
+        &mut self,
+        device: Arc<Device>,
+        allocator: &StandardMemoryAllocator,
+        render_pass: Arc<RenderPass>,
+        min_image_count: u32,
+    ) -> u32 {
+        self.wait_idle();
+
+        let capabilities = device
+            .physical_device()
+            .surface_capabilities(self.surface.as_ref(), Default::default())
+            .unwrap();
+        let min_image_count = clamp_image_count(
+            min_image_count,
+            capabilities.min_image_count,
+            capabilities.max_image_count,
+        );
+
+        let create_info = SwapchainCreateInfo {
+            min_image_count: min_image_count,
+            ..self.swapchain.create_info()
+        };
+
+        self.recreate_with(device, allocator, render_pass, create_info);
+        self.swapchain.image_count()
+    }
+
+    fn recreate_with(
+        &mut self,
+        device: Arc<Device>,
+        allocator: &StandardMemoryAllocator,
+        render_pass: Arc<RenderPass>,
+        create_info: SwapchainCreateInfo,
+    ) {
+        // the window size can change again while this runs, the next resize or out of date
+        // swapchain recreates it with the size that is current then
+        let (swapchain, swapchain_images) = match self.swapchain.recreate(create_info) {
+            Ok(recreated) => recreated,
+            Err(SwapchainCreationError::ImageExtentNotSupported { .. }) => return,
+            Err(e) => {
+                println!("Failed to recreate swapchain! {e}");
+                return;
+            }
+        };
+
+        // keeping the old swapchain makes the next acquire find it out of date and try again
+        let framebuffers = match create_target_framebuffers(
+            device,
+            allocator,
+            &swapchain,
+            &swapchain_images,
+            render_pass,
+        ) {
+            Ok(framebuffers) => framebuffers,
+            Err(e) => {
+                println!("Failed to recreate framebuffers! {e}");
+                return;
+            }
+        };
+
+        self.swapchain = swapchain;
+        self.framebuffers = framebuffers;
+    }
+}
+
+// One slot for the last submitted frame of every in flight index.
+pub(super) fn empty_futures(in_flight_count: usize) -> Vec<Option<Box<dyn GpuFuture>>> {
+    let mut futures = Vec::with_capacity(in_flight_count);
+    futures.resize_with(in_flight_count, || None);
+    futures
+}
+
+// A surface without a maximum allows any number of images.
+fn clamp_image_count(requested: u32, min_image_count: u32, max_image_count: Option<u32>) -> u32 {
+    requested.clamp(min_image_count, max_image_count.unwrap_or(u32::MAX))
+}
+
+/// The depth and msaa buffers are made with the sample count of the render pass.
+fn create_target_framebuffers(
+    device: Arc<Device>,
+    allocator: &StandardMemoryAllocator,
+    swapchain: &Arc<Swapchain>,
+    swapchain_images: &Vec<Arc<SwapchainImage>>,
+    render_pass: Arc<RenderPass>,
+) -> Result<Vec<Arc<Framebuffer>>, FramebufferError> {
+    let samples = render_pass.attachments()[0].samples;
+
+    let image_views = create_image_views(swapchain_images, swapchain.clone());
+    let (depth_buffers, _) = create_depth_buffer(device, swapchain.clone(), allocator, samples)?;
+    let msaa_buffers = create_msaa_buffers(swapchain.clone(), allocator, samples);
+    create_framebuffers(
+        &image_views,
+        render_pass,
+        &depth_buffers,
+        &msaa_buffers,
+        swapchain.image_extent(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requested_image_counts_are_clamped_to_the_surface() {
+        // a typical desktop surface allows two to eight images
+        assert_eq!(clamp_image_count(1, 2, Some(8)), 2);
+        assert_eq!(clamp_image_count(3, 2, Some(8)), 3);
+        assert_eq!(clamp_image_count(16, 2, Some(8)), 8);
+
+        assert_eq!(clamp_image_count(16, 2, None), 16);
+    }
+}
@@ -0,0 +1,102 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter},
+    path::Path,
+};
+
+use vulkano::{
+    buffer::{Buffer, BufferCreateInfo, BufferUsage, Subbuffer},
+    format::Format,
+    memory::allocator::{AllocationCreateInfo, MemoryUsage, StandardMemoryAllocator},
+};
+
+/// A buffer the swapchain image can be copied into and read back from on the host.
+/// Copies into it are tightly packed, so rows have no padding.
+pub(super) fn create_screenshot_buffer(
+    allocator: &StandardMemoryAllocator,
+    extent: [u32; 2],
+) -> Subbuffer<[u8]> {
+    Buffer::new_slice(
+        allocator,
+        BufferCreateInfo {
+            usage: BufferUsage::TRANSFER_DST,
+            ..Default::default()
+        },
+        AllocationCreateInfo {
+            usage: MemoryUsage::Download,
+            ..Default::default()
+        },
+        extent[0] as u64 * extent[1] as u64 * 4,
+    )
+    .expect("Failed to create screenshot buffer.")
+}
+
+/// Writes the pixels of a swapchain image as an 8 bit RGBA png.
+/// sRGB swapchain images already hold gamma encoded values, so the bytes are written as they are.
+pub(super) fn save_screenshot(
+    path: &Path,
+    extent: [u32; 2],
+    format: Format,
+    pixels: &[u8],
+) -> Result<(), png::EncodingError> {
+    let data = match to_rgba(format, pixels) {
+        Some(data) => data,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("screenshots of {format:?} swapchains are not supported"),
+            )
+            .into())
+        }
+    };
+
+    let file = File::create(path)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), extent[0], extent[1]);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(&data)
+}
+
+// None when the format isn't 8 bit RGBA or BGRA.
+fn to_rgba(format: Format, pixels: &[u8]) -> Option<Vec<u8>> {
+    let mut data = pixels.to_vec();
+    match format {
+        Format::B8G8R8A8_SRGB | Format::B8G8R8A8_UNORM => {
+            for pixel in data.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        Format::R8G8B8A8_SRGB | Format::R8G8B8A8_UNORM => (),
+        _ => return None,
+    }
+    // the alpha of the swapchain isn't meaningful with an opaque window
+    for pixel in data.chunks_exact_mut(4) {
+        pixel[3] = 255;
+    }
+    Some(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // two pixels cleared to a dark orange, as a BGRA swapchain stores them
+    const CLEARED_BGRA: [u8; 8] = [16, 96, 224, 0, 16, 96, 224, 0];
+
+    #[test]
+    fn bgra_clear_color_is_written_as_rgba() {
+        let rgba = to_rgba(Format::B8G8R8A8_SRGB, &CLEARED_BGRA).unwrap();
+        assert_eq!(rgba, [224, 96, 16, 255, 224, 96, 16, 255]);
+    }
+
+    #[test]
+    fn rgba_keeps_its_channel_order() {
+        let rgba = to_rgba(Format::R8G8B8A8_UNORM, &CLEARED_BGRA).unwrap();
+        assert_eq!(rgba, [16, 96, 224, 255, 16, 96, 224, 255]);
+    }
+
+    #[test]
+    fn other_formats_are_not_converted() {
+        assert!(to_rgba(Format::A2B10G10R10_UNORM_PACK32, &CLEARED_BGRA).is_none());
+    }
+}
@@ -0,0 +1,54 @@
+use std::sync::{Arc, Mutex};
+
+use super::{
+    draw_order,
+    drawable::{DrawableEntry, GenericDrawable},
+};
+
+/// Drawables queued for a single frame, drawn on top of the world and the overlay of the main window.
+/// The depth buffer is cleared before they are drawn, so the world never hides them.
+/// Queue UI every tick, the queue is cleared after each frame.
+///
+/// All UI is positioned in logical window pixels with the origin in the center of the window and y pointing up,
+/// the same space as `Mouse::cursor_position` and `cartesian_to_normalized`.
+pub struct UiRenderer {
+    // drawables with the layer they were queued on
+    queue: Mutex<Vec<(i32, Arc<GenericDrawable>)>>,
+}
+
+impl UiRenderer {
+    pub fn new() -> Self {
+        Self {
+            queue: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Queues the drawable on layer 0.
+    /// The drawable doesn't have to be registered. Queued drawables are drawn in queue order.
+    pub fn queue_drawable(&self, drawable_entry: &DrawableEntry) {
+        self.queue_drawable_with_order(drawable_entry, 0);
+    }
+
+    /// Drawables on higher layers are drawn over lower ones, the same layer keeps the queue order.
+    /// Use this instead of depth for transparent drawables, depth testing between them breaks blending.
+    pub fn queue_drawable_with_order(&self, drawable_entry: &DrawableEntry, layer: i32) {
+        match self.queue.lock() {
+            Ok(mut guard) => guard.push((layer, drawable_entry.get_arc())),
+            Err(e) => println!("Failed to access ui queue {e}"),
+        }
+    }
+
+    /// The drawables queued for the current frame, sorted by layer.
+    pub fn elements(&self) -> Vec<Arc<GenericDrawable>> {
+        match self.queue.lock() {
+            Ok(guard) => draw_order::sort_by_layer(guard.clone()),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut guard) = self.queue.lock() {
+            guard.clear();
+        }
+    }
+}
@@ -2,6 +2,7 @@ use std::sync::Arc;
 
 use bytemuck::Zeroable;
 use vulkano::shader::ShaderStages;
+use winit::dpi::PhysicalSize;
 
 use crate::graphics::bindable::UniformBuffer;
 
@@ -13,6 +14,8 @@ pub struct MatrixUbo {
     matrix: [[f32; 4]; 4],
 }
 
+/// Both matrices live in uniform buffers that drawables keep bound, so on resize they are
+/// updated in place instead of being replaced.
 pub struct Utils {
     pub perspective_projection: Arc<UniformBuffer<MatrixUbo>>,
     pub cartesian_to_normalized: Arc<UniformBuffer<MatrixUbo>>,
@@ -20,33 +23,13 @@ pub struct Utils {
 
 impl Utils {
     pub fn new(gfx: &Graphics) -> Self {
-        let window_extent = gfx.get_window().inner_size();
-        let aspect = window_extent.width as f32 / window_extent.height as f32;
-        const MAX_DEPTH: f32 = 10.0;
+        let window_extent = gfx.get_logical_extent();
 
         let perspective_projection = UniformBuffer::new(
             gfx,
             0,
             MatrixUbo {
-                matrix: (cgmath::perspective(cgmath::Deg(60.0), aspect, 0.1, MAX_DEPTH)
-                    * cgmath::Matrix4::look_at_rh(
-                        cgmath::Point3 {
-                            x: 0.0,
-                            y: 0.8,
-                            z: 1.5,
-                        },
-                        cgmath::Point3 {
-                            x: 0.0,
-                            y: 0.0,
-                            z: 0.0,
-                        },
-                        cgmath::Vector3 {
-                            x: 0.0,
-                            y: -1.0,
-                            z: 0.0,
-                        },
-                    ))
-                .into(),
+                matrix: perspective_projection_matrix(window_extent),
             },
             ShaderStages::VERTEX,
         );
@@ -55,15 +38,7 @@ impl Utils {
             gfx,
             0,
             MatrixUbo {
-                matrix: cgmath::ortho(
-                    -((window_extent.width / 2) as f32),
-                    (window_extent.width / 2) as f32,
-                    (window_extent.height / 2) as f32,
-                    -((window_extent.height / 2) as f32),
-                    -MAX_DEPTH,
-                    MAX_DEPTH,
-                )
-                .into(),
+                matrix: cartesian_to_normalized_matrix(window_extent),
             },
             ShaderStages::VERTEX,
         );
@@ -75,42 +50,114 @@ impl Utils {
     }
 
     pub fn recreate(&self, gfx: &Graphics) {
-        let window_extent = gfx.get_window().inner_size();
-        let aspect = window_extent.width as f32 / window_extent.height as f32;
-        const MAX_DEPTH: f32 = 10.0;
-
-        self.perspective_projection.access_data(|data| {
-            data.matrix = (cgmath::perspective(cgmath::Deg(60.0), aspect, 0.1, MAX_DEPTH)
-                * cgmath::Matrix4::look_at_rh(
-                    cgmath::Point3 {
-                        x: 0.0,
-                        y: 0.8,
-                        z: 1.5,
-                    },
-                    cgmath::Point3 {
-                        x: 0.0,
-                        y: 0.0,
-                        z: 0.0,
-                    },
-                    cgmath::Vector3 {
-                        x: 0.0,
-                        y: -1.0,
-                        z: 0.0,
-                    },
-                ))
-            .into();
-        });
+        let window_extent = gfx.get_logical_extent();
+
+        self.perspective_projection
+            .access_data(|data| data.matrix = perspective_projection_matrix(window_extent));
+        self.cartesian_to_normalized
+            .access_data(|data| data.matrix = cartesian_to_normalized_matrix(window_extent));
+    }
+}
+
+const MAX_DEPTH: f32 = 10.0;
+
+/// The size of a window in logical pixels, the unit of `cartesian_to_normalized`.
+pub(super) fn logical_extent(inner_size: PhysicalSize<u32>, scale_factor: f64) -> [f32; 2] {
+    let extent = inner_size.to_logical::<f32>(scale_factor);
+    [extent.width, extent.height]
+}
+
+fn perspective_projection_matrix(window_extent: [f32; 2]) -> [[f32; 4]; 4] {
+    let aspect = window_extent[0] / window_extent[1];
+
+    (cgmath::perspective(cgmath::Deg(60.0), aspect, 0.1, MAX_DEPTH)
+        * cgmath::Matrix4::look_at_rh(
+            cgmath::Point3 {
+                x: 0.0,
+                y: 0.8,
+                z: 1.5,
+            },
+            cgmath::Point3 {
+                x: 0.0,
+                y: 0.0,
+                z: 0.0,
+            },
+            cgmath::Vector3 {
+                x: 0.0,
+                y: -1.0,
+                z: 0.0,
+            },
+        ))
+    .into()
+}
 
-        self.cartesian_to_normalized.access_data(|data| {
-            data.matrix = cgmath::ortho(
-                -((window_extent.width / 2) as f32),
-                (window_extent.width / 2) as f32,
-                (window_extent.height / 2) as f32,
-                -((window_extent.height / 2) as f32),
-                -MAX_DEPTH,
-                MAX_DEPTH,
-            )
-            .into()
+/// Maps logical pixels with the origin in the center of the window and y pointing up.
+fn cartesian_to_normalized_matrix(window_extent: [f32; 2]) -> [[f32; 4]; 4] {
+    cgmath::ortho(
+        -window_extent[0] / 2.0,
+        window_extent[0] / 2.0,
+        window_extent[1] / 2.0,
+        -window_extent[1] / 2.0,
+        -MAX_DEPTH,
+        MAX_DEPTH,
+    )
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Matrix4, Vector4};
+
+    use super::*;
+
+    // where a point in logical pixels ends up in normalized device coordinates
+    fn normalized(window_extent: [f32; 2], point: [f32; 2]) -> [f32; 2] {
+        let matrix = Matrix4::from(cartesian_to_normalized_matrix(window_extent));
+        let normalized = matrix * Vector4::new(point[0], point[1], 0.0, 1.0);
+        [normalized.x, normalized.y]
+    }
+
+    #[test]
+    fn resized_windows_map_their_corners_to_the_framebuffer_edges() {
+        for window_extent in [[800.0, 600.0], [1600.0, 600.0]] {
+            let [half_width, half_height] = window_extent.map(|extent| extent / 2.0);
+            // y points up in cartesian space and down in normalized coordinates
+            assert_eq!(
+                normalized(window_extent, [-half_width, half_height]),
+                [-1.0, -1.0]
+            );
+            assert_eq!(
+                normalized(window_extent, [half_width, -half_height]),
+                [1.0, 1.0]
+            );
+            assert_eq!(normalized(window_extent, [0.0, 0.0]), [0.0, 0.0]);
+        }
+
+        // the perspective follows the new aspect ratio
+        assert_ne!(
+            perspective_projection_matrix([800.0, 600.0]),
+            perspective_projection_matrix([1600.0, 600.0])
+        );
+    }
+
+    #[test]
+    fn ui_pixels_keep_their_physical_size_on_high_dpi_screens() {
+        // a 64px quad in the middle of the same window on a regular and on a 2x screen
+        let physical_extents = [
+            (PhysicalSize::new(800, 600), 1.0),
+            (PhysicalSize::new(1600, 1200), 2.0),
+        ]
+        .map(|(inner_size, scale_factor)| {
+            let window_extent = logical_extent(inner_size, scale_factor);
+            let bottom_left = normalized(window_extent, [-32.0, -32.0]);
+            let top_right = normalized(window_extent, [32.0, 32.0]);
+            // normalized coordinates span 2 units across the framebuffer
+            let width = (top_right[0] - bottom_left[0]) / 2.0 * inner_size.width as f32;
+            let height = (top_right[1] - bottom_left[1]).abs() / 2.0 * inner_size.height as f32;
+            [width.round(), height.round()]
         });
+
+        // twice the physical pixels on a screen with twice the pixel density
+        assert_eq!(physical_extents, [[64.0, 64.0], [128.0, 128.0]]);
     }
 }
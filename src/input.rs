@@ -1,13 +1,27 @@
 use std::sync::Arc;
 
+use cgmath::{InnerSpace, Vector2};
 use winit::{event::Event, window::Window};
 
+use crate::timing::{Clock, RealClock};
+
 mod keyboard;
-pub use keyboard::Keyboard;
+pub use keyboard::{Key, Keyboard, TextInput};
 
 mod mouse;
 pub use mouse::Mouse;
 
+mod touch;
+pub use touch::{Touch, TouchPoint};
+
+#[cfg(feature = "gamepad")]
+mod gamepad;
+#[cfg(feature = "gamepad")]
+pub use gamepad::{stick_direction, Gamepad, GamepadAxis, GamepadButton};
+
+mod input_map;
+pub use input_map::{Action, Binding, InputMap};
+
 #[derive(Clone, Debug)]
 pub enum ButtonState {
     Pressed(std::time::Instant),
@@ -25,12 +39,30 @@ pub struct Input {
 
     pub mouse: Mouse,
     mouse_event_handler: fn(&Mouse, &Event<'_, ()>, Arc<Window>) -> bool,
+
+    pub touch: Touch,
+    touch_event_handler: fn(&Touch, &Event<'_, ()>, &Mouse, Arc<Window>) -> bool,
+
+    #[cfg(feature = "gamepad")]
+    pub gamepad: Gamepad,
+    #[cfg(feature = "gamepad")]
+    gamepad_event_handler: fn(&Gamepad, &Event<'_, ()>) -> bool,
+
+    pub input_map: InputMap,
 }
 
 impl Input {
     pub fn new(window: Arc<Window>) -> Arc<Self> {
-        let (keyboard, keyboard_event_handler) = Keyboard::new();
-        let (mouse, mouse_event_handler) = Mouse::new();
+        Self::with_clock(window, Arc::new(RealClock))
+    }
+
+    /// Every device reads press times and hold durations from `clock`.
+    pub fn with_clock(window: Arc<Window>, clock: Arc<dyn Clock>) -> Arc<Self> {
+        let (keyboard, keyboard_event_handler) = Keyboard::with_clock(clock.clone());
+        let (mouse, mouse_event_handler) = Mouse::with_clock(clock.clone());
+        let (touch, touch_event_handler) = Touch::with_clock(clock.clone());
+        #[cfg(feature = "gamepad")]
+        let (gamepad, gamepad_event_handler) = Gamepad::with_clock(clock.clone());
 
         Arc::new(Self {
             window: window,
@@ -38,18 +70,103 @@ impl Input {
             keyboard_event_handler: keyboard_event_handler,
             mouse: mouse,
             mouse_event_handler: mouse_event_handler,
+            touch: touch,
+            touch_event_handler: touch_event_handler,
+            #[cfg(feature = "gamepad")]
+            gamepad: gamepad,
+            #[cfg(feature = "gamepad")]
+            gamepad_event_handler: gamepad_event_handler,
+            input_map: InputMap::new(),
         })
     }
 
     /// returns true if the event was handled and false if it should be passed on.
     pub fn handle_event(&self, event: &Event<'_, ()>, window: Arc<Window>) -> bool {
+        #[cfg(feature = "gamepad")]
+        (self.gamepad_event_handler)(&self.gamepad, event);
+
         (self.keyboard_event_handler)(&self.keyboard, event)
-            | (self.mouse_event_handler)(&self.mouse, event, window)
+            | (self.mouse_event_handler)(&self.mouse, event, window.clone())
+            | (self.touch_event_handler)(&self.touch, event, &self.mouse, window)
+    }
+
+    pub fn action_pressed(&self, action: Action) -> bool {
+        self.input_map.is_action_pressed(action, &self.keyboard, &self.mouse)
     }
 
-    /// call this at the end of each frame to make sure every key press is only counted as a press for one frame
+    pub fn action_held(&self, action: Action) -> Option<std::time::Duration> {
+        self.input_map.is_action_held(action, &self.keyboard, &self.mouse)
+    }
+
+    /// The direction held with the move actions, with y pointing up. With the `gamepad` feature
+    /// the left stick adds to it. The result is at most 1.0 long, so diagonals aren't faster.
+    pub fn movement_direction(&self) -> Vector2<f32> {
+        let direction = key_direction(
+            self.action_held(Action::MoveLeft).is_some(),
+            self.action_held(Action::MoveRight).is_some(),
+            self.action_held(Action::MoveUp).is_some(),
+            self.action_held(Action::MoveDown).is_some(),
+        );
+
+        #[cfg(feature = "gamepad")]
+        let direction = direction + self.gamepad.left_stick();
+
+        clamp_to_unit_length(direction)
+    }
+
+    /// call this at the end of each tick to make sure every key press is only counted as a press for one tick
     pub fn clear_presses(&self) {
         self.mouse.clear_presses();
         self.keyboard.clear_presses();
+        self.touch.clear_presses();
+        #[cfg(feature = "gamepad")]
+        self.gamepad.clear_presses();
+    }
+}
+
+// Opposite keys cancel out.
+fn key_direction(left: bool, right: bool, up: bool, down: bool) -> Vector2<f32> {
+    let axis = |negative: bool, positive: bool| match (negative, positive) {
+        (true, false) => -1.0,
+        (false, true) => 1.0,
+        _ => 0.0,
+    };
+    Vector2::new(axis(left, right), axis(down, up))
+}
+
+fn clamp_to_unit_length(direction: Vector2<f32>) -> Vector2<f32> {
+    match direction.magnitude() > 1.0 {
+        true => direction.normalize(),
+        false => direction,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // how far a second of holding the keys moves something at `speed` units per second
+    fn distance_per_second(left: bool, right: bool, up: bool, down: bool, speed: f32) -> f32 {
+        let delta_time = 1.0 / 60.0;
+        let direction = clamp_to_unit_length(key_direction(left, right, up, down));
+        let moved = (0..60).fold(Vector2::new(0.0, 0.0), |moved, _| {
+            moved + direction * speed * delta_time
+        });
+        moved.magnitude()
+    }
+
+    #[test]
+    fn diagonals_cover_the_same_distance_as_cardinal_moves() {
+        let cardinal = distance_per_second(false, true, false, false, 120.0);
+        let diagonal = distance_per_second(false, true, true, false, 120.0);
+        assert!((cardinal - 120.0).abs() < 1e-3);
+        assert!((diagonal - cardinal).abs() < 1e-3);
+    }
+
+    #[test]
+    fn opposite_keys_cancel_out() {
+        // left and right are both held, so only down is left
+        let direction = key_direction(true, true, false, true);
+        assert_eq!(direction, Vector2::new(0.0, -1.0));
     }
 }
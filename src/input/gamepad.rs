@@ -0,0 +1,317 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+};
+
+use cgmath::{InnerSpace, Vector2};
+use gilrs::{EventType, Gilrs};
+use winit::event::Event;
+
+use super::ButtonState;
+use crate::timing::{Clock, RealClock};
+
+// Stick positions closer to the center than this are treated as the center,
+// worn sticks rarely rest at exactly zero.
+const DEFAULT_DEAD_ZONE: f32 = 0.2;
+
+/// The buttons of a standard controller, named by their position like in `gilrs`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    Select,
+    Start,
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+impl GamepadButton {
+    fn from_gilrs(button: gilrs::Button) -> Option<Self> {
+        match button {
+            gilrs::Button::South => Some(GamepadButton::South),
+            gilrs::Button::East => Some(GamepadButton::East),
+            gilrs::Button::North => Some(GamepadButton::North),
+            gilrs::Button::West => Some(GamepadButton::West),
+            gilrs::Button::LeftTrigger => Some(GamepadButton::LeftBumper),
+            gilrs::Button::RightTrigger => Some(GamepadButton::RightBumper),
+            gilrs::Button::Select => Some(GamepadButton::Select),
+            gilrs::Button::Start => Some(GamepadButton::Start),
+            gilrs::Button::LeftThumb => Some(GamepadButton::LeftStick),
+            gilrs::Button::RightThumb => Some(GamepadButton::RightStick),
+            gilrs::Button::DPadUp => Some(GamepadButton::DPadUp),
+            gilrs::Button::DPadDown => Some(GamepadButton::DPadDown),
+            gilrs::Button::DPadLeft => Some(GamepadButton::DPadLeft),
+            gilrs::Button::DPadRight => Some(GamepadButton::DPadRight),
+            _ => None,
+        }
+    }
+}
+
+/// Sticks go from -1.0 to 1.0 with y pointing up, triggers from 0.0 to 1.0.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+/// Every connected controller is merged into one, the last one touched wins.
+pub struct Gamepad {
+    // None if the platform has no controller support
+    gilrs: Mutex<Option<Gilrs>>,
+    button_map: RwLock<HashMap<GamepadButton, ButtonState>>,
+    axis_map: RwLock<HashMap<GamepadAxis, f32>>,
+    dead_zone: RwLock<f32>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Gamepad {
+    pub fn new() -> (Self, fn(&Gamepad, &Event<'_, ()>) -> bool) {
+        Self::with_clock(Arc::new(RealClock))
+    }
+
+    /// Press times and hold durations are measured with `clock`.
+    pub fn with_clock(clock: Arc<dyn Clock>) -> (Self, fn(&Gamepad, &Event<'_, ()>) -> bool) {
+        let gilrs = match Gilrs::new() {
+            Ok(gilrs) => Some(gilrs),
+            Err(e) => {
+                println!("Gamepads are not supported: {e}");
+                None
+            }
+        };
+
+        (
+            Self {
+                gilrs: Mutex::new(gilrs),
+                button_map: RwLock::new(HashMap::new()),
+                axis_map: RwLock::new(HashMap::new()),
+                dead_zone: RwLock::new(DEFAULT_DEAD_ZONE),
+                clock: clock,
+            },
+            Gamepad::_event_handler,
+        )
+    }
+
+    pub fn is_button_pressed(&self, button: GamepadButton) -> bool {
+        match self.get_button_state(button) {
+            Some(ButtonState::Pressed(_)) => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_button_held(&self, button: GamepadButton) -> Option<std::time::Duration> {
+        match self.get_button_state(button) {
+            Some(ButtonState::Held(start)) => Some(self.clock.now() - start),
+            _ => None,
+        }
+    }
+
+    pub fn get_button_state(&self, button: GamepadButton) -> Option<ButtonState> {
+        self.button_map.read().ok()?.get(&button).cloned()
+    }
+
+    /// The raw value of the axis, without the dead zone.
+    pub fn get_axis(&self, axis: GamepadAxis) -> f32 {
+        match self.axis_map.read() {
+            Ok(guard) => guard.get(&axis).copied().unwrap_or(0.0),
+            Err(_) => 0.0,
+        }
+    }
+
+    /// How far from the center a stick has to be pushed before it counts, from 0.0 to 1.0.
+    pub fn set_dead_zone(&self, dead_zone: f32) {
+        match self.dead_zone.write() {
+            Ok(mut guard) => *guard = dead_zone.clamp(0.0, 0.99),
+            Err(e) => println!("Failed to access dead zone {e}"),
+        }
+    }
+
+    /// The direction of the left stick with the dead zone applied, at most 1.0 long.
+    pub fn left_stick(&self) -> Vector2<f32> {
+        self.stick(GamepadAxis::LeftStickX, GamepadAxis::LeftStickY)
+    }
+
+    pub fn right_stick(&self) -> Vector2<f32> {
+        self.stick(GamepadAxis::RightStickX, GamepadAxis::RightStickY)
+    }
+
+    fn stick(&self, x: GamepadAxis, y: GamepadAxis) -> Vector2<f32> {
+        let dead_zone = self
+            .dead_zone
+            .read()
+            .map_or(DEFAULT_DEAD_ZONE, |guard| *guard);
+        stick_direction(Vector2::new(self.get_axis(x), self.get_axis(y)), dead_zone)
+    }
+
+    // Controllers aren't window events, they are polled once for every batch of events.
+    fn _event_handler(&self, event: &Event<'_, ()>) -> bool {
+        if !matches!(event, Event::NewEvents(_)) {
+            return false;
+        }
+
+        let mut gilrs = match self.gilrs.lock() {
+            Ok(guard) => guard,
+            Err(e) => {
+                println!("Failed to access gamepads {e}");
+                return false;
+            }
+        };
+        let gilrs = match gilrs.as_mut() {
+            Some(gilrs) => gilrs,
+            None => return false,
+        };
+
+        while let Some(gilrs::Event { event, .. }) = gilrs.next_event() {
+            match event {
+                EventType::ButtonPressed(button, _) => {
+                    if let Some(button) = GamepadButton::from_gilrs(button) {
+                        self.press_button(button);
+                    }
+                }
+                EventType::ButtonReleased(button, _) => {
+                    if let Some(button) = GamepadButton::from_gilrs(button) {
+                        if let Ok(mut guard) = self.button_map.write() {
+                            guard.insert(button, ButtonState::Released);
+                        }
+                    }
+                }
+                // most controllers report the triggers as analog buttons
+                EventType::ButtonChanged(gilrs::Button::LeftTrigger2, value, _) => {
+                    self.set_axis(GamepadAxis::LeftTrigger, value);
+                }
+                EventType::ButtonChanged(gilrs::Button::RightTrigger2, value, _) => {
+                    self.set_axis(GamepadAxis::RightTrigger, value);
+                }
+                EventType::AxisChanged(axis, value, _) => {
+                    let axis = match axis {
+                        gilrs::Axis::LeftStickX => GamepadAxis::LeftStickX,
+                        gilrs::Axis::LeftStickY => GamepadAxis::LeftStickY,
+                        gilrs::Axis::RightStickX => GamepadAxis::RightStickX,
+                        gilrs::Axis::RightStickY => GamepadAxis::RightStickY,
+                        gilrs::Axis::LeftZ => GamepadAxis::LeftTrigger,
+                        gilrs::Axis::RightZ => GamepadAxis::RightTrigger,
+                        _ => continue,
+                    };
+                    self.set_axis(axis, value);
+                }
+                // nothing should stay held by a controller that is gone
+                EventType::Disconnected => {
+                    if let Ok(mut guard) = self.button_map.write() {
+                        guard.clear();
+                    }
+                    if let Ok(mut guard) = self.axis_map.write() {
+                        guard.clear();
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        false
+    }
+
+    fn press_button(&self, button: GamepadButton) {
+        if let Ok(mut guard) = self.button_map.write() {
+            match guard.get(&button) {
+                None | Some(ButtonState::Released) => {
+                    guard.insert(button, ButtonState::Pressed(self.clock.now()));
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn set_axis(&self, axis: GamepadAxis, value: f32) {
+        match self.axis_map.write() {
+            Ok(mut guard) => {
+                guard.insert(axis, value);
+            }
+            Err(e) => println!("Failed to access gamepad axes {e}"),
+        }
+    }
+
+    pub fn clear_presses(&self) {
+        match self.button_map.write() {
+            Ok(mut guard) => {
+                guard.iter_mut().for_each(|(_, state)| {
+                    if let ButtonState::Pressed(time) = *state {
+                        *state = ButtonState::Held(time);
+                    }
+                });
+            }
+            Err(e) => println!("Failed to access gamepad buttons {e}"),
+        }
+    }
+}
+
+/// Maps a raw stick position to a direction of at most length 1.0. Positions inside the
+/// dead zone are zero, the rest is rescaled so the edge of the dead zone starts at zero
+/// instead of jumping to `dead_zone`.
+pub fn stick_direction(raw: Vector2<f32>, dead_zone: f32) -> Vector2<f32> {
+    let length = raw.magnitude();
+    if length <= dead_zone {
+        return Vector2::new(0.0, 0.0);
+    }
+
+    let scaled_length = ((length - dead_zone) / (1.0 - dead_zone)).min(1.0);
+    raw * (scaled_length / length)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::timing::ManualClock;
+
+    // the stick direction with a dead zone of 0.2
+    fn direction(x: f32, y: f32) -> Vector2<f32> {
+        stick_direction(Vector2::new(x, y), 0.2)
+    }
+
+    fn assert_close(actual: Vector2<f32>, expected: Vector2<f32>) {
+        assert!(
+            (actual - expected).magnitude() < 1e-5,
+            "{actual:?} is not {expected:?}"
+        );
+    }
+
+    #[test]
+    fn hold_durations_follow_the_clock() {
+        let clock = ManualClock::new();
+        let (gamepad, _) = Gamepad::with_clock(clock.clone());
+        gamepad.press_button(GamepadButton::South);
+        gamepad.clear_presses();
+
+        clock.advance(Duration::from_millis(300));
+        let held_for = gamepad.is_button_held(GamepadButton::South);
+        assert_eq!(held_for, Some(Duration::from_millis(300)));
+    }
+
+    #[test]
+    fn resting_sticks_are_centered() {
+        assert_close(direction(0.1, -0.15), Vector2::new(0.0, 0.0));
+    }
+
+    #[test]
+    fn the_dead_zone_edge_starts_at_zero() {
+        assert_close(direction(0.3, 0.0), Vector2::new(0.125, 0.0));
+        assert_close(direction(0.0, -0.6), Vector2::new(0.0, -0.5));
+    }
+
+    #[test]
+    fn pushed_corners_are_at_most_one_long() {
+        assert_close(direction(1.0, 1.0), Vector2::new(1.0, 1.0).normalize());
+    }
+}
@@ -0,0 +1,234 @@
+use std::{collections::HashMap, sync::RwLock};
+
+use serde::{Deserialize, Serialize};
+
+use super::{Key, Keyboard, Mouse};
+
+/// Something the player can do, independent of which key does it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Interact,
+    ZoomIn,
+    ZoomOut,
+    /// Held together with `Drag` to move the editor camera.
+    PanCamera,
+    Drag,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(Key),
+    MouseButton(u32),
+}
+
+/// Maps actions to the keys and buttons that trigger them.
+/// An action can have several bindings, it is active if any of them is.
+pub struct InputMap {
+    bindings: RwLock<HashMap<Action, Vec<Binding>>>,
+}
+
+impl InputMap {
+    pub fn new() -> Self {
+        Self {
+            bindings: RwLock::new(Self::default_bindings()),
+        }
+    }
+
+    pub fn default_bindings() -> HashMap<Action, Vec<Binding>> {
+        HashMap::from([
+            (
+                Action::MoveUp,
+                vec![Binding::Key(Key::W), Binding::Key(Key::Up)],
+            ),
+            (
+                Action::MoveDown,
+                vec![Binding::Key(Key::S), Binding::Key(Key::Down)],
+            ),
+            (
+                Action::MoveLeft,
+                vec![Binding::Key(Key::A), Binding::Key(Key::Left)],
+            ),
+            (
+                Action::MoveRight,
+                vec![Binding::Key(Key::D), Binding::Key(Key::Right)],
+            ),
+            (Action::Interact, vec![Binding::Key(Key::E)]),
+            (Action::ZoomIn, vec![Binding::Key(Key::Equals)]),
+            (Action::ZoomOut, vec![Binding::Key(Key::Minus)]),
+            (Action::PanCamera, vec![Binding::Key(Key::LAlt)]),
+            (Action::Drag, vec![Binding::MouseButton(1)]),
+        ])
+    }
+
+    pub fn get_bindings(&self, action: Action) -> Vec<Binding> {
+        match self.bindings.read() {
+            Ok(guard) => guard.get(&action).cloned().unwrap_or_default(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Replaces every binding of the action with `binding`.
+    pub fn rebind(&self, action: Action, binding: Binding) {
+        match self.bindings.write() {
+            Ok(mut guard) => {
+                guard.insert(action, vec![binding]);
+            }
+            Err(e) => println!("Failed to access input map {e}"),
+        }
+    }
+
+    /// Adds `binding` next to the existing bindings of the action.
+    pub fn add_binding(&self, action: Action, binding: Binding) {
+        match self.bindings.write() {
+            Ok(mut guard) => {
+                let bindings = guard.entry(action).or_default();
+                if !bindings.contains(&binding) {
+                    bindings.push(binding);
+                }
+            }
+            Err(e) => println!("Failed to access input map {e}"),
+        }
+    }
+
+    pub fn is_action_pressed(&self, action: Action, keyboard: &Keyboard, mouse: &Mouse) -> bool {
+        self.get_bindings(action)
+            .into_iter()
+            .any(|binding| match binding {
+                Binding::Key(key) => keyboard.is_key_pressed_by(key),
+                Binding::MouseButton(button) => mouse.is_button_pressed(button),
+            })
+    }
+
+    /// The longest time any of the bindings has been held.
+    pub fn is_action_held(
+        &self,
+        action: Action,
+        keyboard: &Keyboard,
+        mouse: &Mouse,
+    ) -> Option<std::time::Duration> {
+        self.get_bindings(action)
+            .into_iter()
+            .filter_map(|binding| match binding {
+                Binding::Key(key) => keyboard.is_key_held_by(key),
+                Binding::MouseButton(button) => mouse.is_button_held(button),
+            })
+            .max()
+    }
+
+    /// Writes the bindings to a ron file so remapped controls survive a restart.
+    pub fn save(&self, path: &str) -> Result<(), std::io::Error> {
+        let bindings = match self.bindings.read() {
+            Ok(guard) => guard.clone(),
+            Err(_) => HashMap::new(),
+        };
+
+        let text = ron::ser::to_string_pretty(&bindings, ron::ser::PrettyConfig::default())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        std::fs::write(path, text)
+    }
+
+    /// Replaces the bindings with the ones in the file. Actions missing from the
+    /// file keep their current bindings.
+    pub fn load(&self, path: &str) -> Result<(), std::io::Error> {
+        let text = std::fs::read_to_string(path)?;
+        let loaded: HashMap<Action, Vec<Binding>> = ron::from_str(&text)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+
+        match self.bindings.write() {
+            Ok(mut guard) => guard.extend(loaded),
+            Err(e) => println!("Failed to access input map {e}"),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a file of its own per test, so tests running in parallel don't share one
+    fn temp_path(name: &str) -> String {
+        let path =
+            std::env::temp_dir().join(format!("input_map_{}_{name}.ron", std::process::id()));
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn saved_bindings_load_into_a_new_map() {
+        let path = temp_path("round_trip");
+        let input_map = InputMap::new();
+        input_map.rebind(Action::Interact, Binding::Key(Key::Q));
+        input_map.add_binding(Action::Drag, Binding::MouseButton(3));
+        input_map.save(&path).unwrap();
+
+        let loaded = InputMap::new();
+        loaded.load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            loaded.get_bindings(Action::Interact),
+            vec![Binding::Key(Key::Q)]
+        );
+        assert_eq!(
+            loaded.get_bindings(Action::Drag),
+            vec![Binding::MouseButton(1), Binding::MouseButton(3)]
+        );
+        assert_eq!(
+            loaded.get_bindings(Action::MoveUp),
+            input_map.get_bindings(Action::MoveUp)
+        );
+    }
+
+    #[test]
+    fn actions_missing_from_the_file_keep_their_bindings() {
+        let path = temp_path("partial");
+        let saved = HashMap::from([(Action::MoveUp, vec![Binding::Key(Key::Space)])]);
+        std::fs::write(&path, ron::to_string(&saved).unwrap()).unwrap();
+
+        let input_map = InputMap::new();
+        input_map.rebind(Action::Interact, Binding::Key(Key::Q));
+        input_map.load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            input_map.get_bindings(Action::MoveUp),
+            vec![Binding::Key(Key::Space)]
+        );
+        assert_eq!(
+            input_map.get_bindings(Action::Interact),
+            vec![Binding::Key(Key::Q)]
+        );
+        assert_eq!(
+            input_map.get_bindings(Action::MoveDown),
+            vec![Binding::Key(Key::S), Binding::Key(Key::Down)]
+        );
+    }
+
+    #[test]
+    fn rebind_replaces_and_add_binding_keeps_the_other_bindings() {
+        let input_map = InputMap::new();
+
+        input_map.add_binding(Action::MoveUp, Binding::Key(Key::Space));
+        assert_eq!(
+            input_map.get_bindings(Action::MoveUp),
+            vec![
+                Binding::Key(Key::W),
+                Binding::Key(Key::Up),
+                Binding::Key(Key::Space)
+            ]
+        );
+        // adding a binding twice doesn't count it twice
+        input_map.add_binding(Action::MoveUp, Binding::Key(Key::Space));
+        assert_eq!(input_map.get_bindings(Action::MoveUp).len(), 3);
+
+        input_map.rebind(Action::MoveUp, Binding::Key(Key::Tab));
+        assert_eq!(
+            input_map.get_bindings(Action::MoveUp),
+            vec![Binding::Key(Key::Tab)]
+        );
+    }
+}
@@ -1,16 +1,140 @@
-use std::{collections::HashMap, sync::RwLock};
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Arc, Mutex, RwLock},
+    time::Instant,
+};
 
-use winit::event::{ElementState, Event, WindowEvent};
+use serde::{Deserialize, Serialize};
+
+use winit::event::{ElementState, Event, VirtualKeyCode, WindowEvent};
 
 use super::ButtonState;
+use crate::timing::{Clock, RealClock};
 
 const KEY_COUNT: usize = 128;
 
+/// Named keys. They are looked up by the key they produce on the current layout,
+/// the scancode is only used when the platform doesn't report one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Key {
+    W,
+    A,
+    S,
+    D,
+    Q,
+    E,
+    Up,
+    Down,
+    Left,
+    Right,
+    Space,
+    Enter,
+    Escape,
+    Tab,
+    Minus,
+    Equals,
+    LShift,
+    LControl,
+    LAlt,
+}
+
+impl Key {
+    pub fn virtual_keycode(&self) -> VirtualKeyCode {
+        match self {
+            Key::W => VirtualKeyCode::W,
+            Key::A => VirtualKeyCode::A,
+            Key::S => VirtualKeyCode::S,
+            Key::D => VirtualKeyCode::D,
+            Key::Q => VirtualKeyCode::Q,
+            Key::E => VirtualKeyCode::E,
+            Key::Up => VirtualKeyCode::Up,
+            Key::Down => VirtualKeyCode::Down,
+            Key::Left => VirtualKeyCode::Left,
+            Key::Right => VirtualKeyCode::Right,
+            Key::Space => VirtualKeyCode::Space,
+            Key::Enter => VirtualKeyCode::Return,
+            Key::Escape => VirtualKeyCode::Escape,
+            Key::Tab => VirtualKeyCode::Tab,
+            Key::Minus => VirtualKeyCode::Minus,
+            Key::Equals => VirtualKeyCode::Equals,
+            Key::LShift => VirtualKeyCode::LShift,
+            Key::LControl => VirtualKeyCode::LControl,
+            Key::LAlt => VirtualKeyCode::LAlt,
+        }
+    }
+
+    /// The scancode of the key on a US QWERTY keyboard. The arrow keys use the linux codes.
+    pub fn scancode(&self) -> u32 {
+        match self {
+            Key::W => 17,
+            Key::A => 30,
+            Key::S => 31,
+            Key::D => 32,
+            Key::Q => 16,
+            Key::E => 18,
+            Key::Up => 103,
+            Key::Down => 108,
+            Key::Left => 105,
+            Key::Right => 106,
+            Key::Space => 57,
+            Key::Enter => 28,
+            Key::Escape => 1,
+            Key::Tab => 15,
+            Key::Minus => 12,
+            Key::Equals => 13,
+            Key::LShift => 42,
+            Key::LControl => 29,
+            Key::LAlt => 56,
+        }
+    }
+}
+
+/// Text typed since the last tick, in the order it was typed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextInput {
+    Char(char),
+    Backspace,
+    Enter,
+}
+
 pub struct Keyboard {
     key_map: RwLock<HashMap<u32, ButtonState>>,
+    virtual_key_map: RwLock<HashMap<VirtualKeyCode, ButtonState>>,
+    // keys the platform only reported a scancode for, the fallback of `get_key_state_by`
+    unnamed_key_map: RwLock<HashMap<u32, ButtonState>>,
+    text_input: Mutex<Vec<TextInput>>,
+    clock: Arc<dyn Clock>,
 }
 
 impl Keyboard {
+    pub fn is_key_pressed_by(&self, key: Key) -> bool {
+        match self.get_key_state_by(key) {
+            Some(ButtonState::Pressed(_)) => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_key_held_by(&self, key: Key) -> Option<std::time::Duration> {
+        match self.get_key_state_by(key) {
+            Some(ButtonState::Held(start)) => Some(self.clock.now() - start),
+            _ => None,
+        }
+    }
+
+    /// Falls back to the scancode for key events that came without a name.
+    pub fn get_key_state_by(&self, key: Key) -> Option<ButtonState> {
+        let state = match self.virtual_key_map.read() {
+            Ok(guard) => guard.get(&key.virtual_keycode()).cloned(),
+            Err(_) => None,
+        };
+        state.or_else(|| self.get_unnamed_key_state(key.scancode()))
+    }
+
+    fn get_unnamed_key_state(&self, scancode: u32) -> Option<ButtonState> {
+        self.unnamed_key_map.read().ok()?.get(&scancode).cloned()
+    }
+
     pub fn is_key_pressed(&self, keycode: u32) -> bool {
         match self.get_key_state(keycode) {
             Some(ButtonState::Pressed(_)) => true,
@@ -20,7 +144,7 @@ impl Keyboard {
 
     pub fn is_key_held(&self, keycode: u32) -> Option<std::time::Duration> {
         match self.get_key_state(keycode) {
-            Some(ButtonState::Held(start)) => Some(std::time::Instant::now() - start),
+            Some(ButtonState::Held(start)) => Some(self.clock.now() - start),
             _ => None,
         }
     }
@@ -29,10 +153,39 @@ impl Keyboard {
         self.key_map.read().ok()?.get(&keycode).cloned()
     }
 
+    /// Takes everything typed since the last call, including backspaces and enters.
+    pub fn take_text_input(&self) -> Vec<TextInput> {
+        match self.text_input.lock() {
+            Ok(mut guard) => std::mem::take(&mut *guard),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Takes the characters typed since the last call. Backspaces and enters are dropped,
+    /// use `take_text_input` to get them too.
+    pub fn take_typed_chars(&self) -> String {
+        self.take_text_input()
+            .into_iter()
+            .filter_map(|text_input| match text_input {
+                TextInput::Char(chr) => Some(chr),
+                _ => None,
+            })
+            .collect()
+    }
+
     pub fn new() -> (Self, fn(&Keyboard, &Event<'_, ()>) -> bool) {
+        Self::with_clock(Arc::new(RealClock))
+    }
+
+    /// Press times and hold durations are measured with `clock`.
+    pub fn with_clock(clock: Arc<dyn Clock>) -> (Self, fn(&Keyboard, &Event<'_, ()>) -> bool) {
         (
             Self {
                 key_map: RwLock::new(HashMap::new()),
+                virtual_key_map: RwLock::new(HashMap::new()),
+                unnamed_key_map: RwLock::new(HashMap::new()),
+                text_input: Mutex::new(Vec::new()),
+                clock: clock,
             },
             Keyboard::_event_handler,
         )
@@ -41,39 +194,25 @@ impl Keyboard {
     fn _event_handler(&self, event: &Event<'_, ()>) -> bool {
         match event {
             Event::WindowEvent { event, .. } => {
-                if let WindowEvent::ReceivedCharacter(_chr) = event {
-                    return true;
-                }
+                if let WindowEvent::ReceivedCharacter(chr) = event {
+                    let text_input = match chr {
+                        '\u{8}' => Some(TextInput::Backspace),
+                        '\r' | '\n' => Some(TextInput::Enter),
+                        chr if chr.is_control() => None,
+                        chr => Some(TextInput::Char(*chr)),
+                    };
 
-                if let WindowEvent::KeyboardInput { input, .. } = event {
-                    
-                    match input.state {
-                        ElementState::Pressed => {
-                            let previous_state = match self.key_map.read() {
-                                Ok(guard) => guard.get(&input.scancode).cloned(),
-                                _ => None,
-                            };
-
-                            match previous_state {
-                                None | Some(ButtonState::Released) => {
-                                    println!("[KEYBOARD] {} pressed", input.scancode);
-                                    if let Ok(mut guard) = self.key_map.write() {
-                                        guard.insert(
-                                            input.scancode,
-                                            ButtonState::Pressed(std::time::Instant::now()),
-                                        );
-                                    }
-                                }
-                                _ => { /* ignore */ }
-                            }
-                        }
-                        ElementState::Released => {
-                            if let Ok(mut guard) = self.key_map.write() {
-                                guard.insert(input.scancode, ButtonState::Released);
-                            }
+                    if let Some(text_input) = text_input {
+                        match self.text_input.lock() {
+                            Ok(mut guard) => guard.push(text_input),
+                            Err(e) => println!("Failed to access text input {e}"),
                         }
                     }
+                    return true;
+                }
 
+                if let WindowEvent::KeyboardInput { input, .. } = event {
+                    self.handle_key(input.scancode, input.virtual_keycode, input.state);
                     return true;
                 }
 
@@ -83,8 +222,72 @@ impl Keyboard {
         }
     }
 
+    fn handle_key(
+        &self,
+        scancode: u32,
+        virtual_keycode: Option<VirtualKeyCode>,
+        state: ElementState,
+    ) {
+        let now = self.clock.now();
+        if Self::update_key_state(&self.key_map, scancode, state, now) {
+            println!("[KEYBOARD] {} pressed", scancode);
+        }
+
+        match virtual_keycode {
+            Some(virtual_keycode) => {
+                Self::update_key_state(&self.virtual_key_map, virtual_keycode, state, now);
+            }
+            None => {
+                Self::update_key_state(&self.unnamed_key_map, scancode, state, now);
+            }
+        }
+    }
+
+    // Returns true if the key went from released to pressed.
+    fn update_key_state<K: Eq + Hash>(
+        key_map: &RwLock<HashMap<K, ButtonState>>,
+        key: K,
+        state: ElementState,
+        now: Instant,
+    ) -> bool {
+        match state {
+            ElementState::Pressed => {
+                let previous_state = match key_map.read() {
+                    Ok(guard) => guard.get(&key).cloned(),
+                    _ => None,
+                };
+
+                match previous_state {
+                    None | Some(ButtonState::Released) => {
+                        if let Ok(mut guard) = key_map.write() {
+                            guard.insert(key, ButtonState::Pressed(now));
+                        }
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            ElementState::Released => {
+                if let Ok(mut guard) = key_map.write() {
+                    guard.insert(key, ButtonState::Released);
+                }
+                false
+            }
+        }
+    }
+
     pub fn clear_presses(&self) {
-        match self.key_map.write() {
+        Self::clear_map_presses(&self.key_map);
+        Self::clear_map_presses(&self.virtual_key_map);
+        Self::clear_map_presses(&self.unnamed_key_map);
+        // text that nothing took this tick is thrown away
+        if let Ok(mut guard) = self.text_input.lock() {
+            guard.clear();
+        }
+    }
+
+    fn clear_map_presses<K>(key_map: &RwLock<HashMap<K, ButtonState>>) {
+        match key_map.write() {
             Ok(mut guard) => {
                 guard.iter_mut().for_each(|(_, state)| {
                     if let ButtonState::Pressed(time) = *state {
@@ -93,8 +296,50 @@ impl Keyboard {
                 });
             }
             Err(e) => {
-                println!("Failed to access key s {e}");
+                println!("Failed to access keys {e}");
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::timing::ManualClock;
+
+    #[test]
+    fn named_keys_ignore_the_scancode_of_other_layouts() {
+        let (keyboard, _) = Keyboard::new();
+        // the key in the W position of an AZERTY keyboard types Z
+        keyboard.handle_key(17, Some(VirtualKeyCode::Z), ElementState::Pressed);
+
+        assert!(!keyboard.is_key_pressed_by(Key::W));
+        assert!(keyboard.is_key_pressed(17));
+    }
+
+    #[test]
+    fn unnamed_keys_fall_back_to_the_scancode() {
+        let (keyboard, _) = Keyboard::new();
+        keyboard.handle_key(17, None, ElementState::Pressed);
+        assert!(keyboard.is_key_pressed_by(Key::W));
+
+        keyboard.clear_presses();
+        assert!(!keyboard.is_key_pressed_by(Key::W));
+        assert!(keyboard.is_key_held_by(Key::W).is_some());
+    }
+
+    #[test]
+    fn hold_durations_follow_the_clock() {
+        let clock = ManualClock::new();
+        let (keyboard, _) = Keyboard::with_clock(clock.clone());
+        keyboard.handle_key(57, Some(VirtualKeyCode::Space), ElementState::Pressed);
+        keyboard.clear_presses();
+
+        clock.advance(Duration::from_millis(750));
+        let held_for = Some(Duration::from_millis(750));
+        assert_eq!(keyboard.is_key_held_by(Key::Space), held_for);
+        assert_eq!(keyboard.is_key_held(57), held_for);
+    }
+}
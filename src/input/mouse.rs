@@ -1,37 +1,74 @@
 use std::{
-    cell::{Cell, UnsafeCell},
+    cell::{Cell, RefCell, RefMut},
     collections::HashMap,
     sync::Arc,
+    time::{Duration, Instant},
 };
 
 use cgmath::Vector2;
 use winit::{
-    event::{DeviceEvent, ElementState, Event, WindowEvent},
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{DeviceEvent, ElementState, Event, MouseScrollDelta, WindowEvent},
     window::Window,
 };
 
 use super::ButtonState;
+use crate::timing::{Clock, RealClock};
+
+// How many pixels of precise scrolling, like on a trackpad, count as one scroll wheel line.
+const DEFAULT_PIXELS_PER_LINE: f32 = 20.0;
+
+// Two presses closer together than this count as a double click.
+const DEFAULT_DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
+
+#[derive(Clone, Default)]
+struct ClickHistory {
+    last_press: Option<Instant>,
+    // only true during the tick of the second press
+    double_clicked: bool,
+    last_click_duration: Option<Duration>,
+}
 
 pub struct Mouse {
     pub cursor_position: Cell<Vector2<f64>>,
     pub mouse_movement: Cell<Vector2<f64>>,
     pub scroll_wheel_movement: Cell<f32>,
-    button_map: UnsafeCell<HashMap<u32, ButtonState>>,
+    pixels_per_line: Cell<f32>,
+    double_click_time: Cell<Duration>,
+    button_map: RefCell<HashMap<u32, ButtonState>>,
+    click_map: RefCell<HashMap<u32, ClickHistory>>,
+    clock: Arc<dyn Clock>,
 }
 
 impl Mouse {
     pub fn new() -> (Self, fn(&Mouse, &Event<'_, ()>, Arc<Window>) -> bool) {
+        Self::with_clock(Arc::new(RealClock))
+    }
+
+    /// Press times, double clicks and click durations are measured with `clock`.
+    pub fn with_clock(
+        clock: Arc<dyn Clock>,
+    ) -> (Self, fn(&Mouse, &Event<'_, ()>, Arc<Window>) -> bool) {
         (
             Self {
                 cursor_position: Cell::new(Vector2 { x: 0.0, y: 0.0 }),
                 mouse_movement: Cell::new(Vector2 { x: 0.0, y: 0.0 }),
-                button_map: UnsafeCell::new(HashMap::new()),
+                button_map: RefCell::new(HashMap::new()),
                 scroll_wheel_movement: Cell::new(0.0),
+                pixels_per_line: Cell::new(DEFAULT_PIXELS_PER_LINE),
+                double_click_time: Cell::new(DEFAULT_DOUBLE_CLICK_TIME),
+                click_map: RefCell::new(HashMap::new()),
+                clock: clock,
             },
             Mouse::_event_handler,
         )
     }
 
+    /// How many pixels of precise scrolling make up one line of `scroll_wheel_movement`.
+    pub fn set_pixels_per_line(&self, pixels_per_line: f32) {
+        self.pixels_per_line.set(pixels_per_line.max(f32::EPSILON));
+    }
+
     pub fn is_button_pressed(&self, button_id: u32) -> bool {
         match self.get_button_state(button_id) {
             Some(ButtonState::Pressed(_)) => true,
@@ -41,46 +78,146 @@ impl Mouse {
 
     pub fn is_button_held(&self, button_id: u32) -> Option<std::time::Duration> {
         match self.get_button_state(button_id) {
-            Some(ButtonState::Held(start)) => Some(std::time::Instant::now() - start),
+            Some(ButtonState::Held(start)) => Some(self.clock.now() - start),
             _ => None,
         }
     }
 
+    /// Sets the longest time between two presses that still counts as a double click.
+    pub fn set_double_click_time(&self, double_click_time: Duration) {
+        self.double_click_time.set(double_click_time);
+    }
+
+    /// True during the tick in which the second press of a double click happened.
+    pub fn was_double_clicked(&self, button_id: u32) -> bool {
+        match self.click_map.try_borrow() {
+            Ok(click_map) => click_map
+                .get(&button_id)
+                .map(|history| history.double_clicked)
+                .unwrap_or(false),
+            Err(_) => false,
+        }
+    }
+
+    /// How long the button was held down the last time it was released.
+    pub fn click_duration(&self, button_id: u32) -> Option<Duration> {
+        self.click_map
+            .try_borrow()
+            .ok()?
+            .get(&button_id)?
+            .last_click_duration
+    }
+
     pub fn get_button_state(&self, button_id: u32) -> Option<ButtonState> {
-        let button_map = unsafe { self.button_map.get().as_ref()? };
-        button_map.get(&button_id).cloned()
+        self.button_map.try_borrow().ok()?.get(&button_id).cloned()
+    }
+
+    // Mouse is only used from one thread, so the borrow can only fail if
+    // the button map is already borrowed further up the stack.
+    fn borrow_button_map_mut(&self) -> Option<RefMut<'_, HashMap<u32, ButtonState>>> {
+        let button_map = self.button_map.try_borrow_mut();
+        debug_assert!(button_map.is_ok(), "Mouse button map is borrowed twice.");
+
+        match button_map {
+            Ok(button_map) => Some(button_map),
+            Err(e) => {
+                println!("Failed to access mouse buttons {e}");
+                None
+            }
+        }
+    }
+
+    /// Updates a button as if the button event came from the mouse.
+    pub(super) fn set_button_state(&self, button: u32, state: ElementState) {
+        let mut button_map = match self.borrow_button_map_mut() {
+            Some(guard) => guard,
+            None => return,
+        };
+        let previous_state = button_map.get(&button).cloned();
+
+        let now = self.clock.now();
+        match previous_state {
+            Some(ButtonState::Pressed(start)) | Some(ButtonState::Held(start)) => {
+                if state == ElementState::Released {
+                    button_map.insert(button, ButtonState::Released);
+                    self.update_click_history(button, |history| {
+                        history.last_click_duration = Some(now - start);
+                    });
+                }
+            }
+            _ => {
+                if state == ElementState::Pressed {
+                    button_map.insert(button, ButtonState::Pressed(now));
+                    let double_click_time = self.double_click_time.get();
+                    self.update_click_history(button, |history| {
+                        history.double_clicked = match history.last_press {
+                            Some(last_press) => now - last_press <= double_click_time,
+                            None => false,
+                        };
+                        // a third press starts a new double click instead of finishing another one
+                        history.last_press = match history.double_clicked {
+                            true => None,
+                            false => Some(now),
+                        };
+                    });
+                }
+            }
+        }
+    }
+
+    fn update_click_history(&self, button: u32, update: impl FnOnce(&mut ClickHistory)) {
+        match self.click_map.try_borrow_mut() {
+            Ok(mut click_map) => update(click_map.entry(button).or_default()),
+            Err(e) => println!("Failed to access mouse clicks {e}"),
+        }
+    }
+
+    /// Converts a position in window pixels to the window centered, y up, coordinates of `cursor_position`.
+    /// Those are in logical pixels like `cartesian_to_normalized`.
+    pub(super) fn window_to_cursor_position(
+        position: PhysicalPosition<f64>,
+        window: &Window,
+    ) -> Vector2<f64> {
+        Self::physical_to_cursor_position(position, window.inner_size(), window.scale_factor())
+    }
+
+    fn physical_to_cursor_position(
+        position: PhysicalPosition<f64>,
+        window_size: PhysicalSize<u32>,
+        scale_factor: f64,
+    ) -> Vector2<f64> {
+        let window_size = window_size.to_logical::<f64>(scale_factor);
+        let position = position.to_logical::<f64>(scale_factor);
+
+        Vector2 {
+            x: -window_size.width / 2.0 + position.x,
+            y: window_size.height / 2.0 - position.y,
+        }
+    }
+
+    // Trackpads report precise scrolling in pixels, which is converted to scroll wheel lines.
+    fn add_scroll(&self, delta: MouseScrollDelta) {
+        let lines = match delta {
+            MouseScrollDelta::LineDelta(_, lines) => lines,
+            MouseScrollDelta::PixelDelta(pos) => pos.y as f32 / self.pixels_per_line.get(),
+        };
+        self.scroll_wheel_movement
+            .set(self.scroll_wheel_movement.get() + lines);
     }
 
     fn _event_handler(&self, event: &Event<'_, ()>, window: Arc<Window>) -> bool {
-        let button_map = unsafe { self.button_map.get().as_mut().unwrap() };
         match event {
             Event::DeviceEvent {
                 event,
                 device_id: _,
             } => {
                 if let DeviceEvent::Button { button, state } = event {
-                    let previous_state = button_map.get(button).cloned();
-
                     match *state {
                         ElementState::Pressed => println!("Button: {button}, Pressed"),
                         ElementState::Released => println!("Button: {button}, Released"),
                     };
 
-                    match previous_state {
-                        Some(ButtonState::Pressed(_)) | Some(ButtonState::Held(_)) => {
-                            if *state == ElementState::Released {
-                                button_map.insert(*button, ButtonState::Released);
-                            }
-                        }
-                        _ => {
-                            if *state == ElementState::Pressed {
-                                button_map.insert(
-                                    *button,
-                                    ButtonState::Pressed(std::time::Instant::now()),
-                                );
-                            }
-                        }
-                    }
+                    self.set_button_state(*button, *state);
                 }
                 if let DeviceEvent::MouseMotion { delta } = event {
                     self.mouse_movement
@@ -88,23 +225,14 @@ impl Mouse {
                     return true;
                 }
                 if let DeviceEvent::MouseWheel { delta } = event {
-                    match delta {
-                        winit::event::MouseScrollDelta::LineDelta(_, val) => {
-                            self.scroll_wheel_movement.set(self.scroll_wheel_movement.get() + val)
-                        },
-                        winit::event::MouseScrollDelta::PixelDelta(_) => unimplemented!(),
-                    }
+                    self.add_scroll(*delta);
                 }
                 return false;
             }
             Event::WindowEvent { event, .. } => {
                 if let WindowEvent::CursorMoved { position, .. } = event {
-                    let window_size = window.inner_size();
-
-                    self.cursor_position.set(Vector2 {
-                        x: -((window_size.width / 2) as f64) + position.x,
-                        y: (window_size.height / 2) as f64 - position.y,
-                    });
+                    self.cursor_position
+                        .set(Mouse::window_to_cursor_position(*position, &window));
                     return true;
                 }
 
@@ -117,11 +245,140 @@ impl Mouse {
     pub fn clear_presses(&self) {
         self.scroll_wheel_movement.set(0.0);
         self.mouse_movement.set(Vector2::new(0.0, 0.0));
-        let button_map = unsafe { self.button_map.get().as_mut().unwrap() };
+        let mut button_map = match self.borrow_button_map_mut() {
+            Some(guard) => guard,
+            None => return,
+        };
         for (_, state) in button_map.iter_mut() {
             if let ButtonState::Pressed(inst) = *state {
                 *state = ButtonState::Held(inst);
             }
         }
+
+        if let Ok(mut click_map) = self.click_map.try_borrow_mut() {
+            click_map
+                .values_mut()
+                .for_each(|history| history.double_clicked = false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timing::ManualClock;
+
+    fn click(mouse: &Mouse, clock: &ManualClock, held_for: Duration) {
+        mouse.set_button_state(1, ElementState::Pressed);
+        clock.advance(held_for);
+        mouse.set_button_state(1, ElementState::Released);
+    }
+
+    #[test]
+    fn clear_presses_turns_presses_into_holds() {
+        let (mouse, _) = Mouse::new();
+        mouse.set_button_state(1, ElementState::Pressed);
+        assert!(mouse.is_button_pressed(1));
+
+        mouse.clear_presses();
+        let state = mouse.get_button_state(1);
+        assert!(matches!(state, Some(ButtonState::Held(_))));
+
+        mouse.set_button_state(1, ElementState::Released);
+        let state = mouse.get_button_state(1);
+        assert!(matches!(state, Some(ButtonState::Released)));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Mouse button map is borrowed twice.")]
+    fn aliasing_borrow_of_the_button_map_is_caught() {
+        let (mouse, _) = Mouse::new();
+        let _button_map = mouse.button_map.borrow();
+        mouse.set_button_state(1, ElementState::Pressed);
+    }
+
+    #[test]
+    fn pixel_and_line_scrolling_give_comparable_amounts() {
+        let pixels = |y: f64| MouseScrollDelta::PixelDelta(PhysicalPosition::new(0.0, y));
+        let (mouse, _) = Mouse::new();
+        mouse.add_scroll(MouseScrollDelta::LineDelta(0.0, 2.0));
+        let line_scroll = mouse.scroll_wheel_movement.get();
+        assert_eq!(line_scroll, 2.0);
+
+        mouse.clear_presses();
+        mouse.add_scroll(pixels(2.0 * DEFAULT_PIXELS_PER_LINE as f64));
+        assert_eq!(mouse.scroll_wheel_movement.get(), line_scroll);
+
+        mouse.clear_presses();
+        mouse.set_pixels_per_line(10.0);
+        mouse.add_scroll(pixels(-5.0));
+        assert_eq!(mouse.scroll_wheel_movement.get(), -0.5);
+    }
+
+    #[test]
+    fn presses_inside_the_double_click_time_are_a_double_click() {
+        let clock = ManualClock::new();
+        let (mouse, _) = Mouse::with_clock(clock.clone());
+        mouse.set_double_click_time(Duration::from_millis(400));
+
+        click(&mouse, &clock, Duration::from_millis(50));
+        assert!(!mouse.was_double_clicked(1));
+        clock.advance(Duration::from_millis(100));
+        mouse.clear_presses();
+
+        mouse.set_button_state(1, ElementState::Pressed);
+        assert!(mouse.was_double_clicked(1));
+
+        // only counts for the tick of the second press
+        mouse.clear_presses();
+        assert!(!mouse.was_double_clicked(1));
+    }
+
+    #[test]
+    fn presses_outside_the_double_click_time_are_single_clicks() {
+        let clock = ManualClock::new();
+        let (mouse, _) = Mouse::with_clock(clock.clone());
+        mouse.set_double_click_time(Duration::from_millis(400));
+
+        click(&mouse, &clock, Duration::from_millis(50));
+        clock.advance(Duration::from_millis(500));
+        mouse.clear_presses();
+
+        mouse.set_button_state(1, ElementState::Pressed);
+        assert!(!mouse.was_double_clicked(1));
+    }
+
+    #[test]
+    fn click_duration_is_the_time_between_press_and_release() {
+        let clock = ManualClock::new();
+        let (mouse, _) = Mouse::with_clock(clock.clone());
+        assert_eq!(mouse.click_duration(1), None);
+
+        click(&mouse, &clock, Duration::from_millis(250));
+        assert_eq!(mouse.click_duration(1), Some(Duration::from_millis(250)));
+
+        // a press that is still held doesn't replace the last completed one
+        mouse.set_button_state(1, ElementState::Pressed);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(mouse.click_duration(1), Some(Duration::from_millis(250)));
+        mouse.clear_presses();
+        assert_eq!(mouse.is_button_held(1), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn cursor_position_is_in_logical_pixels() {
+        let regular = Mouse::physical_to_cursor_position(
+            PhysicalPosition::new(600.0, 150.0),
+            PhysicalSize::new(800, 600),
+            1.0,
+        );
+        let high_dpi = Mouse::physical_to_cursor_position(
+            PhysicalPosition::new(1200.0, 300.0),
+            PhysicalSize::new(1600, 1200),
+            2.0,
+        );
+        assert_eq!(regular, Vector2::new(200.0, 150.0));
+        assert_eq!(high_dpi, regular);
     }
 }
@@ -0,0 +1,231 @@
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use cgmath::Vector2;
+use winit::{
+    event::{ElementState, Event, TouchPhase, WindowEvent},
+    window::Window,
+};
+
+use super::{ButtonState, Mouse};
+use crate::timing::{Clock, RealClock};
+
+// The mouse button a single finger is reported as.
+const EMULATED_MOUSE_BUTTON: u32 = 1;
+
+#[derive(Clone, Debug)]
+pub struct TouchPoint {
+    pub id: u64,
+    /// Same coordinate system as `Mouse::cursor_position`.
+    pub position: Vector2<f64>,
+    pub state: ButtonState,
+}
+
+pub struct Touch {
+    touch_map: RwLock<HashMap<u64, TouchPoint>>,
+    // the finger that is currently acting as the mouse
+    emulated_mouse_id: Cell<Option<u64>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Touch {
+    pub fn new() -> (
+        Self,
+        fn(&Touch, &Event<'_, ()>, &Mouse, Arc<Window>) -> bool,
+    ) {
+        Self::with_clock(Arc::new(RealClock))
+    }
+
+    /// The press times of touch points are read from `clock`.
+    pub fn with_clock(
+        clock: Arc<dyn Clock>,
+    ) -> (
+        Self,
+        fn(&Touch, &Event<'_, ()>, &Mouse, Arc<Window>) -> bool,
+    ) {
+        (
+            Self {
+                touch_map: RwLock::new(HashMap::new()),
+                emulated_mouse_id: Cell::new(None),
+                clock: clock,
+            },
+            Touch::_event_handler,
+        )
+    }
+
+    pub fn get_touch(&self, id: u64) -> Option<TouchPoint> {
+        self.touch_map.read().ok()?.get(&id).cloned()
+    }
+
+    /// Every finger currently on the screen, including the ones that were lifted this tick.
+    pub fn touches(&self) -> Vec<TouchPoint> {
+        match self.touch_map.read() {
+            Ok(guard) => guard.values().cloned().collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    pub fn active_touch_count(&self) -> usize {
+        match self.touch_map.read() {
+            Ok(guard) => guard
+                .values()
+                .filter(|touch| !matches!(touch.state, ButtonState::Released))
+                .count(),
+            Err(_) => 0,
+        }
+    }
+
+    fn _event_handler(&self, event: &Event<'_, ()>, mouse: &Mouse, window: Arc<Window>) -> bool {
+        let touch = match event {
+            Event::WindowEvent {
+                event: WindowEvent::Touch(touch),
+                ..
+            } => touch,
+            _ => return false,
+        };
+
+        let position = Mouse::window_to_cursor_position(touch.location, &window);
+        self.handle_touch(touch.id, touch.phase, position, mouse);
+
+        true
+    }
+
+    fn handle_touch(&self, id: u64, phase: TouchPhase, position: Vector2<f64>, mouse: &Mouse) {
+        let previous_position = match self.touch_map.write() {
+            Ok(mut guard) => {
+                let previous_position = guard.get(&id).map(|p| p.position);
+                let state = match phase {
+                    TouchPhase::Started => ButtonState::Pressed(self.clock.now()),
+                    TouchPhase::Moved => match guard.get(&id) {
+                        Some(point) => point.state.clone(),
+                        None => ButtonState::Pressed(self.clock.now()),
+                    },
+                    TouchPhase::Ended | TouchPhase::Cancelled => ButtonState::Released,
+                };
+
+                guard.insert(
+                    id,
+                    TouchPoint {
+                        id: id,
+                        position: position,
+                        state: state,
+                    },
+                );
+                previous_position
+            }
+            Err(_) => None,
+        };
+
+        // The first finger acts as the mouse so the existing ui works on touch screens.
+        if phase == TouchPhase::Started && self.emulated_mouse_id.get().is_none() {
+            if self.active_touch_count() == 1 {
+                self.emulated_mouse_id.set(Some(id));
+                mouse.cursor_position.set(position);
+                mouse.set_button_state(EMULATED_MOUSE_BUTTON, ElementState::Pressed);
+            }
+        }
+
+        if self.emulated_mouse_id.get() == Some(id) {
+            if let Some(previous_position) = previous_position {
+                // mouse movement is in window pixels with y pointing down
+                let delta = position - previous_position;
+                mouse
+                    .mouse_movement
+                    .set(mouse.mouse_movement.get() + Vector2::new(delta.x, -delta.y));
+            }
+            mouse.cursor_position.set(position);
+
+            if let TouchPhase::Ended | TouchPhase::Cancelled = phase {
+                self.emulated_mouse_id.set(None);
+                mouse.set_button_state(EMULATED_MOUSE_BUTTON, ElementState::Released);
+            }
+        }
+    }
+
+    pub fn clear_presses(&self) {
+        match self.touch_map.write() {
+            Ok(mut guard) => {
+                guard.retain(|_, touch| !matches!(touch.state, ButtonState::Released));
+                guard.iter_mut().for_each(|(_, touch)| {
+                    if let ButtonState::Pressed(time) = touch.state {
+                        touch.state = ButtonState::Held(time);
+                    }
+                });
+            }
+            Err(e) => {
+                println!("Failed to access touch points {e}");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timing::ManualClock;
+
+    #[test]
+    fn first_finger_acts_as_mouse_button_one() {
+        let (touch, _) = Touch::new();
+        let (mouse, _) = Mouse::new();
+
+        touch.handle_touch(7, TouchPhase::Started, Vector2::new(10.0, 20.0), &mouse);
+        assert!(mouse.is_button_pressed(EMULATED_MOUSE_BUTTON));
+        assert_eq!(mouse.cursor_position.get(), Vector2::new(10.0, 20.0));
+
+        touch.handle_touch(7, TouchPhase::Moved, Vector2::new(15.0, 20.0), &mouse);
+        assert_eq!(mouse.cursor_position.get(), Vector2::new(15.0, 20.0));
+        assert_eq!(mouse.mouse_movement.get().x, 5.0);
+
+        touch.handle_touch(7, TouchPhase::Ended, Vector2::new(15.0, 20.0), &mouse);
+        assert!(matches!(
+            mouse.get_button_state(EMULATED_MOUSE_BUTTON),
+            Some(ButtonState::Released)
+        ));
+    }
+
+    #[test]
+    fn touches_are_pressed_at_the_time_of_the_clock() {
+        let clock = ManualClock::new();
+        let (touch, _) = Touch::with_clock(clock.clone());
+        let (mouse, _) = Mouse::with_clock(clock.clone());
+
+        clock.advance(std::time::Duration::from_secs(3));
+        touch.handle_touch(4, TouchPhase::Started, Vector2::new(0.0, 0.0), &mouse);
+        match touch.get_touch(4).unwrap().state {
+            ButtonState::Pressed(time) => assert_eq!(time, clock.now()),
+            state => panic!("a started touch is pressed, not {state:?}"),
+        }
+    }
+
+    #[test]
+    fn fingers_are_tracked_by_id() {
+        let (touch, _) = Touch::new();
+        let (mouse, _) = Mouse::new();
+
+        touch.handle_touch(1, TouchPhase::Started, Vector2::new(0.0, 0.0), &mouse);
+        touch.handle_touch(2, TouchPhase::Started, Vector2::new(50.0, 50.0), &mouse);
+        touch.handle_touch(2, TouchPhase::Moved, Vector2::new(60.0, 40.0), &mouse);
+        assert_eq!(touch.active_touch_count(), 2);
+        assert_eq!(touch.get_touch(1).unwrap().position, Vector2::new(0.0, 0.0));
+        assert_eq!(
+            touch.get_touch(2).unwrap().position,
+            Vector2::new(60.0, 40.0)
+        );
+
+        // only the first finger moves the mouse
+        assert_eq!(mouse.cursor_position.get(), Vector2::new(0.0, 0.0));
+
+        touch.handle_touch(1, TouchPhase::Ended, Vector2::new(0.0, 0.0), &mouse);
+        touch.clear_presses();
+        assert_eq!(touch.active_touch_count(), 1);
+        assert!(touch.get_touch(1).is_none());
+        assert!(matches!(
+            touch.get_touch(2).unwrap().state,
+            ButtonState::Held(_)
+        ));
+    }
+}
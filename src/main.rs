@@ -14,17 +14,25 @@ mod app;
 mod drawables;
 mod graphics;
 mod input;
+mod math;
+mod navigation;
+mod network;
+mod timing;
 
 fn main() {
     // initialize subsystems
-    let (mut gfx, event_loop) = Graphics::new();
+    let (mut gfx, event_loop) = match Graphics::new() {
+        Ok(graphics) => graphics,
+        Err(e) => {
+            println!("Could not start the game: {e}");
+            std::process::exit(1);
+        }
+    };
     let input = input::Input::new(gfx.get_window());
 
     // initialize app and pass it a reference to each subsystem
     let mut app = App::new(&mut gfx, input.clone());
 
-    let mut minimized = false;
-
     event_loop.run(move |event, _window_target, control_flow| {
         let event_handled = input.handle_event(&event, gfx.get_window());
 
@@ -35,32 +43,34 @@ fn main() {
         match event {
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
-                ..
-            } => {
-                *control_flow = ControlFlow::Exit;
-            }
-            Event::WindowEvent {
-                event: WindowEvent::Resized(_),
-                ..
+                window_id,
             } => {
-                minimized = is_minimized(gfx.get_window());
-
-                if !minimized {
-                    app.resize_callback(&mut gfx);
+                if gfx.is_main_window(window_id) {
+                    if let Err(e) = gfx.save_pipeline_cache(graphics::PIPELINE_CACHE_PATH) {
+                        println!("Failed to save pipeline cache: {e}");
+                    }
+                    *control_flow = ControlFlow::Exit;
+                } else {
+                    gfx.close_window(window_id);
                 }
             }
+            // the logical pixels everything is drawn in change size with the scale factor
             Event::WindowEvent {
-                event: WindowEvent::Focused(false),
-                ..
+                event: WindowEvent::Resized(_) | WindowEvent::ScaleFactorChanged { .. },
+                window_id,
             } => {
-                minimized = is_minimized(gfx.get_window());
+                if let Some(window) = gfx.get_window_by_id(window_id) {
+                    if !is_minimized(window) {
+                        app.resize_callback(&mut gfx, window_id);
+                    }
+                }
             }
             Event::RedrawEventsCleared => {
+                #[cfg(feature = "hot-reload")]
+                gfx.reload_changed_textures();
                 app.run(&gfx);
-                if !minimized {
-                    gfx.draw_frame()
-                }
-                input.clear_presses();
+                // minimized windows are skipped by draw_frame
+                gfx.draw_frame()
             }
             _ => (),
         }
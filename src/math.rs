@@ -0,0 +1,49 @@
+/// An axis aligned rectangle in world space.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rect {
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+impl Rect {
+    pub fn new(min: [f32; 2], max: [f32; 2]) -> Self {
+        Self { min: min, max: max }
+    }
+
+    pub fn from_center(center: [f32; 2], half_extent: [f32; 2]) -> Self {
+        Self {
+            min: [center[0] - half_extent[0], center[1] - half_extent[1]],
+            max: [center[0] + half_extent[0], center[1] + half_extent[1]],
+        }
+    }
+
+    pub fn width(&self) -> f32 {
+        self.max[0] - self.min[0]
+    }
+
+    pub fn height(&self) -> f32 {
+        self.max[1] - self.min[1]
+    }
+
+    pub fn center(&self) -> [f32; 2] {
+        [
+            (self.min[0] + self.max[0]) / 2.0,
+            (self.min[1] + self.max[1]) / 2.0,
+        ]
+    }
+
+    pub fn contains(&self, point: [f32; 2]) -> bool {
+        point[0] >= self.min[0]
+            && point[0] <= self.max[0]
+            && point[1] >= self.min[1]
+            && point[1] <= self.max[1]
+    }
+
+    /// Touching edges count as intersecting.
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.min[0] <= other.max[0]
+            && self.max[0] >= other.min[0]
+            && self.min[1] <= other.max[1]
+            && self.max[1] >= other.min[1]
+    }
+}
@@ -0,0 +1,221 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+};
+
+use crate::drawables::tiles::StaticTileGroup;
+
+// Step costs are kept as integers so they can be ordered in the open set,
+// a diagonal step costs about sqrt(2) times a straight one.
+const STRAIGHT_COST: u32 = 10;
+const DIAGONAL_COST: u32 = 14;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Connectivity {
+    /// Only up, down, left and right.
+    Four,
+    /// Diagonals too, but never past the corner of a blocked tile.
+    Eight,
+}
+
+/// Which tiles of a grid can be walked on. Tile positions are `[column, row]`
+/// with rows growing downwards, the same as `StaticTileGroup`.
+pub struct NavGrid {
+    dimensions: [u32; 2],
+    blocked: Vec<bool>,
+}
+
+impl NavGrid {
+    /// A grid where every tile can be walked on.
+    pub fn new(dimensions: [u32; 2]) -> Self {
+        Self {
+            dimensions: dimensions,
+            blocked: vec![false; (dimensions[0] * dimensions[1]) as usize],
+        }
+    }
+
+    /// `is_blocked` gets the sprite index of every tile without the flip flags, `None` for empty tiles.
+    pub fn from_tile_group(
+        tile_group: &StaticTileGroup,
+        is_blocked: impl Fn(Option<u32>) -> bool,
+    ) -> Self {
+        let dimensions = tile_group.dimensions();
+        let mut nav_grid = Self::new(dimensions);
+        for y in 0..dimensions[1] {
+            for x in 0..dimensions[0] {
+                let tile = tile_group.get_tile([x as i32, y as i32]);
+                nav_grid.set_blocked([x, y], is_blocked(tile));
+            }
+        }
+        nav_grid
+    }
+
+    pub fn dimensions(&self) -> [u32; 2] {
+        self.dimensions
+    }
+
+    /// Call it when a tile changes, positions outside of the grid are ignored.
+    pub fn set_blocked(&mut self, tile: [u32; 2], blocked: bool) {
+        if let Some(index) = self.index(tile) {
+            self.blocked[index] = blocked;
+        }
+    }
+
+    /// Positions outside of the grid count as blocked.
+    pub fn is_blocked(&self, tile: [u32; 2]) -> bool {
+        match self.index(tile) {
+            Some(index) => self.blocked[index],
+            None => true,
+        }
+    }
+
+    /// The shortest path from `start` to `goal`, both included.
+    /// `None` if either end is blocked or the goal can't be reached.
+    pub fn a_star(
+        &self,
+        start: [u32; 2],
+        goal: [u32; 2],
+        connectivity: Connectivity,
+    ) -> Option<Vec<[u32; 2]>> {
+        if self.is_blocked(start) || self.is_blocked(goal) {
+            return None;
+        }
+
+        let mut open = BinaryHeap::new();
+        let mut came_from: HashMap<[u32; 2], [u32; 2]> = HashMap::new();
+        let mut cost_so_far: HashMap<[u32; 2], u32> = HashMap::new();
+
+        open.push(Reverse((heuristic(start, goal, connectivity), start)));
+        cost_so_far.insert(start, 0);
+
+        while let Some(Reverse((_, current))) = open.pop() {
+            if current == goal {
+                let mut path = vec![goal];
+                let mut tile = goal;
+                while let Some(&previous) = came_from.get(&tile) {
+                    path.push(previous);
+                    tile = previous;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            let current_cost = cost_so_far[&current];
+            for (neighbour, step_cost) in self.neighbours(current, connectivity) {
+                let cost = current_cost + step_cost;
+                if cost_so_far
+                    .get(&neighbour)
+                    .map_or(true, |&known| cost < known)
+                {
+                    cost_so_far.insert(neighbour, cost);
+                    came_from.insert(neighbour, current);
+                    let priority = cost + heuristic(neighbour, goal, connectivity);
+                    open.push(Reverse((priority, neighbour)));
+                }
+            }
+        }
+
+        None
+    }
+
+    fn neighbours(&self, tile: [u32; 2], connectivity: Connectivity) -> Vec<([u32; 2], u32)> {
+        let walkable = |offset: [i32; 2]| -> Option<[u32; 2]> {
+            let x = tile[0].checked_add_signed(offset[0])?;
+            let y = tile[1].checked_add_signed(offset[1])?;
+            match self.is_blocked([x, y]) {
+                true => None,
+                false => Some([x, y]),
+            }
+        };
+
+        let mut neighbours = Vec::with_capacity(8);
+        for offset in [[1, 0], [-1, 0], [0, 1], [0, -1]] {
+            if let Some(neighbour) = walkable(offset) {
+                neighbours.push((neighbour, STRAIGHT_COST));
+            }
+        }
+
+        if connectivity == Connectivity::Eight {
+            for offset in [[1, 1], [1, -1], [-1, 1], [-1, -1]] {
+                // both tiles next to the corner have to be free, otherwise the path clips it
+                let corner_free =
+                    walkable([offset[0], 0]).is_some() && walkable([0, offset[1]]).is_some();
+                if let (true, Some(neighbour)) = (corner_free, walkable(offset)) {
+                    neighbours.push((neighbour, DIAGONAL_COST));
+                }
+            }
+        }
+
+        neighbours
+    }
+
+    fn index(&self, tile: [u32; 2]) -> Option<usize> {
+        match tile[0] < self.dimensions[0] && tile[1] < self.dimensions[1] {
+            true => Some((tile[1] * self.dimensions[0] + tile[0]) as usize),
+            false => None,
+        }
+    }
+}
+
+// Never overestimates, so the path found is the shortest one.
+fn heuristic(from: [u32; 2], to: [u32; 2], connectivity: Connectivity) -> u32 {
+    let dx = from[0].abs_diff(to[0]);
+    let dy = from[1].abs_diff(to[1]);
+    match connectivity {
+        Connectivity::Four => (dx + dy) * STRAIGHT_COST,
+        Connectivity::Eight => {
+            STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_detours_around_wall() {
+        // a wall down the middle column with a gap at the bottom
+        let mut grid = NavGrid::new([5, 5]);
+        for y in 0..4 {
+            grid.set_blocked([2, y], true);
+        }
+
+        let path = grid.a_star([0, 0], [4, 0], Connectivity::Four).unwrap();
+        assert_eq!(path.first(), Some(&[0, 0]));
+        assert_eq!(path.last(), Some(&[4, 0]));
+        assert!(path.contains(&[2, 4]));
+        assert!(path.iter().all(|&tile| !grid.is_blocked(tile)));
+        // down 4, across 4, up 4
+        assert_eq!(path.len(), 13);
+    }
+
+    #[test]
+    fn unreachable_goal_returns_none() {
+        let mut grid = NavGrid::new([5, 5]);
+        for y in 0..5 {
+            grid.set_blocked([2, y], true);
+        }
+
+        assert_eq!(grid.a_star([0, 0], [4, 4], Connectivity::Eight), None);
+        assert_eq!(grid.a_star([0, 0], [2, 2], Connectivity::Four), None);
+    }
+
+    #[test]
+    fn start_equal_to_goal_is_single_tile() {
+        let grid = NavGrid::new([3, 3]);
+        assert_eq!(
+            grid.a_star([1, 1], [1, 1], Connectivity::Four),
+            Some(vec![[1, 1]])
+        );
+    }
+
+    #[test]
+    fn diagonals_do_not_cut_corners() {
+        let mut grid = NavGrid::new([2, 2]);
+        grid.set_blocked([1, 0], true);
+
+        let path = grid.a_star([0, 0], [1, 1], Connectivity::Eight).unwrap();
+        assert_eq!(path, vec![[0, 0], [0, 1], [1, 1]]);
+    }
+}
@@ -0,0 +1,229 @@
+use std::{collections::HashMap, sync::Arc, time::Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::timing::{Clock, RealClock};
+
+/// The replicated part of an entity. Only what is needed to draw a remote entity is sent.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct EntityState {
+    pub id: u32,
+    pub position: [f32; 2],
+    pub velocity: [f32; 2],
+    pub animation_frame: u32,
+}
+
+/// A full copy of every replicated entity at a given simulation tick.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub tick: u64,
+    pub entities: Vec<EntityState>,
+}
+
+impl Snapshot {
+    pub fn new(tick: u64, entities: Vec<EntityState>) -> Self {
+        Self {
+            tick: tick,
+            entities: entities,
+        }
+    }
+
+    pub fn encode(&self) -> Result<Vec<u8>, ron::Error> {
+        ron::to_string(self).map(String::into_bytes)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, ron::error::SpannedError> {
+        ron::de::from_bytes(bytes)
+    }
+}
+
+struct RemoteEntity {
+    previous: EntityState,
+    target: EntityState,
+}
+
+/// Keeps track of the entities received from a remote peer and smooths
+/// their movement between the snapshots that arrive.
+/// Transport is not handled here, feed it whatever bytes arrive from the socket.
+pub struct NetworkSync {
+    remote_entities: HashMap<u32, RemoteEntity>,
+    last_tick: Option<u64>,
+    last_snapshot_time: Instant,
+    snapshot_interval: std::time::Duration,
+    clock: Arc<dyn Clock>,
+}
+
+impl NetworkSync {
+    pub fn new(snapshot_interval: std::time::Duration) -> Self {
+        Self::with_clock(snapshot_interval, Arc::new(RealClock))
+    }
+
+    pub fn with_clock(snapshot_interval: std::time::Duration, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            remote_entities: HashMap::new(),
+            last_tick: None,
+            last_snapshot_time: clock.now(),
+            snapshot_interval: snapshot_interval,
+            clock: clock,
+        }
+    }
+
+    /// Builds a snapshot of the local entities that can be sent to the other peers.
+    pub fn create_snapshot(
+        &self,
+        tick: u64,
+        entities: impl IntoIterator<Item = EntityState>,
+    ) -> Snapshot {
+        Snapshot::new(tick, entities.into_iter().collect())
+    }
+
+    /// Decodes and applies a snapshot received from a remote peer.
+    pub fn receive(&mut self, bytes: &[u8]) -> Result<(), ron::error::SpannedError> {
+        let snapshot = Snapshot::decode(bytes)?;
+        self.apply_snapshot(snapshot);
+        Ok(())
+    }
+
+    /// Sets the snapshot as the new interpolation target.
+    /// Entities start moving from wherever they are currently drawn, so a late
+    /// snapshot doesn't make them jump. Snapshots older than the last applied one are ignored.
+    pub fn apply_snapshot(&mut self, snapshot: Snapshot) {
+        if let Some(last_tick) = self.last_tick {
+            if snapshot.tick <= last_tick {
+                return;
+            }
+        }
+
+        let alpha = self.interpolation_alpha();
+
+        let mut remote_entities = HashMap::with_capacity(snapshot.entities.len());
+        for state in snapshot.entities {
+            let previous = match self.remote_entities.get(&state.id) {
+                Some(entity) => interpolate(&entity.previous, &entity.target, alpha),
+                None => state,
+            };
+
+            remote_entities.insert(
+                state.id,
+                RemoteEntity {
+                    previous: previous,
+                    target: state,
+                },
+            );
+        }
+
+        self.remote_entities = remote_entities;
+        self.last_tick = Some(snapshot.tick);
+        self.last_snapshot_time = self.clock.now();
+    }
+
+    pub fn get_entity_state(&self, id: u32) -> Option<EntityState> {
+        let alpha = self.interpolation_alpha();
+        self.remote_entities
+            .get(&id)
+            .map(|entity| interpolate(&entity.previous, &entity.target, alpha))
+    }
+
+    pub fn entity_states(&self) -> Vec<EntityState> {
+        let alpha = self.interpolation_alpha();
+        self.remote_entities
+            .values()
+            .map(|entity| interpolate(&entity.previous, &entity.target, alpha))
+            .collect()
+    }
+
+    pub fn get_last_tick(&self) -> Option<u64> {
+        self.last_tick
+    }
+
+    fn interpolation_alpha(&self) -> f32 {
+        if self.snapshot_interval.is_zero() {
+            return 1.0;
+        }
+        let elapsed = (self.clock.now() - self.last_snapshot_time).as_secs_f32();
+        (elapsed / self.snapshot_interval.as_secs_f32()).min(1.0)
+    }
+}
+
+fn interpolate(from: &EntityState, to: &EntityState, alpha: f32) -> EntityState {
+    let lerp = |a: f32, b: f32| a + (b - a) * alpha;
+    EntityState {
+        id: to.id,
+        position: [
+            lerp(from.position[0], to.position[0]),
+            lerp(from.position[1], to.position[1]),
+        ],
+        velocity: to.velocity,
+        animation_frame: to.animation_frame,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::timing::ManualClock;
+
+    fn entity(id: u32, position: [f32; 2]) -> EntityState {
+        EntityState {
+            id: id,
+            position: position,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn snapshot_survives_encode_decode() {
+        let snapshot = Snapshot::new(
+            42,
+            vec![
+                EntityState {
+                    id: 1,
+                    position: [1.5, -2.0],
+                    velocity: [0.25, 0.0],
+                    animation_frame: 3,
+                },
+                entity(7, [0.0, 8.0]),
+            ],
+        );
+
+        let bytes = snapshot.encode().unwrap();
+        assert_eq!(Snapshot::decode(&bytes).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn apply_snapshot_interpolates_towards_the_new_target() {
+        let clock = ManualClock::new();
+        let mut sync = NetworkSync::with_clock(Duration::from_secs(1), clock.clone());
+
+        sync.apply_snapshot(Snapshot::new(1, vec![entity(1, [0.0, 0.0])]));
+        sync.apply_snapshot(Snapshot::new(2, vec![entity(1, [8.0, 4.0])]));
+        assert_eq!(sync.get_entity_state(1).unwrap().position, [0.0, 0.0]);
+
+        clock.advance(Duration::from_millis(250));
+        assert_eq!(sync.get_entity_state(1).unwrap().position, [2.0, 1.0]);
+
+        // a snapshot older than the last one doesn't move anything
+        sync.apply_snapshot(Snapshot::new(1, vec![entity(1, [100.0, 100.0])]));
+        assert_eq!(sync.get_last_tick(), Some(2));
+        assert_eq!(sync.get_entity_state(1).unwrap().position, [2.0, 1.0]);
+
+        clock.advance(Duration::from_secs(2));
+        assert_eq!(sync.get_entity_state(1).unwrap().position, [8.0, 4.0]);
+    }
+
+    #[test]
+    fn late_snapshot_starts_from_the_drawn_position() {
+        let clock = ManualClock::new();
+        let mut sync = NetworkSync::with_clock(Duration::from_secs(1), clock.clone());
+
+        sync.apply_snapshot(Snapshot::new(1, vec![entity(1, [0.0, 0.0])]));
+        sync.apply_snapshot(Snapshot::new(2, vec![entity(1, [8.0, 0.0])]));
+        clock.advance(Duration::from_millis(500));
+
+        sync.apply_snapshot(Snapshot::new(3, vec![entity(1, [20.0, 0.0])]));
+        assert_eq!(sync.get_entity_state(1).unwrap().position, [4.0, 0.0]);
+        assert_eq!(sync.get_entity_state(2), None);
+    }
+}
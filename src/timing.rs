@@ -0,0 +1,241 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+// Limits how many ticks are run in a single update so a long stall (dragging the window,
+// a breakpoint) doesn't make the game try to catch up forever.
+const MAX_TICKS_PER_UPDATE: u32 = 5;
+
+/// Where time is read from. Everything that depends on time takes a clock
+/// so a `ManualClock` can be swapped in to step time by hand.
+/// Clocks are shared between threads, like input read on a logic thread.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+pub struct RealClock;
+
+impl Clock for RealClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when told to.
+pub struct ManualClock {
+    now: Mutex<Instant>,
+}
+
+impl ManualClock {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self {
+            now: Mutex::new(Instant::now()),
+        })
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        match self.now.lock() {
+            Ok(mut now) => *now += duration,
+            Err(e) => println!("Failed to advance clock {e}"),
+        }
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        match self.now.lock() {
+            Ok(now) => *now,
+            Err(e) => *e.into_inner(),
+        }
+    }
+}
+
+/// Accumulates real time and hands it out in fixed sized ticks,
+/// so the game logic runs at the same rate no matter how fast frames are rendered.
+pub struct FixedTimestep {
+    tick_duration: Duration,
+    accumulator: Duration,
+    last_update: Instant,
+    clock: Arc<dyn Clock>,
+}
+
+impl FixedTimestep {
+    pub fn new(ticks_per_second: u32) -> Self {
+        Self::with_clock(ticks_per_second, Arc::new(RealClock))
+    }
+
+    pub fn with_clock(ticks_per_second: u32, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            tick_duration: tick_duration(ticks_per_second),
+            accumulator: Duration::ZERO,
+            last_update: clock.now(),
+            clock: clock,
+        }
+    }
+
+    /// The current time of the clock the timestep runs on.
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    /// Full ticks of the new rate that are already accumulated are dropped,
+    /// so `alpha` stays below one until the next `advance`.
+    pub fn set_tick_rate(&mut self, ticks_per_second: u32) {
+        self.tick_duration = tick_duration(ticks_per_second);
+        let remainder = self.accumulator.as_nanos() % self.tick_duration.as_nanos();
+        self.accumulator = Duration::from_nanos(remainder as u64);
+    }
+
+    pub fn get_tick_duration(&self) -> Duration {
+        self.tick_duration
+    }
+
+    /// Returns the number of ticks that should be run this frame.
+    pub fn advance(&mut self) -> u32 {
+        let now = self.clock.now();
+        self.accumulator += now - self.last_update;
+        self.last_update = now;
+
+        let mut ticks = 0;
+        while self.accumulator >= self.tick_duration {
+            self.accumulator -= self.tick_duration;
+            ticks += 1;
+
+            if ticks == MAX_TICKS_PER_UPDATE {
+                self.accumulator = Duration::ZERO;
+                break;
+            }
+        }
+        ticks
+    }
+
+    /// How far between the last tick and the next one we are, in the range `0.0..1.0`.
+    /// Use it to interpolate what is drawn between the previous and current tick state.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.tick_duration.as_secs_f32()
+    }
+}
+
+fn tick_duration(ticks_per_second: u32) -> Duration {
+    Duration::from_secs(1) / ticks_per_second.max(1)
+}
+
+/// The state of a value at the previous and current tick.
+#[derive(Clone, Copy, Debug)]
+pub struct Interpolated<T> {
+    pub previous: T,
+    pub current: T,
+}
+
+impl<T> Interpolated<T>
+where
+    T: Copy + Lerp,
+{
+    pub fn new(value: T) -> Self {
+        Self {
+            previous: value,
+            current: value,
+        }
+    }
+
+    /// Call once per tick with the new state.
+    pub fn set(&mut self, value: T) {
+        self.previous = self.current;
+        self.current = value;
+    }
+
+    /// Jumps to a value without interpolating from the old one.
+    pub fn reset(&mut self, value: T) {
+        self.previous = value;
+        self.current = value;
+    }
+
+    pub fn get(&self, alpha: f32) -> T {
+        self.previous.lerp(&self.current, alpha)
+    }
+}
+
+pub trait Lerp {
+    fn lerp(&self, other: &Self, alpha: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(&self, other: &Self, alpha: f32) -> Self {
+        self + (other - self) * alpha
+    }
+}
+
+impl Lerp for [f32; 2] {
+    fn lerp(&self, other: &Self, alpha: f32) -> Self {
+        [
+            self[0].lerp(&other[0], alpha),
+            self[1].lerp(&other[1], alpha),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timesteps_can_move_to_a_logic_thread() {
+        let clock = ManualClock::new();
+        let mut timestep = FixedTimestep::with_clock(60, clock.clone());
+
+        clock.advance(Duration::from_millis(40));
+        let logic_thread = std::thread::spawn(move || timestep.advance());
+        assert_eq!(logic_thread.join().unwrap(), 2);
+    }
+
+    #[test]
+    fn long_stalls_are_clamped_to_max_ticks() {
+        let clock = ManualClock::new();
+        let mut timestep = FixedTimestep::with_clock(60, clock.clone());
+
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(timestep.advance(), MAX_TICKS_PER_UPDATE);
+        // the rest of the stall is dropped instead of being caught up on later
+        assert_eq!(timestep.alpha(), 0.0);
+        assert_eq!(timestep.advance(), 0);
+    }
+
+    #[test]
+    fn alpha_stays_below_one() {
+        let clock = ManualClock::new();
+        let mut timestep = FixedTimestep::with_clock(60, clock.clone());
+        let step = timestep.get_tick_duration() / 7;
+
+        for _ in 0..50 {
+            clock.advance(step);
+            timestep.advance();
+            let alpha = timestep.alpha();
+            assert!((0.0..1.0).contains(&alpha), "alpha {alpha} out of range");
+        }
+
+        // most of a tick at 60 is more than a full tick at 120 and 240
+        clock.advance(step * 5);
+        timestep.advance();
+        for ticks_per_second in [120, 240] {
+            timestep.set_tick_rate(ticks_per_second);
+            let alpha = timestep.alpha();
+            assert!((0.0..1.0).contains(&alpha), "alpha {alpha} out of range");
+        }
+    }
+
+    #[test]
+    fn lerp_hits_both_endpoints() {
+        assert_eq!(2.0f32.lerp(&6.0, 0.0), 2.0);
+        assert_eq!(2.0f32.lerp(&6.0, 1.0), 6.0);
+        assert_eq!(2.0f32.lerp(&6.0, 0.5), 4.0);
+
+        let mut position = Interpolated::new([0.0, 4.0]);
+        position.set([2.0, -4.0]);
+        assert_eq!(position.get(0.0), [0.0, 4.0]);
+        assert_eq!(position.get(1.0), [2.0, -4.0]);
+
+        position.reset([1.0, 1.0]);
+        assert_eq!(position.get(0.5), [1.0, 1.0]);
+    }
+}